@@ -1,73 +1,1283 @@
 use std::fs;
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::ffi::OsStr;
+use std::os::unix::fs::{DirEntryExt, FileTypeExt, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::env;
-use std::time::SystemTime;
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use unicode_width::UnicodeWidthStr;
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{atomic, Arc, Mutex, OnceLock};
+use std::io::{self, BufRead, BufReader, Write};
+use std::cmp::Ordering;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// Layout used to render the listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+	/// Aligned, space-separated columns
+	Columns,
+	/// Simple space-separated list without alignment
+	Simple,
+	/// Columns drawn inside Unicode box-drawing borders with a header row
+	Table,
+	/// Names reflowed into as many variable-width columns as fit the terminal
+	Grid,
+	/// JSON array of entry objects
+	Json,
+	/// One JSON object per line (NDJSON), which `--stream` writes as entries are read
+	JsonLines,
+	/// Comma-separated values with a header row
+	Csv,
+	/// YAML sequence of entry mappings
+	Yaml,
+	/// HTML tables, one per listed directory, with a CSS class per file type
+	Html,
+	/// One name per line, quoted for pasting into a shell or `eval`
+	Shell,
+}
+
+impl OutputFormat {
+	/// Parse an output format name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"columns" | "column" => Some(OutputFormat::Columns),
+			"simple" | "list" => Some(OutputFormat::Simple),
+			"table" => Some(OutputFormat::Table),
+			"grid" => Some(OutputFormat::Grid),
+			"json" => Some(OutputFormat::Json),
+			"jsonl" | "ndjson" | "json-lines" => Some(OutputFormat::JsonLines),
+			"csv" => Some(OutputFormat::Csv),
+			"yaml" | "yml" => Some(OutputFormat::Yaml),
+			"html" => Some(OutputFormat::Html),
+			"shell" | "shell-quote" => Some(OutputFormat::Shell),
+			_ => None,
+		}
+	}
+	
+	/// Whether this format is meant for other programs rather than a terminal
+	fn is_machine_readable(self) -> bool {
+		matches!(self, OutputFormat::Json | OutputFormat::JsonLines | OutputFormat::Csv | OutputFormat::Yaml | OutputFormat::Html | OutputFormat::Shell)
+	}
+}
+
+/// How wide names may get before they are cut with an ellipsis
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MaxNameWidth {
+	/// Names are never cut
+	Unlimited,
+	/// Names are cut to this many terminal cells
+	Fixed(usize),
+	/// Names are cut just enough for each line to fit the terminal, down to `NAME_MIN_WIDTH`
+	Auto,
+}
+
+impl MaxNameWidth {
+	/// Parse `auto`, a width, or `0`/`off` for no limit
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"auto" => Some(MaxNameWidth::Auto),
+			"off" | "none" | "0" => Some(MaxNameWidth::Unlimited),
+			width => width.parse().ok().map(MaxNameWidth::Fixed),
+		}
+	}
+}
+
+/// What happens when an interactive listing has more than `large_listing_threshold` entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LargeListing {
+	/// List everything, as for any other directory
+	Off,
+	/// Ask on the terminal whether to list all, page, truncate or skip
+	Prompt,
+	/// Show the directory through `$PAGER`
+	Page,
+	/// Show only the first `large_listing_threshold` entries
+	Truncate,
+}
+
+impl LargeListing {
+	/// Parse a large-listing action from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"off" | "none" => Some(LargeListing::Off),
+			"prompt" | "warn" | "ask" => Some(LargeListing::Prompt),
+			"page" | "pager" => Some(LargeListing::Page),
+			"truncate" => Some(LargeListing::Truncate),
+			_ => None,
+		}
+	}
+}
+
+/// What `--since-last` does with entries modified since yal last listed their directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SinceLast {
+	/// Keep no record of runs
+	Off,
+	/// Mark changed entries with `[changed]`
+	Highlight,
+	/// List only the changed entries; directories always pass
+	Filter,
+}
+
+impl SinceLast {
+	/// Parse a since-last mode from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"off" | "none" => Some(SinceLast::Off),
+			"highlight" | "mark" => Some(SinceLast::Highlight),
+			"filter" | "only" => Some(SinceLast::Filter),
+			_ => None,
+		}
+	}
+}
+
+/// How the modified column reads in long format
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateFormat {
+	/// The same time as in other formats, fuzzy or per `time_format`
+	Standard,
+	/// Fuzzy time followed by the exact timestamp, e.g. `3 hours (2024-01-15 11:20)`
+	Detailed,
+}
+
+impl DateFormat {
+	/// Parse a date format name from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"standard" | "default" => Some(DateFormat::Standard),
+			"detailed" => Some(DateFormat::Detailed),
+			_ => None,
+		}
+	}
+}
+
+/// Exact part of detailed dates when no `time_format` is set
+const DETAILED_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Key used to order entries within a listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+	/// Case-insensitive file name
+	Name,
+	/// File size in bytes, smallest first
+	Size,
+	/// Modification time, oldest first
+	Time,
+	/// Access time, least recently read first
+	Accessed,
+	/// Owner name, alphabetically
+	Owner,
+	/// Group name, alphabetically
+	Group,
+	/// Last extension, names without one first (like `ls -X`)
+	Extension,
+	/// Directory order, as returned by the filesystem (like `ls -U`)
+	None,
+}
+
+impl SortKey {
+	/// Parse a sort key name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"name" => Some(SortKey::Name),
+			"size" => Some(SortKey::Size),
+			"time" | "modified" | "mtime" => Some(SortKey::Time),
+			"accessed" | "access" | "atime" => Some(SortKey::Accessed),
+			"owner" | "user" => Some(SortKey::Owner),
+			"group" => Some(SortKey::Group),
+			"extension" | "ext" => Some(SortKey::Extension),
+			"none" | "unsorted" => Some(SortKey::None),
+			_ => None,
+		}
+	}
+}
+
+/// Secondary order for entries of equal size when sorting by size
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeTiebreak {
+	/// File name, using the configured collation
+	Name,
+	/// Modification time, oldest first
+	Mtime,
+	/// File type, directories first, then in `FileKind` order
+	Type,
+}
+
+impl SizeTiebreak {
+	/// Parse a tiebreak name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"name" => Some(SizeTiebreak::Name),
+			"mtime" | "time" | "modified" => Some(SizeTiebreak::Mtime),
+			"type" | "kind" => Some(SizeTiebreak::Type),
+			_ => None,
+		}
+	}
+}
+
+/// Where hidden files go when sorting by name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DotfilePosition {
+	/// Sorted among the other names by the dot
+	Mixed,
+	/// Grouped ahead of the other names
+	First,
+	/// Grouped after the other names
+	Last,
+}
+
+impl DotfilePosition {
+	/// Parse a dotfile position from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"mixed" | "intermixed" => Some(DotfilePosition::Mixed),
+			"first" | "grouped-first" => Some(DotfilePosition::First),
+			"last" | "grouped-last" => Some(DotfilePosition::Last),
+			_ => None,
+		}
+	}
+}
+
+/// How file names compare when sorting by name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Collation {
+	/// Case-insensitive comparison by code point
+	Ascii,
+	/// Like ascii, but runs of digits compare by numeric value (file2 < file10)
+	Natural,
+	/// NFC-normalized, accent- and case-insensitive first, then natural
+	Unicode,
+	/// Case-sensitive comparison of the raw bytes, like `LC_COLLATE=C`
+	Bytes,
+}
+
+impl Collation {
+	/// Parse a collation name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"ascii" => Some(Collation::Ascii),
+			"natural" => Some(Collation::Natural),
+			"unicode" => Some(Collation::Unicode),
+			"bytes" | "byte" | "c" => Some(Collation::Bytes),
+			_ => None,
+		}
+	}
+}
+
+/// Property used to split a listing into labeled sections
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GroupBy {
+	/// One undivided listing
+	None,
+	/// Lowercase file extension
+	Extension,
+	/// Owner name
+	Owner,
+	/// File kind: directory, file, symlink, ...
+	Type,
+	/// Age bucket: today, this week, this month, older
+	Age,
+}
+
+impl GroupBy {
+	/// Parse a grouping name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"none" | "off" => Some(GroupBy::None),
+			"extension" | "ext" => Some(GroupBy::Extension),
+			"owner" | "user" => Some(GroupBy::Owner),
+			"type" | "kind" => Some(GroupBy::Type),
+			"age" => Some(GroupBy::Age),
+			_ => None,
+		}
+	}
+}
+
+/// Order in which recursive listings visit subdirectories
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Traversal {
+	/// Finish each subdirectory, including its own subdirectories, before the next sibling
+	DepthFirst,
+	/// List every directory of one level before descending to the next
+	BreadthFirst,
+}
+
+impl Traversal {
+	/// Parse a traversal name from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"depth-first" | "depth_first" | "dfs" => Some(Traversal::DepthFirst),
+			"breadth-first" | "breadth_first" | "bfs" => Some(Traversal::BreadthFirst),
+			_ => None,
+		}
+	}
+}
+
+/// Column colored on a gradient by its value
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Heatmap {
+	/// Fixed column colors
+	Off,
+	/// Modified column colored by age
+	Time,
+	/// Size column colored by size
+	Size,
+}
+
+impl Heatmap {
+	/// Parse a heatmap mode from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"off" | "none" | "false" => Some(Heatmap::Off),
+			"time" | "age" | "modified" => Some(Heatmap::Time),
+			"size" => Some(Heatmap::Size),
+			_ => None,
+		}
+	}
+}
+
+/// When to write ANSI color escapes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorMode {
+	/// Color only when stdout is a terminal
+	Auto,
+	/// Always color, even when redirected to a file or pipe
+	Always,
+	/// Never color
+	Never,
+}
+
+impl ColorMode {
+	/// Parse a color mode from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"auto" | "tty" => Some(ColorMode::Auto),
+			"always" | "force" | "yes" => Some(ColorMode::Always),
+			"never" | "none" | "no" => Some(ColorMode::Never),
+			_ => None,
+		}
+	}
+	
+	/// Whether colors are enabled for the listing's output
+	fn enabled(self) -> bool {
+		match self {
+			ColorMode::Auto => output_is_terminal(),
+			ColorMode::Always => true,
+			ColorMode::Never => false,
+		}
+	}
+}
+
+/// Terminal background the column colors are tuned for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Theme {
+	/// Bright colors for dark backgrounds
+	Dark,
+	/// Deeper colors that stay readable on light backgrounds
+	Light,
+	/// Detect the background from `COLORFGBG` or an OSC 11 query, falling back to dark
+	Auto,
+}
+
+impl Theme {
+	/// Parse a theme name from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"dark" => Some(Theme::Dark),
+			"light" => Some(Theme::Light),
+			"auto" => Some(Theme::Auto),
+			_ => None,
+		}
+	}
+	
+	/// Palette for this theme, detecting the background for `Auto`
+	fn palette(self) -> &'static Palette {
+		match self {
+			Theme::Dark => &DARK_PALETTE,
+			Theme::Light => &LIGHT_PALETTE,
+			Theme::Auto if detect_light_background() => &LIGHT_PALETTE,
+			Theme::Auto => &DARK_PALETTE,
+		}
+	}
+}
+
+/// Default color escapes of the listing columns
+#[derive(Debug, Clone)]
+struct Palette {
+	directory: &'static str,
+	permissions: &'static str,
+	size: &'static str,
+	owner: &'static str,
+	group: &'static str,
+	modified: &'static str,
+	/// Size bars, filesystem types and sparse sizes
+	accent: &'static str,
+	/// Annotations after names and other secondary text
+	muted: &'static str,
+	/// Heatmap colors for the `AGE_HEAT` steps, freshest first
+	heat_time: [&'static str; 6],
+	/// Heatmap colors for the `SIZE_HEAT` steps, smallest first
+	heat_size: [&'static str; 6],
+}
+
+/// Heatmap colors shared by the built-in palettes
+const HEAT_TIME_COLORS: [&str; 6] = ["\x1b[38;5;46m", "\x1b[38;5;118m", "\x1b[38;5;184m", "\x1b[38;5;172m", "\x1b[38;5;131m", "\x1b[38;5;240m"];
+const HEAT_SIZE_COLORS: [&str; 6] = ["\x1b[38;5;240m", "\x1b[38;5;37m", "\x1b[38;5;78m", "\x1b[38;5;184m", "\x1b[38;5;208m", "\x1b[38;5;196m"];
+
+impl Palette {
+	/// Copy of this palette with a theme's colors laid over it
+	///
+	/// Called once in main, so the new escapes are leaked to live as long as the built-in ones.
+	fn with_theme(&self, theme: &ThemeFile) -> Palette {
+		let escape = |code: &str| -> &'static str {
+			if code.is_empty() { "" } else { Box::leak(format!("\x1b[{}m", code).into_boxed_str()) }
+		};
+		let mut palette = self.clone();
+		for (slot, code) in &theme.colors {
+			let color = match slot.as_str() {
+				"directory" => &mut palette.directory,
+				"permissions" => &mut palette.permissions,
+				"size" => &mut palette.size,
+				"owner" => &mut palette.owner,
+				"group" => &mut palette.group,
+				"modified" => &mut palette.modified,
+				"accent" => &mut palette.accent,
+				"muted" => &mut palette.muted,
+				_ => continue,
+			};
+			*color = escape(code);
+		}
+		for (colors, codes) in [(&mut palette.heat_time, &theme.heat_time), (&mut palette.heat_size, &theme.heat_size)] {
+			if let Some(codes) = codes {
+				for (color, code) in colors.iter_mut().zip(codes) {
+					*color = escape(code);
+				}
+			}
+		}
+		palette
+	}
+}
+
+/// Palette slots a theme can color with `color.<slot>`
+const PALETTE_SLOTS: [&str; 8] = ["directory", "permissions", "size", "owner", "group", "modified", "accent", "muted"];
+
+/// Themes compiled into the binary, in theme file syntax, for `--theme <name>`
+///
+/// The color-blind presets keep neighboring columns apart by hue along the axis
+/// that stays visible (blue-yellow for deuteranopia and protanopia, red-cyan for
+/// tritanopia) and run the heatmaps from bright to dim, so brightness alone
+/// tells the steps apart.
+const BUILTIN_THEMES: [(&str, &str); 5] = [
+	("solarized", "\
+base = dark
+color.directory = 38;5;33;1
+color.permissions = 38;5;136
+color.size = 38;5;64;1
+color.owner = 38;5;37
+color.group = 38;5;61
+color.modified = 38;5;125
+color.accent = 38;5;37
+color.muted = 38;5;240
+heat_time = 38;5;64,38;5;37,38;5;33,38;5;136,38;5;166,38;5;240
+heat_size = 38;5;240,38;5;37,38;5;64,38;5;136,38;5;166,38;5;160
+"),
+	("mono", "\
+color.directory = 1
+color.permissions =
+color.size = 1
+color.owner =
+color.group =
+color.modified =
+color.accent = 2
+color.muted = 2
+heat_time = 1,1,0,0,2,2
+heat_size = 2,2,0,0,1,1
+"),
+	// Red and green look alike: blues against yellows and oranges
+	("deuteranopia", "\
+base = dark
+color.directory = 38;5;33;1
+color.permissions = 38;5;220
+color.size = 38;5;117;1
+color.owner = 38;5;214
+color.group = 38;5;75
+color.modified = 38;5;183
+color.accent = 38;5;117
+color.muted = 38;5;244
+heat_time = 38;5;226,38;5;220,38;5;178,38;5;74,38;5;25,38;5;240
+heat_size = 38;5;240,38;5;25,38;5;74,38;5;178,38;5;220,38;5;226
+"),
+	// Like deuteranopia, but reds also look dark, so none are used at all
+	("protanopia", "\
+base = dark
+color.directory = 38;5;33;1
+color.permissions = 38;5;228
+color.size = 38;5;153;1
+color.owner = 38;5;220
+color.group = 38;5;110
+color.modified = 38;5;147
+color.accent = 38;5;153
+color.muted = 38;5;244
+heat_time = 38;5;231,38;5;228,38;5;220,38;5;110,38;5;67,38;5;239
+heat_size = 38;5;239,38;5;67,38;5;110,38;5;220,38;5;228,38;5;231
+"),
+	// Blue and yellow look alike: reds and pinks against cyans and teals
+	("tritanopia", "\
+base = dark
+color.directory = 38;5;37;1
+color.permissions = 38;5;203
+color.size = 38;5;198;1
+color.owner = 38;5;44
+color.group = 38;5;168
+color.modified = 38;5;224
+color.accent = 38;5;44
+color.muted = 38;5;245
+heat_time = 38;5;231,38;5;217,38;5;204,38;5;161,38;5;89,38;5;240
+heat_size = 38;5;240,38;5;89,38;5;161,38;5;204,38;5;217,38;5;231
+"),
+];
+
+/// Colors, icons and heatmap steps read from a theme file or a built-in named theme
+///
+/// Theme files use the config syntax with their own keys: `base` (dark, light
+/// or auto), `color.<slot>` and `heat_time`/`heat_size` as SGR codes, and
+/// `icon.<extension>` like the config. Icons set in the config win over the theme's.
+#[derive(Debug, Clone, Default)]
+struct ThemeFile {
+	/// Built-in palette the colors are laid over; unset keeps the `theme` setting
+	base: Option<Theme>,
+	/// SGR codes by palette slot; an empty code leaves the slot uncolored
+	colors: HashMap<String, String>,
+	icons: HashMap<String, String>,
+	/// SGR codes for the six heatmap steps
+	heat_time: Option<Vec<String>>,
+	heat_size: Option<Vec<String>>,
+}
+
+impl ThemeFile {
+	/// Parse theme file contents, warning about keys it doesn't know
+	fn parse(contents: &str, source: &str) -> Self {
+		let mut theme = ThemeFile::default();
+		for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+			let Some((key, value)) = line.split_once('=') else { continue };
+			let (key, value) = (key.trim().to_lowercase(), value.trim());
+			match key.as_str() {
+				"base" => match Theme::parse(value) {
+					Some(base) => theme.base = Some(base),
+					None => eprintln!("yal: theme {}: invalid base '{}' (expected dark, light or auto)", source, value),
+				},
+				"heat_time" | "heat_size" => {
+					let codes: Vec<String> = value.split(',').map(|code| code.trim().to_string()).collect();
+					if codes.len() != AGE_HEAT.len() {
+						eprintln!("yal: theme {}: {} needs {} colors, got {}", source, key, AGE_HEAT.len(), codes.len());
+					} else if key == "heat_time" {
+						theme.heat_time = Some(codes);
+					} else {
+						theme.heat_size = Some(codes);
+					}
+				},
+				_ if key.strip_prefix("color.").is_some_and(|slot| PALETTE_SLOTS.contains(&slot)) => {
+					theme.colors.insert(key["color.".len()..].to_string(), value.to_string());
+				},
+				_ if key.starts_with("icon.") && !value.is_empty() => {
+					theme.icons.insert(key["icon.".len()..].to_string(), value.to_string());
+				},
+				_ => eprintln!("yal: theme {}: unknown key '{}'", source, key),
+			}
+		}
+		theme
+	}
+	
+	/// Read a theme file from a path
+	fn load(path: &Path) -> Result<Self, String> {
+		fs::read_to_string(path)
+			.map(|contents| Self::parse(&contents, &format!("'{}'", path.display())))
+			.map_err(|error| format!("cannot read theme '{}': {}", path.display(), error))
+	}
+	
+	/// Find a theme by name in the themes directory, then among the built-in themes
+	fn named(name: &str) -> Option<Self> {
+		let file_name = format!("{}.theme", name);
+		let directories = [
+			env::var_os("XDG_CONFIG_HOME").map(|config| PathBuf::from(config).join("yal").join("themes")),
+			env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("yal").join("themes")),
+		];
+		if let Some(path) = directories.into_iter().flatten().map(|directory| directory.join(&file_name)).find(|path| path.is_file()) {
+			return Self::load(&path).map_err(|error| eprintln!("yal: {}", error)).ok();
+		}
+		BUILTIN_THEMES.iter()
+			.find(|(builtin, _)| builtin.eq_ignore_ascii_case(name))
+			.map(|(builtin, contents)| Self::parse(contents, builtin))
+	}
+}
+
+/// Colors for dark backgrounds, the long-standing defaults
+const DARK_PALETTE: Palette = Palette {
+	directory: "\x1b[34;1m",
+	permissions: "\x1b[33m",
+	size: "\x1b[32;1m",
+	owner: "\x1b[32m",
+	group: "\x1b[36m",
+	modified: "\x1b[35m",
+	accent: "\x1b[36m",
+	muted: "\x1b[90m",
+	heat_time: HEAT_TIME_COLORS,
+	heat_size: HEAT_SIZE_COLORS,
+};
+
+/// Colors for light backgrounds: darker shades instead of pale yellow, cyan and bright blue
+const LIGHT_PALETTE: Palette = Palette {
+	directory: "\x1b[38;5;19;1m",
+	permissions: "\x1b[38;5;130m",
+	size: "\x1b[38;5;22;1m",
+	owner: "\x1b[38;5;28m",
+	group: "\x1b[38;5;24m",
+	modified: "\x1b[38;5;90m",
+	accent: "\x1b[38;5;30m",
+	muted: "\x1b[38;5;243m",
+	heat_time: HEAT_TIME_COLORS,
+	heat_size: HEAT_SIZE_COLORS,
+};
+
+/// Whether the terminal background looks light, from `COLORFGBG` or an OSC 11 query
+fn detect_light_background() -> bool {
+	// rxvt-style "fg;bg" (sometimes "fg;default;bg"): colors 7 and 9-15 are light
+	if let Ok(value) = env::var("COLORFGBG") {
+		if let Some(Ok(background)) = value.rsplit(';').next().map(str::parse::<u8>) {
+			return background == 7 || background >= 9;
+		}
+	}
+	query_background_color().is_some_and(|(r, g, b)| 0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5)
+}
+
+/// Ask the terminal for its background color with OSC 11, as red, green and blue in 0.0-1.0
+///
+/// Only tried when the listing goes to a terminal; terminals that don't answer
+/// within 100ms are treated as unknown.
+fn query_background_color() -> Option<(f64, f64, f64)> {
+	use std::io::Read;
+	use std::os::unix::io::AsRawFd;
+	
+	if !output_is_terminal() {
+		return None;
+	}
+	let mut tty = fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+	let fd = tty.as_raw_fd();
+	let _raw_mode = RawModeGuard::enable(fd).ok()?;
+	tty.write_all(b"\x1b]11;?\x07").ok()?;
+	tty.flush().ok()?;
+	
+	// The reply is "ESC ] 11 ; rgb:RRRR/GGGG/BBBB" ended by BEL or ESC backslash
+	let mut reply = Vec::new();
+	let mut byte = [0u8; 1];
+	while reply.len() < 64 {
+		let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+		if unsafe { libc::poll(&mut poll_fd, 1, 100) } <= 0 || tty.read_exact(&mut byte).is_err() {
+			return None;
+		}
+		if byte[0] == 0x07 || (byte[0] == b'\\' && reply.last() == Some(&0x1b)) {
+			break;
+		}
+		reply.push(byte[0]);
+	}
+	
+	let reply = String::from_utf8_lossy(&reply);
+	let rgb = reply.split("rgb:").nth(1)?.trim_end_matches('\x1b');
+	let mut channels = rgb.split('/').map(|hex| {
+		let value = u32::from_str_radix(hex, 16).ok()?;
+		let max = 16u32.checked_pow(hex.len() as u32)? - 1;
+		Some(value as f64 / max as f64)
+	});
+	Some((channels.next()??, channels.next()??, channels.next()??))
+}
+
+/// Colors chosen in main from the theme
+static PALETTE: OnceLock<&'static Palette> = OnceLock::new();
+
+/// Active palette, dark until main has picked one
+fn palette() -> &'static Palette {
+	PALETTE.get().copied().unwrap_or(&DARK_PALETTE)
+}
+
+/// How many columns East Asian ambiguous-width characters (e.g. ①, ○, Greek) take
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AmbiguousWidth {
+	/// One column, as in most Western terminal setups
+	Single,
+	/// Two columns, as in terminals set up for CJK text
+	Double,
+}
+
+impl AmbiguousWidth {
+	/// Parse an ambiguous width from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"single" | "narrow" | "1" => Some(AmbiguousWidth::Single),
+			"double" | "wide" | "2" => Some(AmbiguousWidth::Double),
+			_ => None,
+		}
+	}
+}
+
+/// How human-readable sizes are rounded to `size_precision` decimals
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeRounding {
+	/// Round down, so a size is never overstated (1.99K shows as 1.9K)
+	Floor,
+	/// Round to nearest, halves away from zero
+	Round,
+	/// Round up, like `ls -sh` and `du -h` (1025 bytes shows as 1.1K)
+	Ceil,
+}
+
+impl SizeRounding {
+	/// Parse a rounding mode from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"floor" | "down" => Some(SizeRounding::Floor),
+			"round" | "nearest" => Some(SizeRounding::Round),
+			"ceil" | "up" => Some(SizeRounding::Ceil),
+			_ => None,
+		}
+	}
+	
+	/// Round `value` to `precision` decimal places
+	fn apply(self, value: f64, precision: usize) -> f64 {
+		let scale = 10f64.powi(precision as i32);
+		let scaled = value * scale;
+		// Sizes like 1.1K aren't exact in binary, so nudge them before cutting
+		let rounded = match self {
+			SizeRounding::Floor => (scaled + 1e-9).floor(),
+			SizeRounding::Round => scaled.round(),
+			SizeRounding::Ceil => (scaled - 1e-9).ceil(),
+		};
+		rounded / scale
+	}
+}
+
+/// Which powers human-readable sizes are counted in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeUnits {
+	/// Powers of 1024, as `ls -h` (1K = 1024 bytes)
+	Iec,
+	/// Powers of 1000, as `ls --si` (1K = 1000 bytes)
+	Si,
+}
+
+impl SizeUnits {
+	/// Parse size units from config or the command line
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"iec" | "binary" | "1024" => Some(SizeUnits::Iec),
+			"si" | "decimal" | "1000" => Some(SizeUnits::Si),
+			_ => None,
+		}
+	}
+	
+	/// Bytes in one step up the unit scale
+	fn base(self) -> u64 {
+		match self {
+			SizeUnits::Iec => 1024,
+			SizeUnits::Si => 1000,
+		}
+	}
+}
+
+/// How the permissions column renders mode bits
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PermissionStyle {
+	/// Octal mode bits (755)
+	Octal,
+	/// `ls -l` style type and mode string (drwxr-xr-x)
+	Symbolic,
+	/// Octal and symbolic side by side (0755 rwxr-xr-x)
+	Both,
+}
+
+impl PermissionStyle {
+	/// Parse a permission style name from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"octal" => Some(PermissionStyle::Octal),
+			"symbolic" => Some(PermissionStyle::Symbolic),
+			"both" => Some(PermissionStyle::Both),
+			_ => None,
+		}
+	}
+}
+
+/// System-wide configuration file, loaded before any user configuration
+const SYSTEM_CONFIG_PATH: &str = "/etc/yal.conf";
+
+/// Environment variable naming the user configuration file, instead of searching for one
+const CONFIG_PATH_VARIABLE: &str = "YAL_CONFIG";
+
+/// Every column name in its canonical display order
+const ALL_COLUMNS: [&str; 15] = ["icon", "permissions", "access", "links", "size", "size_bar", "percent", "owner", "group", "owner_group", "fstype", "info", "modified", "age", "name"];
+
+/// Fields the compact `info` column can combine, in `info_fields`
+const INFO_FIELDS: [&str; 8] = ["size", "time", "modified", "age", "permissions", "owner", "group", "links"];
+
+/// Heuristics applied by `smart_defaults`, each of which can be enabled on its own
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct SmartDefaults {
+	/// Reflow into a grid when a directory holds many short names
+	grid: bool,
+	/// Fit columns to the terminal when it is narrower than 80 cells
+	fit: bool,
+	/// Sort newest first in download-style folders
+	time_sort: bool,
+}
+
+impl SmartDefaults {
+	/// Parse `true`/`false` or a comma-separated list of heuristic names
+	fn parse(value: &str) -> Option<Self> {
+		match value.trim().to_lowercase().as_str() {
+			"true" | "yes" | "on" | "all" => return Some(SmartDefaults { grid: true, fit: true, time_sort: true }),
+			"false" | "no" | "off" | "none" => return Some(SmartDefaults::default()),
+			_ => {},
+		}
+		let mut smart = SmartDefaults::default();
+		for name in value.split(',').map(|name| name.trim().to_lowercase()) {
+			match name.as_str() {
+				"grid" => smart.grid = true,
+				"fit" => smart.fit = true,
+				"time_sort" => smart.time_sort = true,
+				_ => return None,
+			}
+		}
+		Some(smart)
+	}
+}
 
 /// Configuration settings for the file lister
 #[derive(Debug, Clone)]
 struct Config {
 	show_icons: bool,
+	/// Draw the icon right before the name instead of in its own column
+	icon_attached: bool,
 	show_permissions: bool,
 	show_owner: bool,
 	show_group: bool,
 	show_modified: bool,
 	use_fuzzy_time: bool,
-	column_format: bool,
+	/// Render fuzzy times as fixed-width tokens like ` 3h` and `52w`
+	fuzzy_fixed_width: bool,
+	/// Show tenths of hours, days and weeks in fuzzy times, like `1.5 hours`
+	fuzzy_fractional: bool,
+	/// strftime-style format for exact timestamps in the modified column
+	time_format: Option<String>,
+	date_format: DateFormat,
+	unknown_time_text: String,
+	future_time_text: String,
+	/// Language of fuzzy times: a bundled locale code, or `auto` to follow `LANG`
+	time_language: String,
+	/// Fuzzy-time words from `lang_*` keys, applied over the chosen locale in order
+	lang_overrides: Vec<(String, String)>,
+	output_format: OutputFormat,
 	column_order: Vec<String>,
+	/// Fields packed into the `info` column, from `INFO_FIELDS`
+	info_fields: Vec<String>,
+	/// Text between the fields of the `info` column
+	info_separator: String,
 	sort_dirs_first: bool,
+	/// Keep directories on top under `reverse`; otherwise they move to the bottom with it
+	reverse_keeps_dirs_first: bool,
 	show_hidden: bool,
+	/// List synthetic `.` and `..` entries at the top, like `ls -a`
+	show_dot_entries: bool,
 	long_format: bool,
+	header_blank_line: bool,
+	hashed_name_colors: bool,
+	show_age: bool,
+	show_links: bool,
+	show_sparse: bool,
+	/// Leave FIFOs, sockets and devices out of listings
+	skip_special: bool,
+	/// Follow symlinks to their final target and show the number of hops
+	show_symlink_depth: bool,
+	/// Report sizes as whole multiples of this many bytes; `None` for human-readable
+	block_size: Option<u64>,
+	show_total: bool,
+	/// Print a count of each file kind and the total size after each listing
+	show_summary: bool,
+	/// Print one grand total of entries and sizes across every listed directory at the end
+	combined_total: bool,
+	/// Note in the header how many entries were hidden or filtered out
+	show_filtered_count: bool,
+	show_fstype: bool,
+	smart_defaults: SmartDefaults,
+	/// Sort and word listings the same on every host, overriding collation and related options
+	stable: bool,
+	/// List names and types from the directory listing alone, without a stat per entry
+	no_metadata: bool,
+	/// Options set explicitly by a config file, environment variable or flag
+	explicit: HashSet<String>,
+	/// Which source last set each option, by config key
+	origins: HashMap<String, ConfigOrigin>,
+	/// Config and environment options with an unknown key or an invalid value
+	ignored_options: Vec<IgnoredOption>,
+	/// Print where each setting came from and exit, instead of listing
+	explain_config: bool,
+	tree: bool,
+	max_depth: Option<usize>,
+	tree_max_prefix_width: usize,
+	/// Symlinked directories the tree may follow along one branch; 0 never follows them
+	tree_follow_symlinks: usize,
+	/// Draw only directories in the tree, as a map of the layout
+	tree_dirs_only: bool,
+	/// Report each immediate subdirectory's recursive size, largest first, instead of listing
+	dirs_sizes: bool,
+	colorful_permissions: bool,
+	/// SGR codes for read, write and execute bits in colorful permissions
+	permission_colors: [String; 3],
+	/// Tint each whole row by file type in the list and column formats
+	color_whole_row: bool,
+	/// SGR codes for directory, file, symlink and executable rows; empty leaves a row untinted
+	row_colors: [String; 4],
+	flat: bool,
+	/// Print entries as they are read instead of collecting them first
+	stream: bool,
+	/// File the listing is written to instead of stdout
+	output_file: Option<PathBuf>,
+	/// Entry count past which a directory is streamed instead of collected, or `None` for no cap
+	stream_threshold: Option<usize>,
+	show_size_bars: bool,
+	size_bar_width: usize,
+	/// Show each entry's share of the listing's total size
+	show_percent: bool,
+	fit_columns: bool,
+	/// Hide metadata columns whose value is the same for every entry, noting it once below
+	auto_hide_uniform_columns: bool,
+	column_priorities: HashMap<String, u32>,
+	dereference: bool,
+	/// Show listed directories by their real path with symlinks resolved, like `pwd -P`
+	physical: bool,
+	show_access: bool,
+	newer_than: Option<SystemTime>,
+	digit_separator: Option<char>,
+	heatmap: Heatmap,
+	color: ColorMode,
+	theme: Theme,
+	/// Colors, icons and heatmaps from `theme_file` or a named `theme`
+	theme_file: Option<ThemeFile>,
+	/// Columns counted for ambiguous-width characters when aligning
+	ambiguous_width: AmbiguousWidth,
+	/// Make names clickable with OSC 8 hyperlinks (only when colors are on)
+	hyperlinks: bool,
+	/// Link target for each name, with `{path}` replaced by the URL-encoded absolute path
+	hyperlink_scheme: String,
+	show_legend: bool,
+	recursive_size: bool,
+	quiet: bool,
+	/// Report entries skipped because their metadata couldn't be read
+	verbose: bool,
+	recurse_hidden: Option<bool>,
+	show_owner_group: bool,
+	dedup_inodes: bool,
+	age_today_secs: u64,
+	age_week_secs: u64,
+	age_month_secs: u64,
+	max_entries: Option<usize>,
+	/// Action for directories over `large_listing_threshold` entries when stdout is a terminal
+	large_listing: LargeListing,
+	large_listing_threshold: usize,
+	show_size: bool,
+	sort_key: SortKey,
+	size_tiebreak: SizeTiebreak,
+	/// Under extension sort, order by stem and first extension so sidecar files stay next to theirs
+	group_related: bool,
+	dotfile_position: DotfilePosition,
+	group_by: GroupBy,
+	/// Sort key for particular `group_by` sections, by `group_sort_label`; others use `sort_key`
+	group_sort: HashMap<String, SortKey>,
+	collation: Collation,
+	/// Characters stripped from the start of names before they are compared
+	sort_ignore_leading: String,
+	reverse_sort: bool,
+	permission_style: PermissionStyle,
+	show_acl: bool,
+	/// Show the file capabilities (`security.capability`) of regular files, like `getcap`
+	show_capabilities: bool,
+	/// Dim empty files and directories and mark them `(empty)`, at one extra read per directory
+	highlight_empty: bool,
+	/// Minimum display width of names followed by annotations, so the annotations line up; 0 for none
+	name_column_width: usize,
+	max_name_width: MaxNameWidth,
+	/// Show control characters, invisible characters and edge spaces in names visibly
+	show_control_chars: bool,
+	/// Mark entries modified after their directory last changed
+	highlight_newer_than_dir: bool,
+	/// Mark entries newer than this file, which `make` would rebuild it for
+	rebuild_target: Option<PathBuf>,
+	/// Record when each directory was listed and mark or filter what changed since
+	since_last: SinceLast,
+	/// Mark overlayfs whiteouts and opaque directories in container image layers
+	show_overlay_markers: bool,
+	/// Cells left for the name and its annotations, worked out per section under `MaxNameWidth::Auto`
+	name_budget: Option<usize>,
+	pin_names: Vec<String>,
+	/// Glob patterns a file must match to be listed; directories always pass
+	filter_patterns: Vec<String>,
+	/// List the files that don't match `filter_patterns` instead, like `grep -v`
+	invert_filter: bool,
+	/// Glob patterns whose matches are never listed or descended into, like `ls --ignore`
+	ignore_patterns: Vec<String>,
+	select_mode: bool,
+	/// Directories came from `--batch-file`, so an empty batch lists nothing rather than `.`
+	batch_file: bool,
+	paths_relative_to: Option<PathBuf>,
+	/// User database for owner names, e.g. a container's /etc/passwd
+	passwd_file: PathBuf,
+	/// Group database for group names, e.g. a container's /etc/group
+	group_file: PathBuf,
+	/// Show paths under `$HOME` as `~/...` in headers and path-style names
+	abbreviate_home: bool,
+	recursive: bool,
+	traversal: Traversal,
+	audit: bool,
+	/// Print a per-phase timing breakdown to stderr after the listing
+	stats: bool,
+	/// Reuse metadata cached per directory while the directory's mtime is unchanged
+	cache: bool,
+	/// Most worker threads used to stat a directory's entries at once
+	max_threads: usize,
+	size_precision: usize,
+	size_rounding: SizeRounding,
+	size_units: SizeUnits,
+	/// Unit labels for human-readable sizes, from bytes up to exbibytes
+	size_suffixes: Vec<String>,
+	diff_mode: bool,
+	truncate_owner: Option<usize>,
+	truncate_group: Option<usize>,
+	/// Leave the owner cell blank for entries owned by the current user
+	hide_own_owner: bool,
+	/// Leave the group cell blank for entries in the current user's primary group
+	hide_own_group: bool,
+	complete_mode: bool,
+	name_prefix: Option<String>,
+	custom_icons: HashMap<String, String>,
 }
 
 impl Default for Config {
 	fn default() -> Self {
 		Config {
 			show_icons: true,
+			icon_attached: false,
 			show_permissions: true,
 			show_owner: true,
 			show_group: true,
 			show_modified: true,
 			use_fuzzy_time: true,
-			column_format: true,
+			fuzzy_fixed_width: false,
+			fuzzy_fractional: false,
+			time_format: None,
+			date_format: DateFormat::Standard,
+			unknown_time_text: "unknown".to_string(),
+			future_time_text: "in {}".to_string(),
+			time_language: "en".to_string(),
+			lang_overrides: Vec::new(),
+			output_format: OutputFormat::Columns,
+			info_fields: vec!["size".to_string(), "time".to_string()],
+			info_separator: "·".to_string(),
 			column_order: vec![
 				"icon".to_string(),
 				"permissions".to_string(), 
+				"access".to_string(),
+				"links".to_string(),
+				"size".to_string(),
+				"size_bar".to_string(),
+				"percent".to_string(),
 				"owner".to_string(),
 				"group".to_string(),
+				"owner_group".to_string(),
+				"fstype".to_string(),
 				"modified".to_string(),
+				"age".to_string(),
 				"name".to_string(),
 			],
 			sort_dirs_first: true,
+			reverse_keeps_dirs_first: true,
 			show_hidden: false,
+			show_dot_entries: false,
 			long_format: false,
+			header_blank_line: true,
+			hashed_name_colors: false,
+			show_age: false,
+			show_links: false,
+			show_sparse: false,
+			skip_special: false,
+			show_symlink_depth: false,
+			block_size: None,
+			show_total: false,
+			show_summary: false,
+			combined_total: false,
+			show_filtered_count: false,
+			show_fstype: false,
+			smart_defaults: SmartDefaults::default(),
+			stable: false,
+			no_metadata: false,
+			explicit: HashSet::new(),
+			origins: HashMap::new(),
+			ignored_options: Vec::new(),
+			explain_config: false,
+			tree: false,
+			max_depth: None,
+			tree_max_prefix_width: 40,
+			tree_follow_symlinks: 0,
+			tree_dirs_only: false,
+			dirs_sizes: false,
+			colorful_permissions: false,
+			permission_colors: ["33".to_string(), "31".to_string(), "32".to_string()],
+			color_whole_row: false,
+			row_colors: ["48;5;17".to_string(), "48;5;235".to_string(), "48;5;23".to_string(), "48;5;22".to_string()],
+			flat: false,
+			stream: false,
+			output_file: None,
+			stream_threshold: Some(DEFAULT_STREAM_THRESHOLD),
+			show_size_bars: false,
+			size_bar_width: 10,
+			show_percent: false,
+			fit_columns: false,
+			auto_hide_uniform_columns: false,
+			column_priorities: HashMap::new(),
+			dereference: false,
+			physical: false,
+			show_access: false,
+			newer_than: None,
+			digit_separator: None,
+			heatmap: Heatmap::Off,
+			color: ColorMode::Auto,
+			theme: Theme::Dark,
+			theme_file: None,
+			ambiguous_width: AmbiguousWidth::Single,
+			hyperlinks: false,
+			hyperlink_scheme: DEFAULT_HYPERLINK_SCHEME.to_string(),
+			show_legend: false,
+			recursive_size: false,
+			quiet: false,
+			verbose: false,
+			recurse_hidden: None,
+			show_owner_group: false,
+			dedup_inodes: false,
+			age_today_secs: SECS_PER_DAY,
+			age_week_secs: SECS_PER_WEEK,
+			age_month_secs: 30 * SECS_PER_DAY,
+			max_entries: None,
+			large_listing: LargeListing::Off,
+			large_listing_threshold: DEFAULT_LARGE_LISTING_THRESHOLD,
+			show_size: false,
+			sort_key: SortKey::Name,
+			group_related: false,
+			size_tiebreak: SizeTiebreak::Name,
+			dotfile_position: DotfilePosition::Mixed,
+			group_by: GroupBy::None,
+			group_sort: HashMap::new(),
+			collation: Collation::Ascii,
+			sort_ignore_leading: String::new(),
+			reverse_sort: false,
+			permission_style: PermissionStyle::Octal,
+			show_acl: false,
+			show_capabilities: false,
+			highlight_empty: false,
+			name_column_width: 0,
+			max_name_width: MaxNameWidth::Unlimited,
+			show_control_chars: false,
+			highlight_newer_than_dir: false,
+			rebuild_target: None,
+			since_last: SinceLast::Off,
+			show_overlay_markers: false,
+			name_budget: None,
+			pin_names: Vec::new(),
+			filter_patterns: Vec::new(),
+			invert_filter: false,
+			ignore_patterns: Vec::new(),
+			select_mode: false,
+			batch_file: false,
+			paths_relative_to: None,
+			passwd_file: PathBuf::from(PASSWD_PATH),
+			group_file: PathBuf::from(GROUP_PATH),
+			abbreviate_home: false,
+			recursive: false,
+			traversal: Traversal::DepthFirst,
+			audit: false,
+			stats: false,
+			cache: false,
+			max_threads: std::thread::available_parallelism().map_or(1, usize::from),
+			size_precision: 1,
+			size_rounding: SizeRounding::Round,
+			size_units: SizeUnits::Iec,
+			size_suffixes: SIZE_SUFFIXES.iter().map(|suffix| suffix.to_string()).collect(),
+			diff_mode: false,
+			truncate_owner: None,
+			truncate_group: None,
+			hide_own_owner: false,
+			hide_own_group: false,
+			complete_mode: false,
+			name_prefix: None,
+			custom_icons: HashMap::new(),
 		}
 	}
 }
 
 impl Config {
-	/// Load configuration from standard platform locations
+	/// Load configuration by layering every source over the defaults
+	///
+	/// Precedence, lowest first: system file, user file, current directory
+	/// file, `YAL_*` environment variables. Later layers only override the
+	/// keys they set. Command line arguments are applied on top by `main`.
 	fn load() -> Self {
 		let mut config = Config::default();
 		
-		// Try to find config file in standard locations
-		if let Some(config_path) = Self::find_config_file() {
+		for config_path in Self::config_files() {
 			if let Ok(contents) = fs::read_to_string(&config_path) {
-				config.parse_config(&contents);
+				config.parse_config(&contents, &config_path.display().to_string());
 			}
 		}
 		
+		config.apply_env();
 		config
 	}
 	
-	/// Find configuration file in standard locations
+	/// Config files that exist, in the order they are layered (lowest precedence first)
+	fn config_files() -> Vec<PathBuf> {
+		let mut files = Vec::new();
+		
+		// System-wide defaults set by administrators
+		let system = PathBuf::from(SYSTEM_CONFIG_PATH);
+		if system.exists() {
+			files.push(system);
+		}
+		
+		if let Some(user) = Self::find_config_file() {
+			files.push(user);
+		}
+		
+		// Project-local overrides in the current directory
+		let local = PathBuf::from("yal.conf");
+		if local.exists() {
+			files.push(local);
+		}
+		
+		files
+	}
+	
+	/// Find the user configuration file in standard locations
 	fn find_config_file() -> Option<PathBuf> {
 		let config_name = "yal.conf";
 		let app_name = "yal";
 		
+		// An explicit $YAL_CONFIG wins over the search, e.g. in CI or containers without a HOME
+		if let Some(path) = env::var_os(CONFIG_PATH_VARIABLE).filter(|value| !value.is_empty()).map(PathBuf::from) {
+			if path.exists() {
+				return Some(path);
+			}
+			static WARNED: std::sync::Once = std::sync::Once::new();
+			WARNED.call_once(|| eprintln!("yal: {} is set to '{}', which doesn't exist; searching the usual places", CONFIG_PATH_VARIABLE, path.display()));
+		}
+		
 		// Check XDG_CONFIG_HOME/yal/yal.conf
 		if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
 			let path = PathBuf::from(xdg_config).join(app_name).join(config_name);
@@ -108,17 +1318,20 @@ impl Config {
 			}
 		}
 		
-		// Check current directory
-		let path = PathBuf::from(config_name);
-		if path.exists() {
-			return Some(path);
-		}
-		
 		None
 	}
 	
-	/// Parse configuration from file contents
-	fn parse_config(&mut self, contents: &str) {
+	/// Apply `YAL_<KEY>` environment variables, e.g. `YAL_SHOW_ICONS=false`
+	fn apply_env(&mut self) {
+		for (name, value) in env::vars() {
+			if let Some(key) = name.strip_prefix("YAL_").filter(|_| name != CONFIG_PATH_VARIABLE) {
+				self.set_option_from(&key.to_lowercase(), value.trim(), &format!("env {}", name));
+			}
+		}
+	}
+	
+	/// Parse configuration from file contents, `source` naming the file for `--explain-config`
+	fn parse_config(&mut self, contents: &str, source: &str) {
 		for line in contents.lines() {
 			let line = line.trim();
 			
@@ -129,427 +1342,7390 @@ impl Config {
 			
 			// Parse key=value pairs
 			if let Some((key, value)) = line.split_once('=') {
-				let key = key.trim().to_lowercase();
-				let value = value.trim();
-				
-				match key.as_str() {
-					"show_icons" => self.show_icons = Self::parse_bool(value),
-					"show_permissions" => self.show_permissions = Self::parse_bool(value),
-					"show_owner" => self.show_owner = Self::parse_bool(value),
-					"show_group" => self.show_group = Self::parse_bool(value),
-					"show_modified" => self.show_modified = Self::parse_bool(value),
-					"use_fuzzy_time" => self.use_fuzzy_time = Self::parse_bool(value),
-					"column_format" => self.column_format = Self::parse_bool(value),
-					"sort_dirs_first" => self.sort_dirs_first = Self::parse_bool(value),
-					"show_hidden" => self.show_hidden = Self::parse_bool(value),
-					"long_format" => self.long_format = Self::parse_bool(value),
-					"column_order" => {
-						self.column_order = value.split(',')
-							.map(|s| s.trim().to_string())
-							.filter(|s| !s.is_empty())
-							.collect();
-					},
-					_ => {
-						// Unknown config option, ignore silently
-					}
-				}
+				self.set_option_from(&key.trim().to_lowercase(), value.trim(), source);
 			}
 		}
 	}
 	
-	/// Parse boolean values from config
-	fn parse_bool(value: &str) -> bool {
-		match value.to_lowercase().as_str() {
-			"true" | "yes" | "1" | "on" | "enabled" => true,
-			"false" | "no" | "0" | "off" | "disabled" => false,
-			_ => false, // Default to false for invalid values
+	/// Set an option and remember which source set it, and which sources it overrode
+	fn set_option_from(&mut self, key: &str, value: &str, source: &str) {
+		if self.set_option(key, value) {
+			self.record_origin(key, value, source);
+		} else {
+			self.ignored_options.push(IgnoredOption {
+				setting: format!("{} = {}", key, value),
+				source: source.to_string(),
+				known: self.explicit.contains(key),
+			});
 		}
 	}
-}
-
-/// Cache for user and group name lookups
-struct NameCache {
-	users: HashMap<u32, String>,
-	groups: HashMap<u32, String>,
-}
-
-impl NameCache {
-	/// Create a new NameCache and populate it by reading system files
-	fn new() -> Self {
-		let mut cache = NameCache {
-			users: HashMap::new(),
-			groups: HashMap::new(),
-		};
-		
-		// Load user names from /etc/passwd
-		if let Ok(file) = fs::File::open("/etc/passwd") {
-			let reader = BufReader::new(file);
-			for line in reader.lines() {
-				if let Ok(line) = line {
-					let parts: Vec<&str> = line.split(':').collect();
-					if parts.len() >= 3 {
-						if let Ok(uid) = parts[2].parse::<u32>() {
-							cache.users.insert(uid, parts[0].to_string());
-						}
-					}
+	
+	/// Record `source` as having set `key` to `value`, over whichever source set it before
+	fn record_origin(&mut self, key: &str, value: &str, source: &str) {
+		let overridden = match self.origins.remove(key) {
+			// A source setting a key twice only overrides itself
+			Some(previous) if previous.source == source => previous.overridden,
+			Some(previous) => [previous.overridden, vec![previous.source]].concat(),
+			None => Vec::new(),
+		};
+		self.origins.insert(key.to_string(), ConfigOrigin { value: value.to_string(), source: source.to_string(), overridden });
+	}
+	
+	/// Set a single option from its key and raw value; false if the key is unknown or the value invalid
+	fn set_option(&mut self, key: &str, value: &str) -> bool {
+		self.explicit.insert(key.to_string());
+		match key {
+			"show_icons" => self.show_icons = Self::parse_bool(value),
+			"icon_attached" => self.icon_attached = Self::parse_bool(value),
+			"show_permissions" => self.show_permissions = Self::parse_bool(value),
+			"show_owner" => self.show_owner = Self::parse_bool(value),
+			"show_group" => self.show_group = Self::parse_bool(value),
+			"show_modified" => self.show_modified = Self::parse_bool(value),
+			"use_fuzzy_time" => self.use_fuzzy_time = Self::parse_bool(value),
+			"fuzzy_fixed_width" => self.fuzzy_fixed_width = Self::parse_bool(value),
+			"fuzzy_fractional" => self.fuzzy_fractional = Self::parse_bool(value),
+			"time_format" => self.set_time_format(value),
+			"date_format" => match DateFormat::parse(value) {
+				Some(format) => self.date_format = format,
+				None => return false,
+			},
+			"unknown_time_text" => self.unknown_time_text = value.to_string(),
+			"future_time_text" => self.future_time_text = value.to_string(),
+			"time_language" => self.time_language = value.to_string(),
+			"column_format" => {
+				self.output_format = if Self::parse_bool(value) { OutputFormat::Columns } else { OutputFormat::Simple };
+			},
+			"output_format" => match OutputFormat::parse(value) {
+				Some(format) => self.output_format = format,
+				None => return false,
+			},
+			"sort_dirs_first" => self.sort_dirs_first = Self::parse_bool(value),
+			"reverse_keeps_dirs_first" => self.reverse_keeps_dirs_first = Self::parse_bool(value),
+			"show_hidden" => self.show_hidden = Self::parse_bool(value),
+			"show_dot_entries" => self.show_dot_entries = Self::parse_bool(value),
+			"long_format" => self.long_format = Self::parse_bool(value),
+			"header_blank_line" => self.header_blank_line = Self::parse_bool(value),
+			"hashed_name_colors" => self.hashed_name_colors = Self::parse_bool(value),
+			"show_age" => self.show_age = Self::parse_bool(value),
+			"show_links" => self.show_links = Self::parse_bool(value),
+			"show_sparse" => self.show_sparse = Self::parse_bool(value),
+			"skip_special" => self.skip_special = Self::parse_bool(value),
+			"show_symlink_depth" => self.show_symlink_depth = Self::parse_bool(value),
+			"block_size" => match parse_block_size(value) {
+				Some(block_size) => self.block_size = block_size,
+				None => return false,
+			},
+			"show_total" => self.show_total = Self::parse_bool(value),
+			"show_summary" => self.show_summary = Self::parse_bool(value),
+			"combined_total" => self.combined_total = Self::parse_bool(value),
+			"show_filtered_count" => self.show_filtered_count = Self::parse_bool(value),
+			"show_fstype" => self.show_fstype = Self::parse_bool(value),
+			"stable" => self.stable = Self::parse_bool(value),
+			"no_metadata" => self.no_metadata = Self::parse_bool(value),
+			"smart_defaults" => match SmartDefaults::parse(value) {
+				Some(smart) => self.smart_defaults = smart,
+				None => return false,
+			},
+			"tree_max_prefix_width" => match value.parse() {
+				Ok(width) => self.tree_max_prefix_width = width,
+				Err(_) => return false,
+			},
+			"tree_dirs_only" => self.tree_dirs_only = Self::parse_bool(value),
+			"tree_follow_symlinks" => match value.parse() {
+				Ok(limit) => self.tree_follow_symlinks = limit,
+				Err(_) => return false,
+			},
+			"colorful_permissions" => self.colorful_permissions = Self::parse_bool(value),
+			"permission_color_read" => self.permission_colors[0] = value.to_string(),
+			"permission_color_write" => self.permission_colors[1] = value.to_string(),
+			"permission_color_execute" => self.permission_colors[2] = value.to_string(),
+			"color_whole_row" => self.color_whole_row = Self::parse_bool(value),
+			"row_color_directory" => self.row_colors[0] = value.to_string(),
+			"row_color_file" => self.row_colors[1] = value.to_string(),
+			"row_color_symlink" => self.row_colors[2] = value.to_string(),
+			"row_color_executable" => self.row_colors[3] = value.to_string(),
+			"show_size_bars" => self.show_size_bars = Self::parse_bool(value),
+			"show_percent" => self.show_percent = Self::parse_bool(value),
+			"size_bar_width" => match value.parse() {
+				Ok(width @ 1..) => self.size_bar_width = width,
+				_ => return false,
+			},
+			"fit_columns" => self.fit_columns = Self::parse_bool(value),
+			"auto_hide_uniform_columns" => self.auto_hide_uniform_columns = Self::parse_bool(value),
+			"dereference" => self.dereference = Self::parse_bool(value),
+			"physical" => self.physical = Self::parse_bool(value),
+			"show_access" => self.show_access = Self::parse_bool(value),
+			"number_format" => match parse_digit_separator(value) {
+				Some(separator) => self.digit_separator = separator,
+				None => {
+					eprintln!("yal: invalid number format '{}' (expected none, comma, period, underscore or space)", value);
+					return false;
+				},
+			},
+			"recurse_hidden" => self.recurse_hidden = Some(Self::parse_bool(value)),
+			"show_owner_group" => self.show_owner_group = Self::parse_bool(value),
+			"dedup_inodes" => self.dedup_inodes = Self::parse_bool(value),
+			"age_today" => match parse_duration(value) {
+				Some(secs) => self.age_today_secs = secs,
+				None => return false,
+			},
+			"age_week" => match parse_duration(value) {
+				Some(secs) => self.age_week_secs = secs,
+				None => return false,
+			},
+			"age_month" => match parse_duration(value) {
+				Some(secs) => self.age_month_secs = secs,
+				None => return false,
+			},
+			"max_entries" => match value.parse::<usize>() {
+				Ok(limit) => self.max_entries = Some(limit).filter(|&limit| limit > 0),
+				Err(_) => return false,
+			},
+			"large_listing" => match LargeListing::parse(value) {
+				Some(action) => self.large_listing = action,
+				None => return false,
+			},
+			"large_listing_threshold" => match value.parse() {
+				Ok(threshold @ 1..) => self.large_listing_threshold = threshold,
+				_ => return false,
+			},
+			"stream_threshold" => match value.parse::<usize>() {
+				Ok(limit) => self.stream_threshold = Some(limit).filter(|&limit| limit > 0),
+				Err(_) => return false,
+			},
+			"show_size" => self.show_size = Self::parse_bool(value),
+			"recursive_size" => self.recursive_size = Self::parse_bool(value),
+			"quiet" => self.quiet = Self::parse_bool(value),
+			"verbose" => self.verbose = Self::parse_bool(value),
+			"size_precision" => match value.parse() {
+				Ok(precision @ 0..=2) => self.size_precision = precision,
+				_ => return false,
+			},
+			"size_rounding" => match SizeRounding::parse(value) {
+				Some(rounding) => self.size_rounding = rounding,
+				None => return false,
+			},
+			"size_units" => match SizeUnits::parse(value) {
+				Some(units) => self.size_units = units,
+				None => return false,
+			},
+			"size_suffixes" => {
+				let suffixes: Vec<String> = value.split(',').map(|s| s.trim().to_string()).collect();
+				if suffixes.len() == SIZE_SUFFIXES.len() {
+					self.size_suffixes = suffixes;
+				} else {
+					eprintln!(
+						"yal: size_suffixes needs {} labels (bytes through exbibytes, e.g. {}), got {}",
+						SIZE_SUFFIXES.len(), SIZE_SUFFIXES.join(","), suffixes.len()
+					);
+					return false;
 				}
-			}
-		}
-		
-		// Load group names from /etc/group
-		if let Ok(file) = fs::File::open("/etc/group") {
-			let reader = BufReader::new(file);
-			for line in reader.lines() {
-				if let Ok(line) = line {
-					let parts: Vec<&str> = line.split(':').collect();
-					if parts.len() >= 3 {
-						if let Ok(gid) = parts[2].parse::<u32>() {
-							cache.groups.insert(gid, parts[0].to_string());
-						}
+			},
+			"sort" => {
+				if !self.set_sort(value) {
+					return false;
+				}
+			},
+			"group_related" => self.group_related = Self::parse_bool(value),
+			"dotfile_position" => match DotfilePosition::parse(value) {
+				Some(position) => self.dotfile_position = position,
+				None => return false,
+			},
+			"size_tiebreak" => match SizeTiebreak::parse(value) {
+				Some(tiebreak) => self.size_tiebreak = tiebreak,
+				None => return false,
+			},
+			"group_by" => match GroupBy::parse(value) {
+				Some(group_by) => self.group_by = group_by,
+				None => return false,
+			},
+			"collation" => match Collation::parse(value) {
+				Some(collation) => self.collation = collation,
+				None => return false,
+			},
+			"sort_ignore_leading" => self.sort_ignore_leading = value.to_string(),
+			"reverse" => self.reverse_sort = Self::parse_bool(value),
+			"permission_style" => match PermissionStyle::parse(value) {
+				Some(style) => self.permission_style = style,
+				None => return false,
+			},
+			"show_acl" => self.show_acl = Self::parse_bool(value),
+			"show_capabilities" => self.show_capabilities = Self::parse_bool(value),
+			"highlight_empty" => self.highlight_empty = Self::parse_bool(value),
+			"show_control_chars" => self.show_control_chars = Self::parse_bool(value),
+			"highlight_newer_than_dir" => self.highlight_newer_than_dir = Self::parse_bool(value),
+			"since_last" => match SinceLast::parse(value) {
+				Some(since_last) => self.since_last = since_last,
+				None => return false,
+			},
+			"show_overlay_markers" => self.show_overlay_markers = Self::parse_bool(value),
+			"max_name_width" => match MaxNameWidth::parse(value) {
+				Some(width) => self.max_name_width = width,
+				None => return false,
+			},
+			"name_column_width" => match value.parse() {
+				Ok(width) => self.name_column_width = width,
+				Err(_) => return false,
+			},
+			"color" => match ColorMode::parse(value) {
+				Some(mode) => self.color = mode,
+				None => return false,
+			},
+			"theme" => {
+				if let Err(error) = self.set_theme(value) {
+					eprintln!("yal: {}", error);
+					return false;
+				}
+			},
+			"theme_file" => match ThemeFile::load(&expand_base_path(value)) {
+				Ok(theme) => self.theme_file = Some(theme),
+				Err(error) => {
+					eprintln!("yal: {}", error);
+					return false;
+				},
+			},
+			"ambiguous_width" => match AmbiguousWidth::parse(value) {
+				Some(width) => self.ambiguous_width = width,
+				None => return false,
+			},
+			"hyperlinks" => self.hyperlinks = Self::parse_bool(value),
+			"hyperlink_scheme" => {
+				if let Err(message) = self.set_hyperlink_scheme(value) {
+					eprintln!("yal: {}", message);
+					return false;
+				}
+			},
+			"heatmap" => match Heatmap::parse(value) {
+				Some(heatmap) => self.heatmap = heatmap,
+				None => return false,
+			},
+			"legend" => self.show_legend = Self::parse_bool(value),
+			"paths_relative_to" => self.paths_relative_to = Some(expand_base_path(value)),
+			"passwd_file" => self.passwd_file = expand_base_path(value),
+			"group_file" => self.group_file = expand_base_path(value),
+			"abbreviate_home" => self.abbreviate_home = Self::parse_bool(value),
+			"recursive" => self.recursive = Self::parse_bool(value),
+			"cache" => self.cache = Self::parse_bool(value),
+			"max_threads" => match parse_thread_count(value) {
+				Ok(threads) => self.max_threads = threads,
+				Err(_) => return false,
+			},
+			"traversal" => match Traversal::parse(value) {
+				Some(traversal) => self.traversal = traversal,
+				None => return false,
+			},
+			"truncate_owner" => match value.parse::<usize>() {
+				Ok(width) => self.truncate_owner = Some(width).filter(|&width| width > 0),
+				Err(_) => return false,
+			},
+			"truncate_group" => match value.parse::<usize>() {
+				Ok(width) => self.truncate_group = Some(width).filter(|&width| width > 0),
+				Err(_) => return false,
+			},
+			"hide_own_owner" => self.hide_own_owner = Self::parse_bool(value),
+			"hide_own_group" => self.hide_own_group = Self::parse_bool(value),
+			"pin_names" => {
+				self.pin_names = value.split(',')
+					.map(|s| s.trim().to_string())
+					.filter(|s| !s.is_empty())
+					.collect();
+			},
+			"filter" => {
+				self.filter_patterns = value.split(',')
+					.map(|s| s.trim().to_string())
+					.filter(|s| !s.is_empty())
+					.collect();
+			},
+			"invert_filter" => self.invert_filter = Self::parse_bool(value),
+			"ignore" => {
+				self.ignore_patterns = value.split(',')
+					.map(|s| s.trim().to_string())
+					.filter(|s| !s.is_empty())
+					.collect();
+			},
+			"info_fields" => {
+				let fields: Vec<String> = value.split(',')
+					.map(|field| field.trim().to_lowercase())
+					.filter(|field| INFO_FIELDS.contains(&field.as_str()))
+					.collect();
+				if fields.is_empty() {
+					return false;
+				}
+				self.info_fields = fields;
+			},
+			"info_separator" => self.info_separator = value.to_string(),
+			"column_order" => {
+				let columns: Vec<String> = value.split(',')
+					.map(|s| s.trim().to_string())
+					.filter(|s| !s.is_empty())
+					.collect();
+				
+				// `+column` entries extend the current order instead of replacing it
+				if !columns.is_empty() && columns.iter().all(|column| column.starts_with('+')) {
+					for column in &columns {
+						self.add_column(column.trim_start_matches('+').trim());
 					}
+				} else {
+					self.column_order = columns;
 				}
+			},
+			// Column priorities for fit_columns: priority_<column> = N
+			_ if key.starts_with("priority_") => match value.parse() {
+				Ok(priority) => {
+					self.column_priorities.insert(key["priority_".len()..].to_string(), priority);
+				},
+				Err(_) => return false,
+			},
+			// Custom icons: icon.<extension> = glyph, or icon.directory for folders
+			_ if key.starts_with("icon.") && !value.is_empty() => {
+				self.custom_icons.insert(key["icon.".len()..].to_string(), value.to_string());
+			},
+			// Per-group sort keys: group_sort.<group label> = key, e.g. group_sort.mp4 = size
+			_ if key.starts_with("group_sort.") => match SortKey::parse(value) {
+				Some(sort_key) => {
+					self.group_sort.insert(group_sort_label(&key["group_sort.".len()..]), sort_key);
+				},
+				None => return false,
+			},
+			// Fuzzy-time translations: lang_now, lang_plural_rule, lang_<1|few|many|n>_<unit>
+			_ if key.starts_with("lang_") => {
+				self.lang_overrides.push((key["lang_".len()..].to_string(), value.to_string()));
+			},
+			_ => {
+				// Unknown config option, ignore silently (but `--explain-config` reports it)
+				self.explicit.remove(key);
+				return false;
 			}
 		}
-		
-		cache
+		true
 	}
 	
-	/// Get user name from UID, fallback to UID string if not found
-	fn get_user_name(&self, uid: u32) -> String {
-		self.users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+	/// Set the theme: dark, light and auto pick a built-in palette, anything else names a theme file
+	fn set_theme(&mut self, value: &str) -> Result<(), String> {
+		if let Some(theme) = Theme::parse(value) {
+			self.theme = theme;
+			self.theme_file = None;
+			return Ok(());
+		}
+		let theme = ThemeFile::named(value)
+			.ok_or_else(|| format!("invalid theme '{}' (expected dark, light, auto or a theme name)", value))?;
+		self.theme_file = Some(theme);
+		Ok(())
 	}
 	
-	/// Get group name from GID, fallback to GID string if not found
-	fn get_group_name(&self, gid: u32) -> String {
-		self.groups.get(&gid).cloned().unwrap_or_else(|| gid.to_string())
+	/// Set the sort key; `natural` sorts by name with natural collation
+	fn set_sort(&mut self, value: &str) -> bool {
+		if value.eq_ignore_ascii_case("natural") {
+			self.sort_key = SortKey::Name;
+			self.collation = Collation::Natural;
+			return true;
+		}
+		match SortKey::parse(value) {
+			Some(key) => {
+				self.sort_key = key;
+				true
+			},
+			None => false,
+		}
 	}
-}
-
-/// Represents a file system entry with display information
-#[derive(Debug)]
-struct FileEntry {
-	name: String,
-	permissions: String,
-	owner: String,
-	group: String,
-	modified_text: String,
-	icon: &'static str,
-	is_dir: bool,
-}
-
-impl FileEntry {
-	/// Create a new FileEntry from a directory entry
-	fn new(entry: &fs::DirEntry, name_cache: &NameCache, config: &Config) -> std::io::Result<Self> {
-		let metadata = entry.metadata()?;
-		let file_name = entry.file_name().to_string_lossy().to_string();
-		
-		// Get permissions in octal format
-		let mode = metadata.permissions().mode();
-		let permissions = format!("{:o}", mode & 0o777);
-		
-		// Get owner and group IDs and resolve to names
-		let owner_uid = metadata.uid();
-		let group_gid = metadata.gid();
-		let owner = name_cache.get_user_name(owner_uid);
-		let group = name_cache.get_group_name(group_gid);
-		
-		// Get modification time and format according to config
-		let modified_text = match metadata.modified() {
-			Ok(modified_time) => format_duration_since(modified_time, config.use_fuzzy_time),
-			Err(_) => "unknown".to_string(),
+	
+	/// Set the timestamp format, warning about specifiers that will be shown literally
+	fn set_time_format(&mut self, value: &str) {
+		let unsupported = unsupported_time_specifiers(value);
+		if !unsupported.is_empty() {
+			eprintln!("yal: time format '{}': unsupported {} (supported: {})", value, unsupported.join(", "), TIME_SPECIFIERS);
+		}
+		self.time_format = Some(value.to_string()).filter(|format| !format.is_empty());
+	}
+	
+	/// Use a hyperlink template, which must contain `{path}`
+	fn set_hyperlink_scheme(&mut self, value: &str) -> Result<(), String> {
+		if !value.contains("{path}") {
+			return Err(format!("invalid hyperlink scheme '{}' (expected a template containing {{path}})", value));
+		}
+		self.hyperlink_scheme = value.to_string();
+		Ok(())
+	}
+	
+	/// Add a column to the current order at its canonical position and enable it
+	fn add_column(&mut self, column: &str) {
+		let Some(rank) = ALL_COLUMNS.iter().position(|&known| known == column) else {
+			return; // Unknown column
 		};
 		
-		let is_dir = metadata.is_dir();
-		let icon = get_file_icon(&file_name, is_dir);
+		if !self.column_order.iter().any(|existing| existing == column) {
+			// Insert before the first column that canonically comes after this one
+			let position = self.column_order
+				.iter()
+				.position(|existing| ALL_COLUMNS.iter().position(|&known| known == existing).is_some_and(|other| other > rank))
+				.unwrap_or(self.column_order.len());
+			self.column_order.insert(position, column.to_string());
+		}
 		
-		Ok(FileEntry {
-			name: file_name,
-			permissions,
-			owner,
-			group,
-			modified_text,
-			icon,
-			is_dir,
-		})
+		match column {
+			"icon" => self.show_icons = true,
+			"permissions" => self.show_permissions = true,
+			"size" => self.show_size = true,
+			"owner" => self.show_owner = true,
+			"group" => self.show_group = true,
+			"modified" => self.show_modified = true,
+			"age" => self.show_age = true,
+			"links" => self.show_links = true,
+			"fstype" => self.show_fstype = true,
+			"size_bar" => self.show_size_bars = true,
+			"percent" => self.show_percent = true,
+			"access" => self.show_access = true,
+			"owner_group" => self.show_owner_group = true,
+			_ => {},
+		}
 	}
 	
-	/// Format this entry for display with proper column alignment
-	fn format_display(&self, config: &Config, max_icon_width: usize, max_perms_len: usize, max_owner_len: usize, max_group_len: usize, max_modified_len: usize) -> String {
-		// Use ANSI escape codes for colors
-		let (name_color, reset) = if self.is_dir {
-			("\x1b[34;1m", "\x1b[0m") // Blue bold for directories
+	/// Fix everything that makes the order or wording depend on the host, for `--stable`
+	///
+	/// Names compare byte-wise and case-sensitively, so no two names tie and
+	/// directory order never shows through; `collation`, `sort_ignore_leading`,
+	/// `size_tiebreak`, `time_language` and smart defaults are overridden.
+	fn apply_stable(&mut self) {
+		for (key, value) in [("collation", "bytes"), ("sort_ignore_leading", ""), ("size_tiebreak", "name"), ("time_language", "en"), ("smart_defaults", "off")] {
+			self.set_option_from(key, value, "--stable");
+		}
+		self.lang_overrides.clear();
+	}
+	
+	/// Turn off everything that needs an entry's metadata, for `--no-metadata`
+	///
+	/// Only the name, icon and type coloring remain; sorts by size, time or
+	/// owner fall back to name, since every entry would tie.
+	fn apply_no_metadata(&mut self) {
+		for key in NO_METADATA_OFF {
+			self.set_option_from(key, "false", "--no-metadata");
+		}
+		self.set_option_from("since_last", "off", "--no-metadata");
+		self.rebuild_target = None;
+		self.newer_than = None;
+		self.audit = false;
+		if !matches!(self.sort_key, SortKey::Name | SortKey::Extension | SortKey::None) {
+			self.set_option_from("sort", "name", "--no-metadata");
+		}
+	}
+	
+	/// Whether any of the given options was set explicitly
+	fn is_explicit(&self, keys: &[&str]) -> bool {
+		keys.iter().any(|key| self.explicit.contains(*key))
+	}
+	
+	/// Fuzzy-time words for the configured language, with `lang_*` overrides applied
+	fn fuzzy_locale(&self) -> FuzzyLocale {
+		// Following LANG quietly falls back to English; asking for a language doesn't
+		let mut locale = if self.time_language.eq_ignore_ascii_case("auto") {
+			FuzzyLocale::bundled(&environment_language()).unwrap_or_else(FuzzyLocale::english)
 		} else {
-			("", "") // No color for files
+			let language = self.time_language.to_lowercase();
+			FuzzyLocale::bundled(&language).unwrap_or_else(|| {
+				eprintln!("yal: no fuzzy time words for language '{}', using English", language);
+				FuzzyLocale::english()
+			})
 		};
-		
-		if config.column_format {
-			// Column format with alignment
-			self.format_columns(config, max_icon_width, max_perms_len, max_owner_len, max_group_len, max_modified_len, &name_color, &reset)
-		} else {
-			// Simple list format
-			self.format_simple(config, max_icon_width, &name_color, &reset)
+		for (key, value) in &self.lang_overrides {
+			locale.set(key, value);
 		}
+		locale
 	}
 	
-	/// Format entry in column layout
-	fn format_columns(&self, config: &Config, max_icon_width: usize, max_perms_len: usize, max_owner_len: usize, max_group_len: usize, max_modified_len: usize, name_color: &str, reset: &str) -> String {
-		let mut parts = Vec::new();
-		
-		for column in &config.column_order {
-			match column.as_str() {
-				"icon" if config.show_icons => parts.push(pad_to_display_width(self.icon, max_icon_width)),
-				"permissions" if config.show_permissions => parts.push(format!("\x1b[33m{:<width$}\x1b[0m", self.permissions, width = max_perms_len)),
-				"owner" if config.show_owner => parts.push(format!("\x1b[32m{:<width$}\x1b[0m", self.owner, width = max_owner_len)),
-				"group" if config.show_group => parts.push(format!("\x1b[36m{:<width$}\x1b[0m", self.group, width = max_group_len)),
-				"modified" if config.show_modified => parts.push(format!("\x1b[35m{:<width$}\x1b[0m", self.modified_text, width = max_modified_len)),
-				"name" => parts.push(format!("{}{}{}", name_color, self.name, reset)),
-				_ => {} // Skip unknown or disabled columns
-			}
+	/// Style for relative times, fuzzy or not, honoring `fuzzy_fixed_width` and then `fuzzy_fractional`
+	fn relative_time_style(&self, fuzzy: bool) -> RelativeTimeStyle {
+		match (fuzzy, self.fuzzy_fixed_width, self.fuzzy_fractional) {
+			(false, _, _) => RelativeTimeStyle::Timestamp,
+			(true, true, _) => RelativeTimeStyle::Compact,
+			(true, false, true) => RelativeTimeStyle::Fractional,
+			(true, false, false) => RelativeTimeStyle::Fuzzy,
 		}
-		
-		parts.join(" ")
 	}
 	
-	/// Format entry in simple list layout
-	fn format_simple(&self, config: &Config, max_icon_width: usize, name_color: &str, reset: &str) -> String {
-		let mut parts = Vec::new();
+	/// Format a byte count in the configured unit: human-readable, or whole blocks rounded up
+	fn format_bytes(&self, bytes: u64) -> String {
+		match self.block_size {
+			Some(block_size) => self.format_number(bytes.div_ceil(block_size)),
+			None => format_size(bytes, self.size_units, self.size_precision, self.size_rounding, &self.size_suffixes),
+		}
+	}
+	
+	/// Format a count using the configured digit grouping
+	fn format_number(&self, number: u64) -> String {
+		match self.digit_separator {
+			Some(separator) => group_digits(number, separator),
+			None => number.to_string(),
+		}
+	}
+	
+	/// Parse boolean values from config
+	fn parse_bool(value: &str) -> bool {
+		match value.to_lowercase().as_str() {
+			"true" | "yes" | "1" | "on" | "enabled" => true,
+			"false" | "no" | "0" | "off" | "disabled" => false,
+			_ => false, // Default to false for invalid values
+		}
+	}
+	
+	/// Apply command line arguments on top of the loaded configuration,
+	/// returning the directories to list
+	fn apply_args(&mut self, args: &[String]) -> Result<Vec<PathBuf>, String> {
+		let mut directories = Vec::new();
+		let mut iter = args.iter();
 		
-		for column in &config.column_order {
-			match column.as_str() {
-				"icon" if config.show_icons => parts.push(pad_to_display_width(self.icon, max_icon_width)),
-				"permissions" if config.show_permissions => parts.push(format!("\x1b[33m{}\x1b[0m", self.permissions)),
-				"owner" if config.show_owner => parts.push(format!("\x1b[32m{}\x1b[0m", self.owner)),
-				"group" if config.show_group => parts.push(format!("\x1b[36m{}\x1b[0m", self.group)),
-				"modified" if config.show_modified => parts.push(format!("\x1b[35m{}\x1b[0m", self.modified_text)),
-				"name" => parts.push(format!("{}{}{}", name_color, self.name, reset)),
-				_ => {} // Skip unknown or disabled columns
+		while let Some(arg) = iter.next() {
+			// Support both `--flag=value` and `--flag value` forms
+			let (flag, inline_value) = match arg.split_once('=') {
+				Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+				_ => (arg.as_str(), None),
+			};
+			// The value the flag took, kept for `--explain-config`
+			let mut given = None;
+			let mut value = || inline_value.clone()
+				.or_else(|| iter.next().cloned())
+				.ok_or_else(|| format!("option '{}' requires a value", flag))
+				.inspect(|value| given = Some(value.clone()));
+			
+			// Remember options that smart defaults must not override
+			let explicit = match flag {
+				"--format" | "--reflow" | "--html" | "--shell-quote" => Some("output_format"),
+				"--sort" | "--no-sort" | "--biggest" | "--newest" | "--touch-order" | "-r" | "--reverse" => Some("sort"),
+				"--fit" => Some("fit_columns"),
+				"--deduplicate-columns" => Some("auto_hide_uniform_columns"),
+				_ => None,
+			};
+			if let Some(key) = explicit {
+				self.explicit.insert(key.to_string());
+			}
+			
+			match flag {
+				"-h" | "--help" => {
+					print_usage();
+					std::process::exit(0);
+				},
+				"--which-config" => {
+					print_config_files();
+					std::process::exit(0);
+				},
+				"--json-schema" => {
+					print_json_schema();
+					std::process::exit(0);
+				},
+				"--explain-config" | "--config-precedence" => self.explain_config = true,
+				"--init" => {
+					let shell = value()?;
+					let script = shell_init_script(&shell)
+						.ok_or_else(|| format!("unsupported shell '{}' (expected bash, zsh or fish)", shell))?;
+					print!("{}", script);
+					std::process::exit(0);
+				},
+				"--format" => {
+					let value = value()?;
+					self.output_format = OutputFormat::parse(&value)
+						.ok_or_else(|| format!("invalid format '{}' (expected columns, simple, table, grid, json, jsonl, csv, yaml or html)", value))?;
+				},
+				"--reflow" => self.output_format = OutputFormat::Grid,
+				"--html" => self.output_format = OutputFormat::Html,
+				"--shell-quote" => self.output_format = OutputFormat::Shell,
+				"--fit" => self.fit_columns = true,
+				"--deduplicate-columns" => self.auto_hide_uniform_columns = true,
+				"--max-entries" => self.max_entries = parse_limit(&value()?)?,
+				"--sort" => {
+					let value = value()?;
+					if !self.set_sort(&value) {
+						return Err(format!("invalid sort key '{}' (expected name, natural, size, time, accessed, owner, group, extension or none)", value));
+					}
+				},
+				"--group-by" => {
+					let value = value()?;
+					self.group_by = GroupBy::parse(&value)
+						.ok_or_else(|| format!("invalid grouping '{}' (expected extension, owner, type, age or none)", value))?;
+				},
+				"--size-tiebreak" => {
+					let value = value()?;
+					self.size_tiebreak = SizeTiebreak::parse(&value)
+						.ok_or_else(|| format!("invalid size tiebreak '{}' (expected name, mtime or type)", value))?;
+				},
+				"--collation" => {
+					let value = value()?;
+					self.collation = Collation::parse(&value)
+						.ok_or_else(|| format!("invalid collation '{}' (expected ascii, natural, unicode or bytes)", value))?;
+				},
+				"-a" | "--all" => {
+					self.show_hidden = true;
+					self.show_dot_entries = true;
+				},
+				"-A" | "--almost-all" => {
+					self.show_hidden = true;
+					self.show_dot_entries = false;
+				},
+				"--no-sort" => self.sort_key = SortKey::None,
+				"--group-related" => self.group_related = true,
+				"--max-name-width" => {
+					let value = value()?;
+					self.max_name_width = MaxNameWidth::parse(&value)
+						.ok_or_else(|| format!("invalid name width '{}' (expected a number or auto)", value))?;
+				},
+				"--stream" => self.stream = true,
+				"--output" | "--output-file" => self.output_file = Some(PathBuf::from(value()?)),
+				"--stream-threshold" => self.stream_threshold = parse_limit(&value()?)?,
+				"-r" | "--reverse" => self.reverse_sort = true,
+				"-R" | "--recursive" => self.recursive = true,
+				"--depth-first" => self.traversal = Traversal::DepthFirst,
+				"--breadth-first" => self.traversal = Traversal::BreadthFirst,
+				"--total" => self.show_total = true,
+				"--summary" => self.show_summary = true,
+				"--combined-total" => self.combined_total = true,
+				"--compare-times" => self.highlight_newer_than_dir = true,
+				"--stable" => self.stable = true,
+				"--no-metadata" => self.no_metadata = true,
+				"--filtered-count" => self.show_filtered_count = true,
+				"--block-size" => {
+					let value = value()?;
+					self.block_size = parse_block_size(&value)
+						.ok_or_else(|| format!("invalid block size '{}' (expected human or a size like 1K, 1M, 4096)", value))?;
+				},
+				"--si" => self.size_units = SizeUnits::Si,
+				"--iec" => self.size_units = SizeUnits::Iec,
+				"--no-owner" => self.show_owner = false,
+				"--no-group" => self.show_group = false,
+				"--flat" => self.flat = true,
+				"--tree" => self.tree = true,
+				"--dirs-tree" => {
+					self.tree = true;
+					self.tree_dirs_only = true;
+				},
+				"--dirs-sizes" => self.dirs_sizes = true,
+				"--max-depth" => {
+					let value = value()?;
+					self.max_depth = Some(value.parse()
+						.map_err(|_| format!("invalid depth '{}'", value))?);
+				},
+				"--follow-symlink-dirs-in-tree" => self.tree_follow_symlinks = DEFAULT_TREE_FOLLOW_SYMLINKS,
+				"-L" | "--dereference" => self.dereference = true,
+				"--no-dereference" => self.dereference = false,
+				"--symlink-depth" => self.show_symlink_depth = true,
+				"-P" | "--physical" => self.physical = true,
+				"--logical" => self.physical = false,
+				"--recurse-into-hidden" => self.recurse_hidden = Some(true),
+				"--no-recurse-into-hidden" => self.recurse_hidden = Some(false),
+				"--audit" => self.audit = true,
+				"--stats" => self.stats = true,
+				"--cache" => self.cache = true,
+				"--threads" => self.max_threads = parse_thread_count(&value()?)?,
+				"--no-cache" => self.cache = false,
+				"--diff" => self.diff_mode = true,
+				"--dedup-inodes" => self.dedup_inodes = true,
+				"--complete" => self.complete_mode = true,
+				"--recursive-size" => {
+					self.recursive_size = true;
+					self.show_size = true;
+				},
+				"-q" | "--quiet" => self.quiet = true,
+				"-v" | "--verbose" => self.verbose = true,
+				"--size-bars" => self.show_size_bars = true,
+				"--percent" => self.show_percent = true,
+				"--heatmap" => {
+					let value = value()?;
+					self.heatmap = Heatmap::parse(&value)
+						.ok_or_else(|| format!("invalid heatmap '{}' (expected off, time or size)", value))?;
+				},
+				"--legend" => self.show_legend = true,
+				"--color" => {
+					let value = value()?;
+					self.color = ColorMode::parse(&value)
+						.ok_or_else(|| format!("invalid color mode '{}' (expected auto, always or never)", value))?;
+				},
+				"--force-color" => self.color = ColorMode::Always,
+				"--theme" => {
+					let value = value()?;
+					self.set_theme(&value)?;
+				},
+				"--time-format" => self.set_time_format(&value()?),
+				"--ambiguous-width" => {
+					let value = value()?;
+					self.ambiguous_width = AmbiguousWidth::parse(&value)
+						.ok_or_else(|| format!("invalid ambiguous width '{}' (expected single or double)", value))?;
+				},
+				"--hyperlink" => self.hyperlinks = true,
+				"--no-hyperlink" => self.hyperlinks = false,
+				"--hyperlink-scheme" => {
+					self.set_hyperlink_scheme(&value()?)?;
+					self.hyperlinks = true;
+				},
+				"--prefix" => self.name_prefix = Some(value()?),
+				"--filter" => self.filter_patterns.push(value()?),
+				"--invert" | "--invert-filter" => self.invert_filter = true,
+				"--skip-special" => self.skip_special = true,
+				"-I" | "--ignore" => self.ignore_patterns.push(value()?),
+				"--select" => self.select_mode = true,
+				"--newer-than-file" => {
+					let reference = value()?;
+					let modified = fs::metadata(&reference)
+						.and_then(|metadata| metadata.modified())
+						.map_err(|error| format!("cannot read reference file '{}': {}", reference, error))?;
+					self.newer_than = Some(modified);
+				},
+				"--batch-file" => {
+					directories.extend(read_batch_file(&value()?)?);
+					self.batch_file = true;
+				},
+				"--paths-relative-to" => self.paths_relative_to = Some(expand_base_path(&value()?)),
+				"--passwd-file" => self.passwd_file = expand_base_path(&value()?),
+				"--group-file" => self.group_file = expand_base_path(&value()?),
+				"--resolve-ids-from" => {
+					// Names as seen inside a mounted container or chroot
+					let root = expand_base_path(&value()?);
+					self.passwd_file = root.join(PASSWD_PATH.trim_start_matches('/'));
+					self.group_file = root.join(GROUP_PATH.trim_start_matches('/'));
+				},
+				"--abbreviate-home" => self.abbreviate_home = true,
+				"--expand-home" => self.abbreviate_home = false,
+				// Shorthands expand to the underlying options; a later --sort still overrides
+				"--biggest" => {
+					self.sort_key = SortKey::Size;
+					self.reverse_sort = true;
+					self.max_entries = parse_limit(&value()?)?;
+				},
+				"--newest" => {
+					self.sort_key = SortKey::Time;
+					self.reverse_sort = true;
+					self.max_entries = parse_limit(&value()?)?;
+				},
+				"--touch-order" => {
+					self.sort_key = SortKey::Time;
+					self.reverse_sort = false;
+				},
+				"--target" => self.rebuild_target = Some(PathBuf::from(value()?)),
+				"--since-last" => self.since_last = SinceLast::Highlight,
+				"--only-since-last" => self.since_last = SinceLast::Filter,
+				_ if !arg.starts_with('-') || arg == "-" => directories.push(PathBuf::from(arg)),
+				_ => return Err(format!("unrecognized option '{}'", arg)),
+			}
+			for (key, value) in self.flag_settings(flag, given.as_deref().unwrap_or_default()) {
+				self.record_origin(key, &value, "command line");
 			}
 		}
 		
-		parts.join(" ")
+		Ok(directories)
+	}
+	
+	/// The options a command line flag stands for, in config syntax, given the value it took
+	///
+	/// Flags with no config equivalent, like `--tree` or `--diff`, stand for none.
+	fn flag_settings(&self, flag: &str, value: &str) -> Vec<(&'static str, String)> {
+		let settings: &[(&str, &str)] = match flag {
+			"--format" => &[("output_format", value)],
+			"--reflow" => &[("output_format", "grid")],
+			"--html" => &[("output_format", "html")],
+			"--shell-quote" => &[("output_format", "shell")],
+			"--fit" => &[("fit_columns", "true")],
+			"--deduplicate-columns" => &[("auto_hide_uniform_columns", "true")],
+			"--max-entries" => &[("max_entries", value)],
+			"--sort" => &[("sort", value)],
+			"--group-by" => &[("group_by", value)],
+			"--size-tiebreak" => &[("size_tiebreak", value)],
+			"--collation" => &[("collation", value)],
+			"-a" | "--all" => &[("show_hidden", "true"), ("show_dot_entries", "true")],
+			"-A" | "--almost-all" => &[("show_hidden", "true"), ("show_dot_entries", "false")],
+			"--no-sort" => &[("sort", "none")],
+			"--group-related" => &[("group_related", "true")],
+			"--max-name-width" => &[("max_name_width", value)],
+			"--stream-threshold" => &[("stream_threshold", value)],
+			"-r" | "--reverse" => &[("reverse", "true")],
+			"-R" | "--recursive" => &[("recursive", "true")],
+			"--depth-first" => &[("traversal", "depth-first")],
+			"--breadth-first" => &[("traversal", "breadth-first")],
+			"--total" => &[("show_total", "true")],
+			"--summary" => &[("show_summary", "true")],
+			"--combined-total" => &[("combined_total", "true")],
+			"--compare-times" => &[("highlight_newer_than_dir", "true")],
+			"--stable" => &[("stable", "true")],
+			"--no-metadata" => &[("no_metadata", "true")],
+			"--filtered-count" => &[("show_filtered_count", "true")],
+			"--block-size" => &[("block_size", value)],
+			"--si" => &[("size_units", "si")],
+			"--iec" => &[("size_units", "iec")],
+			"--no-owner" => &[("show_owner", "false")],
+			"--no-group" => &[("show_group", "false")],
+			"--dirs-tree" => &[("tree_dirs_only", "true")],
+			"-L" | "--dereference" => &[("dereference", "true")],
+			"--no-dereference" => &[("dereference", "false")],
+			"--symlink-depth" => &[("show_symlink_depth", "true")],
+			"-P" | "--physical" => &[("physical", "true")],
+			"--logical" => &[("physical", "false")],
+			"--recurse-into-hidden" => &[("recurse_hidden", "true")],
+			"--no-recurse-into-hidden" => &[("recurse_hidden", "false")],
+			"--cache" => &[("cache", "true")],
+			"--no-cache" => &[("cache", "false")],
+			"--threads" => &[("max_threads", value)],
+			"--dedup-inodes" => &[("dedup_inodes", "true")],
+			"--recursive-size" => &[("recursive_size", "true"), ("show_size", "true")],
+			"-q" | "--quiet" => &[("quiet", "true")],
+			"-v" | "--verbose" => &[("verbose", "true")],
+			"--size-bars" => &[("show_size_bars", "true")],
+			"--percent" => &[("show_percent", "true")],
+			"--heatmap" => &[("heatmap", value)],
+			"--legend" => &[("legend", "true")],
+			"--color" => &[("color", value)],
+			"--force-color" => &[("color", "always")],
+			"--theme" => &[("theme", value)],
+			"--time-format" => &[("time_format", value)],
+			"--ambiguous-width" => &[("ambiguous_width", value)],
+			"--hyperlink" => &[("hyperlinks", "true")],
+			"--no-hyperlink" => &[("hyperlinks", "false")],
+			"--hyperlink-scheme" => &[("hyperlink_scheme", value), ("hyperlinks", "true")],
+			"--invert" | "--invert-filter" => &[("invert_filter", "true")],
+			"--skip-special" => &[("skip_special", "true")],
+			"--paths-relative-to" => &[("paths_relative_to", value)],
+			"--passwd-file" => &[("passwd_file", value)],
+			"--group-file" => &[("group_file", value)],
+			"--abbreviate-home" => &[("abbreviate_home", "true")],
+			"--expand-home" => &[("abbreviate_home", "false")],
+			"--biggest" => &[("sort", "size"), ("reverse", "true"), ("max_entries", value)],
+			"--newest" => &[("sort", "time"), ("reverse", "true"), ("max_entries", value)],
+			"--touch-order" => &[("sort", "time"), ("reverse", "false")],
+			"--since-last" => &[("since_last", "highlight")],
+			"--only-since-last" => &[("since_last", "filter")],
+			_ => &[],
+		};
+		let mut settings: Vec<(&'static str, String)> = settings.iter()
+			.map(|&(key, value)| (key, value.to_string()))
+			.collect();
+		// These flags add to what the config set, so the option now holds all of it
+		match flag {
+			"--filter" => settings.push(("filter", self.filter_patterns.join(","))),
+			"-I" | "--ignore" => settings.push(("ignore", self.ignore_patterns.join(","))),
+			"--resolve-ids-from" => settings.extend([
+				("passwd_file", self.passwd_file.display().to_string()),
+				("group_file", self.group_file.display().to_string()),
+			]),
+			"--follow-symlink-dirs-in-tree" => settings.push(("tree_follow_symlinks", self.tree_follow_symlinks.to_string())),
+			_ => {},
+		}
+		settings
 	}
 }
 
-/// Pad a string with spaces to reach a target terminal display width
-fn pad_to_display_width(text: &str, target_width: usize) -> String {
-	let current_width = UnicodeWidthStr::width(text);
-	if current_width >= target_width {
-		// Ensure at least original text (no truncation), rely on outer separator for spacing
-		text.to_string()
-	} else {
-		let pad_spaces = target_width - current_width;
-		format!("{}{}", text, " ".repeat(pad_spaces))
-	}
+/// Resolve a base directory for relative path display, expanding a leading `~`
+fn expand_base_path(value: &str) -> PathBuf {
+	let path = match (value.strip_prefix('~'), env::var("HOME")) {
+		(Some(rest), Ok(home)) => PathBuf::from(home).join(rest.trim_start_matches('/')),
+		_ => PathBuf::from(value),
+	};
+	std::path::absolute(&path).unwrap_or(path)
 }
 
-/// Format duration since a given time into human-readable fuzzy text
-fn format_duration_since(modified_time: SystemTime, use_fuzzy: bool) -> String {
-	if !use_fuzzy {
-		// Return simplified timestamp instead of fuzzy time
-		if let Ok(duration) = modified_time.duration_since(std::time::UNIX_EPOCH) {
-			let secs = duration.as_secs();
-			// Simple timestamp format (days since epoch approximation)
-			let days = secs / 86400;
-			let hours = (secs % 86400) / 3600;
-			let minutes = (secs % 3600) / 60;
-			return format!("{}d {}h:{}m", days % 365, hours, minutes);
+/// Text of `path` for display, with a leading home directory shown as `~` when `abbreviate_home` is on
+fn display_path(path: &Path, config: &Config) -> String {
+	if config.abbreviate_home {
+		// Compared by component, so /home/al doesn't abbreviate /home/alice
+		let home = env::var_os("HOME").filter(|home| !home.is_empty() && home != "/");
+		if let Some(rest) = home.as_ref().and_then(|home| path.strip_prefix(home).ok()) {
+			return if rest.as_os_str().is_empty() { "~".to_string() } else { format!("~/{}", rest.display()) };
 		}
-		return "unknown".to_string();
 	}
-	let now = SystemTime::now();
+	path.display().to_string()
+}
+
+/// Parse an entry limit argument, where zero disables the limit
+fn parse_limit(value: &str) -> Result<Option<usize>, String> {
+	let limit: usize = value.parse()
+		.map_err(|_| format!("invalid entry limit '{}'", value))?;
+	Ok(Some(limit).filter(|&limit| limit > 0))
+}
+
+/// Parse a worker thread count, which must be at least 1
+fn parse_thread_count(value: &str) -> Result<usize, String> {
+	value.parse()
+		.ok()
+		.filter(|&threads| threads > 0)
+		.ok_or_else(|| format!("invalid thread count '{}' (expected a number of at least 1)", value))
+}
+
+/// The working directory as reached through any symlinks, like `pwd -L`
+///
+/// `$PWD` is used only while it is absolute, free of `.` and `..`, and still names
+/// the same directory; otherwise this is the real path from `getcwd`.
+fn logical_current_dir() -> io::Result<PathBuf> {
+	use std::path::Component;
 	
-	let duration = match now.duration_since(modified_time) {
-		Ok(d) => d,
-		Err(_) => return "future".to_string(), // File modified in the future?
+	let physical = env::current_dir()?;
+	let same_directory = |path: &Path| match (fs::metadata(path), fs::metadata(&physical)) {
+		(Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+		_ => false,
 	};
+	let logical = env::var_os("PWD")
+		.map(PathBuf::from)
+		.filter(|pwd| pwd.is_absolute())
+		.filter(|pwd| pwd.components().all(|component| matches!(component, Component::RootDir | Component::Normal(_))))
+		.filter(|pwd| same_directory(pwd));
+	Ok(logical.unwrap_or(physical))
+}
+
+/// Read directory paths from a batch file (or stdin for `-`), one per line
+///
+/// Only the line ending is stripped, so names with leading or trailing spaces survive.
+fn read_batch_file(path: &str) -> Result<Vec<PathBuf>, String> {
+	let contents = if path == "-" {
+		io::read_to_string(io::stdin())
+	} else {
+		fs::read_to_string(path)
+	}.map_err(|error| format!("cannot read batch file '{}': {}", path, error))?;
 	
-	let seconds = duration.as_secs();
+	Ok(contents.lines()
+		.map(|line| line.trim_end_matches('\r'))
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(PathBuf::from)
+		.collect())
+}
+
+/// Value of one field in machine-readable output
+enum FieldValue {
+	Text(String),
+	Number(u64),
+	Null,
+}
+
+/// Type of a machine-readable field, as described by `--json-schema`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldType {
+	Text,
+	Number,
+	/// Number, or null when unknown
+	OptionalNumber,
+	/// Text, or null when absent
+	OptionalText,
+}
+
+/// Name, type and description of each field `entry_fields` returns, in order
+const ENTRY_FIELDS: [(&str, FieldType, &str); 10] = [
+	("name", FieldType::Text, "File name, or the path below the listed directory with --flat"),
+	("path", FieldType::Text, "Path of the entry as listed"),
+	("type", FieldType::Text, "File type: file, directory, symlink, fifo, socket, char_device or block_device"),
+	("size", FieldType::Number, "Size in bytes, or the total of the contents with recursive_size"),
+	("mode", FieldType::Text, "Permission bits as four octal digits"),
+	("owner", FieldType::Text, "Owner name, or the UID when it has none"),
+	("group", FieldType::Text, "Group name, or the GID when it has none"),
+	("links", FieldType::Number, "Hard link count"),
+	("modified", FieldType::OptionalNumber, "Modification time in seconds since the Unix epoch"),
+	("target", FieldType::OptionalText, "Where a symlink points, read only on pseudo filesystems like /proc"),
+];
+
+/// Raw, undecorated fields of an entry, shared by the JSON, CSV, YAML and HTML writers
+fn entry_fields(entry: &FileEntry) -> Vec<(&'static str, FieldValue)> {
+	let modified = entry.modified
+		.and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+		.map_or(FieldValue::Null, |duration| FieldValue::Number(duration.as_secs()));
+	// Values in `ENTRY_FIELDS` order; the array lengths keep the two in step
+	let values = [
+		FieldValue::Text(entry.name.clone()),
+		FieldValue::Text(entry.path.display().to_string()),
+		FieldValue::Text(entry.kind.name().to_string()),
+		FieldValue::Number(entry.size),
+		FieldValue::Text(format!("{:04o}", entry.mode & 0o7777)),
+		FieldValue::Text(entry.owner.clone()),
+		FieldValue::Text(entry.group.clone()),
+		FieldValue::Number(entry.links),
+		modified,
+		entry.symlink_target.clone().map_or(FieldValue::Null, FieldValue::Text),
+	];
+	ENTRY_FIELDS.iter().map(|(name, _, _)| *name).zip(values).collect()
+}
+
+/// Print a JSON Schema for `--format=json` output, whose `entry` definition also describes each `jsonl` line
+fn print_json_schema() {
+	let properties: Vec<String> = ENTRY_FIELDS.iter()
+		.map(|(name, field_type, description)| {
+			let json_type = match field_type {
+				FieldType::Text => "\"string\"",
+				FieldType::Number => "\"integer\", \"minimum\": 0",
+				FieldType::OptionalNumber => "[\"integer\", \"null\"], \"minimum\": 0",
+				FieldType::OptionalText => "[\"string\", \"null\"]",
+			};
+			format!("        \"{}\": {{\"type\": {}, \"description\": {}}}", name, json_type, json_string(description))
+		})
+		.collect();
+	let required: Vec<String> = ENTRY_FIELDS.iter().map(|(name, _, _)| format!("\"{}\"", name)).collect();
 	
-	match seconds {
-		0..=59 => {
-			if seconds == 0 { "now".to_string() }
-			else if seconds == 1 { "1 second".to_string() }
-			else { format!("{} seconds", seconds) }
-		},
-		60..=3599 => {
-			let minutes = seconds / 60;
-			if minutes == 1 { "1 minute".to_string() }
-			else { format!("{} minutes", minutes) }
-		},
-		3600..=86399 => {
-			let hours = seconds / 3600;
-			if hours == 1 { "1 hour".to_string() }
-			else { format!("{} hours", hours) }
-		},
-		86400..=604799 => {
-			let days = seconds / 86400;
-			if days == 1 { "1 day".to_string() }
-			else { format!("{} days", days) }
-		},
-		604800..=2629743 => {
-			let weeks = seconds / 604800;
-			if weeks == 1 { "1 week".to_string() }
-			else { format!("{} weeks", weeks) }
+	println!("{{");
+	println!("  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",");
+	println!("  \"title\": \"yal --format=json output\",");
+	println!("  \"description\": \"An array of entries; each line of --format=jsonl output is one entry (#/$defs/entry)\",");
+	println!("  \"type\": \"array\",");
+	println!("  \"items\": {{\"$ref\": \"#/$defs/entry\"}},");
+	println!("  \"$defs\": {{");
+	println!("    \"entry\": {{");
+	println!("      \"type\": \"object\",");
+	println!("      \"properties\": {{");
+	println!("{}", properties.join(",\n"));
+	println!("      }},");
+	println!("      \"required\": [{}],", required.join(", "));
+	println!("      \"additionalProperties\": false");
+	println!("    }}");
+	println!("  }}");
+	println!("}}");
+}
+
+/// Write all entries to `out` as one JSON, CSV, YAML or HTML document
+///
+/// `sections` names the listed directory each range of `entries` came from.
+fn print_machine_readable(out: &mut impl Write, entries: &[FileEntry], sections: &[(PathBuf, Range<usize>)], config: &Config) -> io::Result<()> {
+	match config.output_format {
+		OutputFormat::Json => print_json(out, entries)?,
+		OutputFormat::JsonLines => {
+			for entry in entries {
+				writeln!(out, "{}", json_object(entry))?;
+			}
 		},
-		2629744..=31556925 => {
-			let months = seconds / 2629744; // Approximate month
-			if months == 1 { "1 month".to_string() }
-			else { format!("{} months", months) }
+		OutputFormat::Csv => print_csv(out, entries)?,
+		OutputFormat::Yaml => print_yaml(out, entries)?,
+		OutputFormat::Html => print_html(out, entries, sections)?,
+		OutputFormat::Shell => {
+			// Bare names would be ambiguous across several directories
+			let qualified = config.recursive || sections.len() > 1;
+			for entry in entries {
+				let name = if qualified { entry.path.clone() } else { entry.shell_name(config) };
+				writeln!(out, "{}", shell_quote(name.as_os_str()))?;
+			}
 		},
-		_ => {
-			let years = seconds / 31556926; // Approximate year
-			if years == 1 { "1 year".to_string() }
-			else { format!("{} years", years) }
+		_ => {},
+	}
+	out.flush()
+}
+
+/// Default colors for HTML output, matching the terminal's directory and audit highlighting
+const HTML_STYLE: &str = "<style>
+table.yal { border-collapse: collapse; font-family: monospace; }
+table.yal caption { font-weight: bold; text-align: left; padding: 0.25em 0.5em; }
+table.yal th, table.yal td { padding: 0.1em 0.75em; text-align: left; }
+table.yal td.size, table.yal td.links { text-align: right; }
+table.yal tr.directory td.name { color: #3465a4; font-weight: bold; }
+table.yal tr.symlink td.name { color: #06989a; }
+table.yal tr.flagged td.name { color: #cc0000; font-weight: bold; }
+</style>";
+
+/// Print entries as HTML tables captioned with their directory, rows classed by file type
+fn print_html(out: &mut impl Write, entries: &[FileEntry], sections: &[(PathBuf, Range<usize>)]) -> io::Result<()> {
+	writeln!(out, "{}", HTML_STYLE)?;
+	for (directory, range) in sections {
+		writeln!(out, "<table class=\"yal\">")?;
+		writeln!(out, "<caption>{}</caption>", html_escape(&directory.display().to_string()))?;
+		if let Some(first) = entries.get(range.start).filter(|_| !range.is_empty()) {
+			let header: Vec<String> = entry_fields(first).iter()
+				.map(|(key, _)| format!("<th class=\"{}\">{}</th>", key, key))
+				.collect();
+			writeln!(out, "<thead><tr>{}</tr></thead>", header.concat())?;
+		}
+		writeln!(out, "<tbody>")?;
+		for entry in &entries[range.clone()] {
+			let mut classes = vec![entry.kind.name()];
+			if !entry.audit_flags.is_empty() {
+				classes.push("flagged");
+			}
+			let cells: Vec<String> = entry_fields(entry)
+				.into_iter()
+				.map(|(key, value)| {
+					let text = match value {
+						FieldValue::Text(text) => html_escape(&text),
+						// Epoch seconds are for programs; readers get a local date
+						FieldValue::Number(secs) if key == "modified" => format!(
+							"<time datetime=\"{}\">{}</time>",
+							format_unix_time(secs as i64, local_utc_offset(secs as i64), "%Y-%m-%dT%H:%M:%S"),
+							format_unix_time(secs as i64, local_utc_offset(secs as i64), "%Y-%m-%d %H:%M"),
+						),
+						FieldValue::Number(number) => number.to_string(),
+						FieldValue::Null => String::new(),
+					};
+					format!("<td class=\"{}\">{}</td>", key, text)
+				})
+				.collect();
+			writeln!(out, "<tr class=\"{}\">{}</tr>", classes.join(" "), cells.concat())?;
 		}
+		writeln!(out, "</tbody>")?;
+		writeln!(out, "</table>")?;
 	}
+	Ok(())
+}
+
+/// Whether a character is invisible or reorders text, so a name containing it can't be read as written
+///
+/// Besides control characters this covers zero-width characters, bidirectional
+/// overrides and isolates (as in "Trojan Source" names) and the byte order mark.
+fn is_invisible_char(c: char) -> bool {
+	c.is_control() || matches!(c, '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+}
+
+/// Whether a name has invisible characters or leading or trailing spaces
+fn has_invisible_chars(name: &str) -> bool {
+	name.starts_with(' ') || name.ends_with(' ') || name.chars().any(is_invisible_char)
 }
 
-/// Get an appropriate icon for the file type
-fn get_file_icon(filename: &str, is_dir: bool) -> &'static str {
-	if is_dir {
-		return "📁";  // nf-cod-folder or folder emoji
+/// A name with its invisible characters made visible for `show_control_chars`
+///
+/// Control characters use caret notation (`^M` for a carriage return, `^?` for
+/// DEL), other invisible characters `<U+202E>`, and leading and trailing
+/// spaces `␣`. Spaces between words are left alone.
+fn visible_chars(name: &str) -> String {
+	let body = name.trim_matches(' ');
+	let leading = name.len() - name.trim_start_matches(' ').len();
+	let trailing = if body.is_empty() { 0 } else { name.len() - name.trim_end_matches(' ').len() };
+	let mut visible = "␣".repeat(leading);
+	for c in body.chars() {
+		match c {
+			'\x00'..='\x1f' => {
+				visible.push('^');
+				visible.push((c as u8 + b'@') as char);
+			},
+			'\x7f' => visible.push_str("^?"),
+			c if is_invisible_char(c) => visible.push_str(&format!("<U+{:04X}>", c as u32)),
+			c => visible.push(c),
+		}
 	}
+	visible.push_str(&"␣".repeat(trailing));
+	visible
+}
+
+/// Quote a name for a POSIX shell, leaving names of only safe characters bare
+///
+/// Everything else is wrapped in single quotes, with embedded quotes written
+/// as `'\''`, so `it's` becomes `'it'\''s'`. Names with control characters or
+/// bytes that aren't UTF-8 use `$'...'` escapes instead, keeping one name per
+/// line and the exact bytes. A leading `-` gets `./` so commands don't take the
+/// name for an option.
+fn shell_quote(name: &OsStr) -> String {
+	use std::os::unix::ffi::OsStrExt;
 	
-	// Get file extension
-	let extension = Path::new(filename)
-		.extension()
-		.and_then(|ext| ext.to_str())
-		.unwrap_or("")
-		.to_lowercase();
+	let bytes = name.as_bytes();
+	let prefix = if bytes.starts_with(b"-") { "./" } else { "" };
+	let text = match std::str::from_utf8(bytes) {
+		Ok(text) if !text.chars().any(char::is_control) => text,
+		_ => return format!("{}$'{}'", prefix, ansi_c_escape(bytes)),
+	};
 	
-	match extension.as_str() {
-		"rs" => "🦀",          // nf-dev-rust / Rust crab
-		"py" => "🐍",          // nf-dev-python / Python snake
-		"js" => "󰌞",          // nf-dev-javascript
-		"ts" => "󰛦",          // nf-dev-typescript
-		"html" | "htm" => "󰌝",// nf-dev-html5
-		"css" => "󰌜",         // nf-dev-css3
-		"json" => "󰘦",        // nf-mdi-code_json
-		"md" | "markdown" => "󰍔", // nf-dev-markdown
-		"txt" => "󰈙",         // nf-fa-file_text_o
-		"pdf" => "󰈦",         // nf-fa-file_pdf_o
-		"zip" | "tar" | "gz" | "rar" => "🗜️", // nf-fa-file_archive_o
-		"jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" => "🖼️", // nf-fa-file_image_o
-		"mp3" | "wav" | "flac" | "ogg" => "🎵", // nf-fa-file_audio_o
-		"mp4" | "mkv" | "avi" | "mov" => "🎬", // nf-fa-file_video_o
-		"exe" | "bin" => "⚙️",  // nf-mdi-application
-		"toml" | "yaml" | "yml" | "ini" | "conf" => "⚙️", // nf-mdi-settings
-		"c" | "h" => "󰙱",      // nf-custom-c
-		"cpp" | "cc" | "cxx" | "hpp" => "󰙲", // nf-custom-cpp
-		"java" => "󰬷",        // nf-dev-java
-		"php" => "󰌟",         // nf-dev-php
-		"rb" => "󰴭",          // nf-dev-ruby
-		"go" => "󰟓",          // nf-dev-go or "🐹" for gopher
-		"sh" | "bash" | "zsh" => "󰆍", // nf-dev-terminal
-		"sql" => "󰆼",         // nf-dev-database
-		"xml" => "󰗀",         // nf-mdi-xml
-		"log" => "󰌱",         // nf-fa-file_text_o
-		"lock" => "󰌾",        // nf-fa-lock
-		"dockerfile" => "🐳",  // Docker whale
-		"vue" => "󰡄",         // nf-mdi-vuejs
-		"react" | "jsx" | "tsx" => "󰜈", // nf-dev-react
-		"git" => "󰊢",         // nf-dev-git
-		"node" => "󰎙",        // nf-dev-nodejs_small
-		"npm" => "󰎙",         // nf-dev-nodejs_small
-		"yarn" => "󰬷",        // nf-seti-yarn
-		"docker" => "🐳",     // Docker whale
-		_ if filename.starts_with('.') => "󰘓", // nf-fa-eye_slash (hidden)
-		_ => "📄",             // nf-fa-file_o or generic file emoji
+	let safe = |c: char| c.is_ascii_alphanumeric() || "_-.,/:@%+=".contains(c);
+	// zsh expands a leading `=` into a command's path
+	if !text.is_empty() && text.chars().all(safe) && !text.starts_with('=') {
+		return format!("{}{}", prefix, text);
 	}
+	format!("{}'{}'", prefix, text.replace('\'', "'\\''"))
 }
 
-/// Main function - lists current directory contents with aligned columns
-fn main() -> std::io::Result<()> {
-	let current_dir = env::current_dir()?;
-	let entries = fs::read_dir(&current_dir)?;
-	
-	// Load configuration
-	let config = Config::load();
-	
-	// Create name cache for user/group resolution
-	let name_cache = NameCache::new();
-	
-	// Collect and sort entries
-	let mut file_entries = Vec::new();
-	for entry in entries {
-		let entry = entry?;
-		
-		// Skip hidden files unless configured to show them
-		let file_name = entry.file_name().to_string_lossy().to_string();
-		if !config.show_hidden && file_name.starts_with('.') {
-			continue;
+/// Escape bytes for a shell `$'...'` string: `\n`, `\t`, `\\`, `\'` and `\xNN`
+/// for other control and non-UTF-8 bytes; valid UTF-8 text is kept as it is
+fn ansi_c_escape(bytes: &[u8]) -> String {
+	let mut escaped = String::with_capacity(bytes.len());
+	for chunk in bytes.utf8_chunks() {
+		for c in chunk.valid().chars() {
+			match c {
+				'\n' => escaped.push_str("\\n"),
+				'\t' => escaped.push_str("\\t"),
+				'\\' => escaped.push_str("\\\\"),
+				'\'' => escaped.push_str("\\'"),
+				c if c.is_control() && c.is_ascii() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+				c => escaped.push(c),
+			}
 		}
-		
-		match FileEntry::new(&entry, &name_cache, &config) {
-			Ok(file_entry) => file_entries.push(file_entry),
-			Err(_) => continue, // Skip entries we can't read
+		for byte in chunk.invalid() {
+			escaped.push_str(&format!("\\x{:02x}", byte));
 		}
 	}
-	
-	// Sort according to configuration
-	file_entries.sort_by(|a, b| {
-		if config.sort_dirs_first {
-			match (a.is_dir, b.is_dir) {
-				(true, false) => std::cmp::Ordering::Less,
-				(false, true) => std::cmp::Ordering::Greater,
-				_ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-			}
-		} else {
-			a.name.to_lowercase().cmp(&b.name.to_lowercase())
+	escaped
+}
+
+/// Escape the characters that are special in HTML text and attribute values
+fn html_escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
 		}
-	});
+	}
+	escaped
+}
+
+/// Print entries as a JSON array of objects
+fn print_json(out: &mut impl Write, entries: &[FileEntry]) -> io::Result<()> {
+	writeln!(out, "[")?;
+	for (index, entry) in entries.iter().enumerate() {
+		let separator = if index + 1 < entries.len() { "," } else { "" };
+		writeln!(out, "  {}{}", json_object(entry), separator)?;
+	}
+	writeln!(out, "]")?;
+	Ok(())
+}
+
+/// Serialize one entry as a single-line JSON object
+fn json_object(entry: &FileEntry) -> String {
+	let fields: Vec<String> = entry_fields(entry)
+		.into_iter()
+		.map(|(key, value)| {
+			let value = match value {
+				FieldValue::Text(text) => json_string(&text),
+				FieldValue::Number(number) => number.to_string(),
+				FieldValue::Null => "null".to_string(),
+			};
+			format!("\"{}\": {}", key, value)
+		})
+		.collect();
+	format!("{{{}}}", fields.join(", "))
+}
+
+/// Quote a string for JSON, escaping quotes, backslashes and control characters
+fn json_string(text: &str) -> String {
+	let mut quoted = String::with_capacity(text.len() + 2);
+	quoted.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'\n' => quoted.push_str("\\n"),
+			'\r' => quoted.push_str("\\r"),
+			'\t' => quoted.push_str("\\t"),
+			c if (c as u32) < 0x20 || c == '\u{7f}' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+			c => quoted.push(c),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+/// Print entries as CSV with a header row, quoting fields as RFC 4180 requires
+fn print_csv(out: &mut impl Write, entries: &[FileEntry]) -> io::Result<()> {
+	let Some(first) = entries.first() else { return Ok(()) };
+	let header: Vec<&str> = entry_fields(first).iter().map(|(key, _)| *key).collect();
+	writeln!(out, "{}", header.join(","))?;
 	
-	if file_entries.is_empty() {
-		println!(" Empty directory");
+	for entry in entries {
+		let row: Vec<String> = entry_fields(entry)
+			.into_iter()
+			.map(|(_, value)| match value {
+				FieldValue::Text(text) if text.contains([',', '"', '\n', '\r']) => format!("\"{}\"", text.replace('"', "\"\"")),
+				FieldValue::Text(text) => text,
+				FieldValue::Number(number) => number.to_string(),
+				FieldValue::Null => String::new(),
+			})
+			.collect();
+		writeln!(out, "{}", row.join(","))?;
+	}
+	Ok(())
+}
+
+/// Print entries as a YAML sequence of mappings
+fn print_yaml(out: &mut impl Write, entries: &[FileEntry]) -> io::Result<()> {
+	if entries.is_empty() {
+		writeln!(out, "[]")?;
 		return Ok(());
 	}
+	for entry in entries {
+		for (index, (key, value)) in entry_fields(entry).into_iter().enumerate() {
+			let value = match value {
+				FieldValue::Text(text) => yaml_string(&text),
+				FieldValue::Number(number) => number.to_string(),
+				FieldValue::Null => "null".to_string(),
+			};
+			let prefix = if index == 0 { "- " } else { "  " };
+			writeln!(out, "{}{}: {}", prefix, key, value)?;
+		}
+	}
+	Ok(())
+}
+
+/// Render a string as a YAML scalar, double-quoting it when plain style would change its meaning
+fn yaml_string(text: &str) -> String {
+	let ambiguous = matches!(
+		text.to_lowercase().as_str(),
+		"" | "~" | "null" | "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n"
+	) || text.parse::<f64>().is_ok();
+	let special_start = text.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@` ".contains(c));
+	let needs_quotes = ambiguous
+		|| special_start
+		|| text.ends_with(' ')
+		|| text.ends_with(':')
+		|| text.contains(": ")
+		|| text.contains(" #")
+		|| text.chars().any(|c| c.is_control());
 	
-	// Display header
-	println!(" {} ({} items)", current_dir.display(), file_entries.len());
-	println!();
-	
-	// Calculate column widths for perfect alignment (only if using column format)
-	let max_icon_width: usize = if config.show_icons {
-		file_entries
-			.iter()
-			.map(|entry| UnicodeWidthStr::width(entry.icon))
-			.max()
-			.unwrap_or(0)
-	} else { 0 };
-
-	let (max_perms_len, max_owner_len, max_group_len, max_modified_len) = if config.column_format {
-		(
-			if config.show_permissions { 
-				file_entries.iter().map(|entry| entry.permissions.len()).max().unwrap_or(0) 
-			} else { 0 },
-			if config.show_owner { 
-				file_entries.iter().map(|entry| entry.owner.len()).max().unwrap_or(0) 
-			} else { 0 },
-			if config.show_group { 
-				file_entries.iter().map(|entry| entry.group.len()).max().unwrap_or(0) 
-			} else { 0 },
-			if config.show_modified { 
-				file_entries.iter().map(|entry| entry.modified_text.len()).max().unwrap_or(0) 
-			} else { 0 },
-		)
-	} else {
-		(0, 0, 0, 0)
+	// JSON strings are valid double-quoted YAML scalars
+	if needs_quotes { json_string(text) } else { text.to_string() }
+}
+
+/// Shell integration printed by `--init`: list after every directory change, plus `l` and `ll` aliases
+fn shell_init_script(shell: &str) -> Option<&'static str> {
+	let script = match shell {
+		"bash" => concat!(
+			"# yal integration for bash: eval \"$(yal --init bash)\"\n",
+			"cd() { builtin cd \"$@\" && yal; }\n",
+			"pushd() { builtin pushd \"$@\" && yal; }\n",
+			"popd() { builtin popd \"$@\" && yal; }\n",
+			"alias l='yal'\n",
+			"alias ll='yal --format table'\n",
+		),
+		"zsh" => concat!(
+			"# yal integration for zsh: eval \"$(yal --init zsh)\"\n",
+			"autoload -Uz add-zsh-hook\n",
+			"_yal_chpwd() { yal; }\n",
+			"add-zsh-hook chpwd _yal_chpwd\n",
+			"alias l='yal'\n",
+			"alias ll='yal --format table'\n",
+		),
+		"fish" => concat!(
+			"# yal integration for fish: yal --init fish | source\n",
+			"function __yal_on_pwd --on-variable PWD\n",
+			"    status --is-command-substitution; or yal\n",
+			"end\n",
+			"alias l 'yal'\n",
+			"alias ll 'yal --format table'\n",
+		),
+		_ => return None,
 	};
-	
-	// Display entries according to configuration
-	for entry in &file_entries {
-		println!(
-			"{}",
-			entry.format_display(
-				&config,
-				max_icon_width,
-				max_perms_len,
-				max_owner_len,
-				max_group_len,
-				max_modified_len
-			)
-		);
+	Some(script)
+}
+
+/// Print the absolute path of each config file that is loaded, lowest precedence first
+fn print_config_files() {
+	let files = Config::config_files();
+	if files.is_empty() {
+		println!("no config file found, using defaults");
+	}
+	for path in files {
+		println!("{}", std::path::absolute(&path).unwrap_or(path).display());
 	}
 	
-	Ok(())
-} 
\ No newline at end of file
+	// Environment overrides are applied on top of every file
+	let mut overrides: Vec<String> = env::vars()
+		.map(|(name, _)| name)
+		.filter(|name| name.starts_with("YAL_") && name != CONFIG_PATH_VARIABLE)
+		.collect();
+	if !overrides.is_empty() {
+		overrides.sort();
+		println!("environment overrides: {}", overrides.join(", "));
+	}
+}
+
+/// Where an option's final value came from, for `--explain-config`
+#[derive(Debug, Clone)]
+struct ConfigOrigin {
+	/// The value as the source gave it, in config syntax
+	value: String,
+	/// File path, `env YAL_<KEY>`, `command line` or the mode that last set the option
+	source: String,
+	/// Lower-precedence sources that also set the option, lowest first
+	overridden: Vec<String>,
+}
+
+/// A config file or environment option with an unknown key or an invalid value
+#[derive(Debug, Clone)]
+struct IgnoredOption {
+	/// The option as given, `key = value`
+	setting: String,
+	source: String,
+	/// Whether yal recognized the key, so it was the value that had no effect
+	known: bool,
+}
+
+/// Switches `--no-metadata` turns off, since each needs an entry's metadata
+const NO_METADATA_OFF: [&str; 25] = [
+	"show_permissions", "show_owner", "show_group", "show_owner_group", "show_size", "show_modified", "show_age",
+	"show_links", "show_access", "show_fstype", "show_size_bars", "show_percent", "show_total", "show_summary",
+	"combined_total", "show_acl", "show_capabilities", "show_symlink_depth", "show_sparse", "highlight_empty",
+	"highlight_newer_than_dir", "show_overlay_markers", "recursive_size", "dedup_inodes", "cache",
+];
+
+/// Every option with a fixed default, in config syntax, for `--explain-config`
+///
+/// Options left out default to something worked out at run time (`max_threads`)
+/// or to being unset (`recurse_hidden`, `paths_relative_to`, `theme_file`).
+const CONFIG_DEFAULTS: [(&str, &str); 117] = [
+	("abbreviate_home", "false"),
+	("age_month", "30d"),
+	("age_today", "1d"),
+	("age_week", "7d"),
+	("ambiguous_width", "single"),
+	("auto_hide_uniform_columns", "false"),
+	("block_size", "human"),
+	("cache", "false"),
+	("collation", "ascii"),
+	("color", "auto"),
+	("color_whole_row", "false"),
+	("colorful_permissions", "false"),
+	("column_format", "true"),
+	("column_order", "icon,permissions,access,links,size,size_bar,percent,owner,group,owner_group,fstype,modified,age,name"),
+	("combined_total", "false"),
+	("date_format", "standard"),
+	("dedup_inodes", "false"),
+	("dereference", "false"),
+	("dotfile_position", "mixed"),
+	("filter", ""),
+	("fit_columns", "false"),
+	("future_time_text", "in {}"),
+	("fuzzy_fixed_width", "false"),
+	("fuzzy_fractional", "false"),
+	("group_by", "none"),
+	("group_file", "/etc/group"),
+	("group_related", "false"),
+	("hashed_name_colors", "false"),
+	("header_blank_line", "true"),
+	("heatmap", "off"),
+	("hide_own_group", "false"),
+	("hide_own_owner", "false"),
+	("highlight_empty", "false"),
+	("highlight_newer_than_dir", "false"),
+	("hyperlink_scheme", "file://{path}"),
+	("hyperlinks", "false"),
+	("icon_attached", "false"),
+	("ignore", ""),
+	("info_fields", "size,time"),
+	("info_separator", "·"),
+	("invert_filter", "false"),
+	("large_listing", "off"),
+	("large_listing_threshold", "1000"),
+	("legend", "false"),
+	("long_format", "false"),
+	("max_entries", "0"),
+	("max_name_width", "0"),
+	("name_column_width", "0"),
+	("no_metadata", "false"),
+	("number_format", "none"),
+	("output_format", "columns"),
+	("passwd_file", "/etc/passwd"),
+	("permission_color_execute", "32"),
+	("permission_color_read", "33"),
+	("permission_color_write", "31"),
+	("permission_style", "octal"),
+	("physical", "false"),
+	("pin_names", ""),
+	("quiet", "false"),
+	("recursive", "false"),
+	("recursive_size", "false"),
+	("reverse", "false"),
+	("reverse_keeps_dirs_first", "true"),
+	("row_color_directory", "48;5;17"),
+	("row_color_executable", "48;5;22"),
+	("row_color_file", "48;5;235"),
+	("row_color_symlink", "48;5;23"),
+	("show_access", "false"),
+	("show_acl", "false"),
+	("show_age", "false"),
+	("show_capabilities", "false"),
+	("show_control_chars", "false"),
+	("show_dot_entries", "false"),
+	("show_filtered_count", "false"),
+	("show_fstype", "false"),
+	("show_group", "true"),
+	("show_hidden", "false"),
+	("show_icons", "true"),
+	("show_links", "false"),
+	("show_modified", "true"),
+	("show_overlay_markers", "false"),
+	("show_owner", "true"),
+	("show_owner_group", "false"),
+	("show_percent", "false"),
+	("show_permissions", "true"),
+	("show_size", "false"),
+	("show_size_bars", "false"),
+	("show_sparse", "false"),
+	("show_summary", "false"),
+	("show_symlink_depth", "false"),
+	("show_total", "false"),
+	("since_last", "off"),
+	("size_bar_width", "10"),
+	("size_precision", "1"),
+	("size_rounding", "round"),
+	("size_suffixes", "B,K,M,G,T,P,E"),
+	("size_tiebreak", "name"),
+	("size_units", "iec"),
+	("skip_special", "false"),
+	("smart_defaults", "false"),
+	("sort", "name"),
+	("sort_dirs_first", "true"),
+	("sort_ignore_leading", ""),
+	("stable", "false"),
+	("stream_threshold", "500000"),
+	("theme", "dark"),
+	("time_format", ""),
+	("time_language", "en"),
+	("traversal", "depth-first"),
+	("tree_dirs_only", "false"),
+	("tree_follow_symlinks", "0"),
+	("tree_max_prefix_width", "40"),
+	("truncate_group", "0"),
+	("truncate_owner", "0"),
+	("unknown_time_text", "unknown"),
+	("use_fuzzy_time", "true"),
+	("verbose", "false"),
+];
+
+/// Print every option with its final value and the source that set it, as an aligned table
+///
+/// Options no source set are marked `default`; options that changed nothing,
+/// because the key is unknown or the value invalid, follow the table.
+fn print_config_origins(config: &Config) {
+	let mut options: Vec<(&str, &str, Option<&ConfigOrigin>)> = CONFIG_DEFAULTS.iter()
+		.filter(|(key, _)| !config.origins.contains_key(*key))
+		.map(|&(key, default)| (key, default, None))
+		.chain(config.origins.iter().map(|(key, origin)| (key.as_str(), origin.value.as_str(), Some(origin))))
+		.collect();
+	options.sort_by_key(|&(key, _, _)| key);
+	let settings: Vec<String> = options.iter().map(|(key, value, _)| format!("{} = {}", key, value)).collect();
+	// Long lists are left unpadded so they don't push every source far right
+	let width = settings.iter().map(|setting| display_width(setting)).filter(|&width| width <= 48).max().unwrap_or(0);
+	for ((_, _, origin), setting) in options.iter().zip(&settings) {
+		let line = match origin {
+			Some(origin) if origin.overridden.is_empty() => format!("{}  (from {})", pad_to_display_width(setting, width), origin.source),
+			Some(origin) => format!(
+				"{}  (from {}), overriding {}",
+				pad_to_display_width(setting, width), origin.source, origin.overridden.join(", ")
+			),
+			None => format!("{}  (default)", pad_to_display_width(setting, width)),
+		};
+		println!("{}", line);
+	}
+	
+	if !config.ignored_options.is_empty() {
+		println!();
+		println!("ignored:");
+	}
+	for ignored in &config.ignored_options {
+		let reason = if ignored.known { "invalid value" } else { "unknown key" };
+		println!("  {}  (from {}) [{}]", ignored.setting, ignored.source, reason);
+	}
+}
+
+/// Print command line usage information
+fn print_usage() {
+	println!("Usage: yal [OPTIONS] [DIRECTORY]...");
+	println!();
+	println!("Options:");
+	println!("  --format <FORMAT>   Output layout: columns, simple, table, grid, json, jsonl, csv, yaml, html or shell");
+	println!("  --html              Print HTML tables for reports (same as --format=html)");
+	println!("  --shell-quote       Print one shell-quoted name per line (same as --format=shell)");
+	println!("  --fit               Drop low-priority columns until the listing fits the terminal");
+	println!("  --deduplicate-columns");
+	println!("                      Hide columns with the same value for every entry, noting it below");
+	println!("  --reflow            Wrap names into balanced columns (same as --format=grid)");
+	println!("  --max-entries <N>   Show at most N entries after sorting (0 for no limit)");
+	println!("  --sort <KEY>        Sort by name, natural, size, time, accessed, owner, group, extension or none");
+	println!("  --group-related     With --sort=extension, keep sidecars like a.jpg.thumb next to a.jpg");
+	println!("  --max-name-width <N|auto>");
+	println!("                      Cut names to N cells, or just enough to fit the terminal");
+	println!("  -a, --all           Show hidden files plus . and .. (like ls -a)");
+	println!("  -A, --almost-all    Show hidden files without . and ..");
+	println!("  --no-sort           Keep directory order (same as --sort=none)");
+	println!("  --stream            Print entries as they are read, unsorted and unaligned");
+	println!("  --output <FILE>     Write the listing to FILE, headers to stderr (colors off unless forced)");
+	println!("  --stream-threshold <N>");
+	println!("                      Stream directories with more than N entries (0 for no limit)");
+	println!("  --group-by <KEY>    Split the listing into sections by extension, owner, type or age");
+	println!("  --size-tiebreak <KEY>");
+	println!("                      Order equal sizes by name (default), mtime or type");
+	println!("  --collation <MODE>  Compare names as ascii, natural or unicode");
+	println!("  -r, --reverse       Reverse the sort order");
+	println!("  --biggest <N>       Show the N largest files (--sort=size --reverse --max-entries N)");
+	println!("  --newest <N>        Show the N newest files (--sort=time --reverse --max-entries N)");
+	println!("  --touch-order       Sort oldest first, the order make sees timestamps in (--sort=time)");
+	println!("  --target <FILE>     Mark files newer than FILE, which make would rebuild it for");
+	println!("  -R, --recursive     List subdirectories recursively");
+	println!("  --depth-first       Finish each subdirectory before its siblings (default)");
+	println!("  --breadth-first     List each level completely before descending");
+	println!("  --block-size <SIZE> Show sizes as whole units of SIZE (1K, 1M, 4096) or human");
+	println!("  --si                Show human-readable sizes in powers of 1000 (1K = 1000 bytes)");
+	println!("  --iec               Show human-readable sizes in powers of 1024 (default)");
+	println!("  --total             Print ls-style 'total N' allocated blocks above the entries");
+	println!("  --summary           Print counts per file type and the total size after the entries");
+	println!("  --stable            Sort byte-wise in English wording for identical output on every host");
+	println!("  --no-metadata       List names and types only, without reading each entry's metadata");
+	println!("  --combined-total    Print one total of entries and size across all listed directories");
+	println!("  --compare-times     Mark entries modified after their directory last changed");
+	println!("  --since-last        Mark entries modified since yal last listed the directory");
+	println!("  --only-since-last   List only entries modified since yal last listed the directory");
+	println!("  --filtered-count    Note how many entries were hidden or filtered out in the header");
+	println!("  --no-owner          Hide the owner column for this run");
+	println!("  --no-group          Hide the group column for this run");
+	println!("  --tree              Show the directory tree with connecting lines");
+	println!("  --dirs-tree         Show only the directory structure as a tree");
+	println!("  --dirs-sizes        List subdirectories by recursive size, largest first");
+	println!("  --max-depth <N>     Descend at most N levels in tree mode");
+	println!("  --flat              List every file in the tree as one globally sorted list");
+	println!("  -L, --dereference   Show the metadata of symlink targets instead of the links");
+	println!("  --no-dereference    Show the links' own metadata (default)");
+	println!("  --symlink-depth     Show each link's final target and the hops to reach it");
+	println!("  -P, --physical      Show directories by their real path, resolving symlinks");
+	println!("  --logical           Show directories by the path they were reached through (default)");
+	println!("  --[no-]recurse-into-hidden");
+	println!("                      Descend into hidden directories (default: follow show_hidden)");
+	println!("  --audit             Flag world-writable, setuid/setgid and unowned entries");
+	println!("  --cache             Reuse cached metadata while a directory's mtime is unchanged");
+	println!("  --threads <N>       Stat entries on at most N threads (default: number of CPUs)");
+	println!("  --stats             Print time spent per phase to stderr after the listing");
+	println!("  --diff              Compare two directories (yal --diff OLD NEW)");
+	println!("  --dedup-inodes      Show hard links to the same file once, listing the other names");
+	println!("  --recursive-size    Show directory sizes as the total of their contents (like du)");
+	println!("  --size-bars         Show a bar of each size relative to the largest entry");
+	println!("  --percent           Show each entry's share of the listing's total size");
+	println!("  -q, --quiet         Suppress the progress indicator on stderr");
+	println!("  -v, --verbose       Report entries that vanished or couldn't be read while listing");
+	println!("  --heatmap <MODE>    Color the modified (time) or size (size) column on a gradient");
+	println!("  --legend            Print a color key for the active heatmap after the listing");
+	println!("  --color <WHEN>      Color output: auto (only on a terminal, default), always or never");
+	println!("  --force-color       Keep colors when redirected (same as --color=always)");
+	println!("  --theme <THEME>     Colors for a dark or light background, auto to detect it, or a theme name");
+	println!("  --time-format <FMT> Show exact modification times, e.g. '%Y-%m-%d %H:%M'");
+	println!("  --ambiguous-width <WIDTH>");
+	println!("                      Count ambiguous-width characters as single or double for alignment");
+	println!("  --hyperlink         Make names clickable links (OSC 8) when colors are on");
+	println!("  --hyperlink-scheme <TEMPLATE>");
+	println!("                      Link to TEMPLATE with {{path}} filled in, e.g. 'vscode://file{{path}}'");
+	println!("  --complete          Print bare names for shell completion (dirs end in /)");
+	println!("  --prefix <TEXT>     Only list names starting with TEXT");
+	println!("  --filter <GLOB>     Only list files matching GLOB (repeatable; directories always show)");
+	println!("  --invert            List the files that don't match --filter instead");
+	println!("  -I, --ignore <GLOB> Never list or descend into entries matching GLOB (repeatable)");
+	println!("  --skip-special      Leave out FIFOs, sockets and block/character devices");
+	println!("  --select            Pick entries interactively and print their paths");
+	println!("  --newer-than-file <FILE>");
+	println!("                      Only list entries modified after FILE (like find -newer)");
+	println!("  --batch-file <FILE> Also list each directory named in FILE (- for stdin)");
+	println!("  --paths-relative-to <DIR>");
+	println!("                      Show names as paths relative to DIR");
+	println!("  --passwd-file <FILE>");
+	println!("                      Resolve owner names from FILE instead of /etc/passwd");
+	println!("  --group-file <FILE> Resolve group names from FILE instead of /etc/group");
+	println!("  --resolve-ids-from <ROOT>");
+	println!("                      Resolve names from ROOT/etc/passwd and ROOT/etc/group");
+	println!("  --abbreviate-home   Show paths under $HOME as ~ in headers and path names");
+	println!("  --expand-home       Show full home paths (overrides abbreviate_home)");
+	println!("  --which-config      Print the config files that are loaded and exit");
+	println!("  --json-schema       Print a JSON Schema of the json and jsonl output and exit");
+	println!("  --explain-config    Print each config setting with the source that set it and exit");
+	println!("  --init <SHELL>      Print shell integration for bash, zsh or fish and exit");
+	println!("  -h, --help          Show this help and exit");
+}
+
+/// User database read by default, overridden by `passwd_file`
+const PASSWD_PATH: &str = "/etc/passwd";
+
+/// Group database read by default, overridden by `group_file`
+const GROUP_PATH: &str = "/etc/group";
+
+/// Cache for user and group name lookups
+struct NameCache {
+	users: HashMap<u32, String>,
+	groups: HashMap<u32, String>,
+}
+
+impl NameCache {
+	/// Create a new NameCache from passwd- and group-format files, usually /etc/passwd and /etc/group
+	///
+	/// IDs missing from a file, or all of them when it can't be read, show as numbers.
+	fn new(passwd_file: &Path, group_file: &Path) -> Self {
+		NameCache {
+			users: Self::read_names(passwd_file, PASSWD_PATH),
+			groups: Self::read_names(group_file, GROUP_PATH),
+		}
+	}
+	
+	/// Read `name:password:id:...` lines into a map from ID to name
+	///
+	/// Only a file other than the system `default` is worth a warning when it can't be read.
+	fn read_names(path: &Path, default: &str) -> HashMap<u32, String> {
+		let mut names = HashMap::new();
+		let file = match fs::File::open(path) {
+			Ok(file) => file,
+			Err(error) => {
+				if path != Path::new(default) {
+					eprintln!("yal: {}: {}; showing numeric IDs", path.display(), error);
+				}
+				return names;
+			}
+		};
+		for line in BufReader::new(file).lines().map_while(Result::ok) {
+			let parts: Vec<&str> = line.split(':').collect();
+			if parts.len() >= 3 {
+				if let Ok(id) = parts[2].parse::<u32>() {
+					names.insert(id, parts[0].to_string());
+				}
+			}
+		}
+		names
+	}
+	
+	/// Get user name from UID, fallback to UID string if not found
+	fn get_user_name(&self, uid: u32) -> String {
+		self.users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+	}
+	
+	/// Whether a UID resolves to a known user
+	fn has_user(&self, uid: u32) -> bool {
+		self.users.contains_key(&uid)
+	}
+	
+	/// Whether a GID resolves to a known group
+	fn has_group(&self, gid: u32) -> bool {
+		self.groups.contains_key(&gid)
+	}
+	
+	/// Get group name from GID, fallback to GID string if not found
+	fn get_group_name(&self, gid: u32) -> String {
+		self.groups.get(&gid).cloned().unwrap_or_else(|| gid.to_string())
+	}
+}
+
+/// Maximum display widths of each column, used to align rows
+#[derive(Debug, Default, Clone)]
+struct ColumnWidths {
+	widths: HashMap<String, usize>,
+}
+
+impl ColumnWidths {
+	/// Calculate column widths for perfect alignment across all entries
+	fn compute(entries: &[FileEntry], config: &Config) -> Self {
+		let mut widths = ColumnWidths::default();
+		
+		for column in visible_columns(config) {
+			// Only the icon is padded in simple format; the name is never padded
+			if column == "name" || (column != "icon" && config.output_format != OutputFormat::Columns) {
+				continue;
+			}
+			let width = entries.iter().map(|entry| display_width(&entry.cell(column, config))).max().unwrap_or(0);
+			widths.widths.insert(column.to_string(), width);
+		}
+		
+		widths
+	}
+	
+	/// Width of a column, or zero when the column is not aligned
+	fn get(&self, column: &str) -> usize {
+		self.widths.get(column).copied().unwrap_or(0)
+	}
+}
+
+/// Type of a directory entry, as reported without following symlinks
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileKind {
+	File,
+	Directory,
+	Symlink,
+	Fifo,
+	Socket,
+	CharDevice,
+	BlockDevice,
+}
+
+impl FileKind {
+	/// Classify a file type from `DirEntry::file_type` or `symlink_metadata`
+	fn from_file_type(file_type: fs::FileType) -> Self {
+		if file_type.is_dir() {
+			FileKind::Directory
+		} else if file_type.is_symlink() {
+			FileKind::Symlink
+		} else if file_type.is_fifo() {
+			FileKind::Fifo
+		} else if file_type.is_socket() {
+			FileKind::Socket
+		} else if file_type.is_char_device() {
+			FileKind::CharDevice
+		} else if file_type.is_block_device() {
+			FileKind::BlockDevice
+		} else {
+			FileKind::File
+		}
+	}
+	
+	/// Classify the file type bits of a raw mode
+	fn from_mode(mode: u32) -> Self {
+		match mode & 0o170000 {
+			0o040000 => FileKind::Directory,
+			0o120000 => FileKind::Symlink,
+			0o010000 => FileKind::Fifo,
+			0o140000 => FileKind::Socket,
+			0o020000 => FileKind::CharDevice,
+			0o060000 => FileKind::BlockDevice,
+			_ => FileKind::File,
+		}
+	}
+	
+	/// Position when ordering by type: directories first, then declaration order
+	fn rank(self) -> usize {
+		if self == FileKind::Directory { 0 } else { self as usize + 1 }
+	}
+	
+	/// Name used for the `type` field of machine-readable output
+	fn name(self) -> &'static str {
+		match self {
+			FileKind::File => "file",
+			FileKind::Directory => "directory",
+			FileKind::Symlink => "symlink",
+			FileKind::Fifo => "fifo",
+			FileKind::Socket => "socket",
+			FileKind::CharDevice => "char_device",
+			FileKind::BlockDevice => "block_device",
+		}
+	}
+	
+	/// Short label for special files, None for regular files, directories and symlinks
+	fn special_label(self) -> Option<&'static str> {
+		match self {
+			FileKind::Fifo => Some("fifo"),
+			FileKind::Socket => Some("socket"),
+			FileKind::CharDevice => Some("char device"),
+			FileKind::BlockDevice => Some("block device"),
+			_ => None,
+		}
+	}
+	
+	/// Whether this is a FIFO, socket or device rather than a file, directory or link
+	fn is_special(self) -> bool {
+		self.special_label().is_some()
+	}
+}
+
+/// Raw metadata an entry is built from, read with a stat call or from the listing cache
+#[derive(Debug, Clone, PartialEq)]
+struct Stat {
+	/// Mode bits, including the file type
+	mode: u32,
+	uid: u32,
+	gid: u32,
+	size: u64,
+	dev: u64,
+	ino: u64,
+	links: u64,
+	/// Allocated 512-byte blocks
+	blocks: u64,
+	modified: Option<SystemTime>,
+	accessed: Option<SystemTime>,
+	/// Whether the entry itself is a symlink, even when the metadata is its target's
+	is_symlink: bool,
+}
+
+impl Stat {
+	/// Take the fields of already-read metadata
+	fn new(metadata: &fs::Metadata, is_symlink: bool) -> Self {
+		Stat {
+			mode: metadata.permissions().mode(),
+			uid: metadata.uid(),
+			gid: metadata.gid(),
+			size: metadata.len(),
+			dev: metadata.dev(),
+			ino: metadata.ino(),
+			links: metadata.nlink(),
+			blocks: metadata.blocks(),
+			modified: metadata.modified().ok(),
+			accessed: metadata.accessed().ok(),
+			is_symlink,
+		}
+	}
+}
+
+/// A directory entry as listed by a `FileSource`, before its metadata is read
+#[derive(Debug, Clone)]
+struct SourceEntry {
+	name: String,
+	path: PathBuf,
+	/// Type reported by the directory listing itself, without a stat
+	kind: Option<FileKind>,
+	ino: u64,
+}
+
+impl SourceEntry {
+	/// Take the fields `read_dir` returns without reading metadata
+	fn of_dir_entry(entry: &fs::DirEntry) -> Self {
+		SourceEntry {
+			name: entry.file_name().to_string_lossy().to_string(),
+			path: entry.path(),
+			kind: entry.file_type().ok().map(FileKind::from_file_type),
+			ino: entry.ino(),
+		}
+	}
+}
+
+/// Where directory listings and their metadata come from
+///
+/// Collection and everything after it (filtering, sorting, formatting) only see
+/// entries through this, so they can run on synthetic entries as well as the disk.
+trait FileSource: Sync {
+	/// Entries of a directory in the order it returns them, read lazily
+	fn read_dir(&self, directory: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<SourceEntry>>>>;
+	
+	/// Metadata of an entry: a symlink's own like `ls -l`, or its target's with `--dereference`
+	fn stat(&self, entry: &SourceEntry, config: &Config) -> io::Result<Stat>;
+}
+
+/// The real filesystem
+struct RealFiles;
+
+impl FileSource for RealFiles {
+	fn read_dir(&self, directory: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<SourceEntry>>>> {
+		let entries = fs::read_dir(directory)?;
+		Ok(Box::new(entries.map(|entry| entry.map(|entry| SourceEntry::of_dir_entry(&entry)))))
+	}
+	
+	fn stat(&self, entry: &SourceEntry, config: &Config) -> io::Result<Stat> {
+		// Broken links keep their own metadata under --dereference
+		let metadata = if config.dereference {
+			fs::metadata(&entry.path).or_else(|_| fs::symlink_metadata(&entry.path))?
+		} else {
+			fs::symlink_metadata(&entry.path)?
+		};
+		Ok(Stat::new(&metadata, entry.kind == Some(FileKind::Symlink)))
+	}
+}
+
+/// Represents a file system entry with display information
+#[derive(Debug)]
+struct FileEntry {
+	name: String,
+	path: PathBuf,
+	permissions: String,
+	/// Raw mode bits, including the file type
+	mode: u32,
+	owner: String,
+	group: String,
+	/// Numeric owner and group, before name resolution
+	uid: u32,
+	gid: u32,
+	size: u64,
+	/// Device and inode, identifying hard links to the same file
+	dev: u64,
+	ino: u64,
+	/// Hard link count
+	links: u64,
+	/// Allocated 512-byte blocks
+	blocks: u64,
+	/// What the current user may do with the entry, as `rwx`-style text
+	access: String,
+	/// Filesystem type a directory lives on, when `show_fstype` is set
+	fstype: Option<String>,
+	/// Size relative to the largest entry in the listing, drawn as a bar
+	size_bar: String,
+	/// Share of the listing's total size, like `34%`, or `-` for directories without `recursive_size`
+	percent: String,
+	/// Other names for the same inode, collected by `--dedup-inodes`
+	aliases: Vec<String>,
+	modified: Option<SystemTime>,
+	/// Last access time, used by `--sort=accessed`
+	accessed: Option<SystemTime>,
+	modified_text: String,
+	time_anomaly: Option<TimeAnomaly>,
+	age_secs: Option<u64>,
+	acl_summary: Option<String>,
+	/// Decoded file capabilities, empty when the xattr is present but unreadable
+	capabilities: Option<String>,
+	audit_flags: Vec<AuditFlag>,
+	icon: Arc<str>,
+	kind: FileKind,
+	/// Where a symlink points, read only on pseudo filesystems like /proc
+	symlink_target: Option<String>,
+	/// Where a chain of symlinks ends, when `show_symlink_depth` is set
+	symlink_chain: Option<SymlinkChain>,
+	is_dir: bool,
+	is_symlink: bool,
+	/// Empty regular file or directory with no entries, when `highlight_empty` is set
+	is_empty: bool,
+	/// Modified after its directory last changed, when `highlight_newer_than_dir` is set
+	newer_than_dir: bool,
+	/// Newer than `rebuild_target`, so `make` would rebuild the target
+	triggers_rebuild: bool,
+	/// Modified since yal last listed the directory, under `since_last`
+	changed_since_last: bool,
+	/// Overlayfs whiteout or opaque directory, when `show_overlay_markers` is set
+	overlay_marker: Option<OverlayMarker>,
+}
+
+/// Special meaning an entry has in an overlayfs layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OverlayMarker {
+	/// Character device 0/0, hiding a deleted file of the lower layers
+	Whiteout,
+	/// Directory whose lower-layer contents are hidden, marked by an `overlay.opaque` xattr
+	Opaque,
+}
+
+impl OverlayMarker {
+	/// Detect a marker from an entry's type, reading its device number or xattrs only when it could be one
+	///
+	/// Whiteouts and opaque markers are only visible in the layer directories
+	/// themselves (e.g. under /var/lib/docker), which live on ordinary filesystems.
+	fn detect(path: &Path, kind: FileKind) -> Option<Self> {
+		match kind {
+			FileKind::CharDevice => fs::symlink_metadata(path).ok()
+				.filter(|metadata| metadata.rdev() == 0)
+				.map(|_| OverlayMarker::Whiteout),
+			// Mounts with `userxattr` (rootless containers) use the user namespace
+			FileKind::Directory => ["trusted.overlay.opaque", "user.overlay.opaque"].iter()
+				.any(|name| read_xattr(path, name).as_deref() == Some(b"y"))
+				.then_some(OverlayMarker::Opaque),
+			_ => None,
+		}
+	}
+}
+
+impl FileEntry {
+	/// Create a new FileEntry from a directory entry, reading its metadata from `source`
+	fn new(entry: &SourceEntry, source: &dyn FileSource, name_cache: &NameCache, icons: &IconResolver, config: &Config, pseudo_fs: bool) -> std::io::Result<Self> {
+		if config.no_metadata {
+			return Ok(Self::names_only(entry, icons));
+		}
+		let stat = source.stat(entry, config)?;
+		Ok(Self::from_stat(entry.name.clone(), entry.path.clone(), &stat, name_cache, icons, config, pseudo_fs))
+	}
+	
+	/// Synthetic `.` or `..` entry for `--all`, showing the metadata of the directory it names
+	fn dot_entry(name: &str, path: PathBuf, name_cache: &NameCache, icons: &IconResolver, config: &Config) -> std::io::Result<Self> {
+		let stat = Stat::new(&fs::metadata(&path)?, false);
+		Ok(Self::from_stat(name.to_string(), path, &stat, name_cache, icons, config, false))
+	}
+	
+	/// Build an entry from already-read metadata
+	fn from_stat(file_name: String, path: PathBuf, stat: &Stat, name_cache: &NameCache, icons: &IconResolver, config: &Config, pseudo_fs: bool) -> Self {
+		// Get permissions in the configured format
+		let mode = stat.mode;
+		let mut permissions = match config.permission_style {
+			PermissionStyle::Octal => format!("{:o}", mode & 0o777),
+			PermissionStyle::Symbolic => format_symbolic_permissions(mode),
+			PermissionStyle::Both => {
+				// Drop the file type character so both forms describe the same bits
+				let symbolic = format_symbolic_permissions(mode);
+				format!("{:04o} {}", mode & 0o7777, &symbolic[1..])
+			},
+		};
+		
+		// Mark entries carrying extended ACLs with a trailing '+', like `ls -l`
+		let acl = if config.show_acl && !pseudo_fs { read_acl(&path) } else { None };
+		if acl.is_some() {
+			permissions.push('+');
+		}
+		let acl_summary = acl
+			.filter(|entries| config.long_format && !entries.is_empty())
+			.map(|entries| summarize_acl(&entries, name_cache));
+		
+		// Get owner and group IDs and resolve to names
+		let owner_uid = stat.uid;
+		let group_gid = stat.gid;
+		let mut owner = name_cache.get_user_name(owner_uid);
+		let mut group = name_cache.get_group_name(group_gid);
+		
+		// Cap long names before widths are computed so alignment uses the truncated text
+		if let Some(max_width) = config.truncate_owner {
+			owner = truncate_to_display_width(&owner, max_width);
+		}
+		if let Some(max_width) = config.truncate_group {
+			group = truncate_to_display_width(&group, max_width);
+		}
+		
+		// Get modification time and format according to config
+		let modified = stat.modified;
+		let detailed = config.long_format && config.date_format == DateFormat::Detailed;
+		let time = modified
+			.ok_or(TimeAnomaly::Unknown)
+			.and_then(|modified_time| match &config.time_format {
+				_ if detailed => format_duration_since(modified_time, config.relative_time_style(true)),
+				Some(format) => format_timestamp(modified_time, format).ok_or(TimeAnomaly::Unknown),
+				None => format_duration_since(modified_time, config.relative_time_style(config.use_fuzzy_time)),
+			});
+		let (mut modified_text, time_anomaly) = match time {
+			Ok(text) => (text, None),
+			Err(anomaly) => (anomaly.text(config), Some(anomaly)),
+		};
+		// Future times keep their exact part too, since that's what explains them
+		let exact = modified.filter(|_| detailed)
+			.and_then(|modified_time| format_timestamp(modified_time, config.time_format.as_deref().unwrap_or(DETAILED_TIME_FORMAT)));
+		if let Some(exact) = exact {
+			modified_text = format!("{} ({})", modified_text, exact);
+		}
+		let age_secs = modified
+			.and_then(|modified_time| SystemTime::now().duration_since(modified_time).ok())
+			.map(|duration| duration.as_secs());
+		
+		let kind = FileKind::from_mode(mode);
+		let is_dir = kind == FileKind::Directory;
+		let capabilities = if config.show_capabilities && kind == FileKind::File && !pseudo_fs { read_capabilities(&path) } else { None };
+		let is_symlink = stat.is_symlink;
+		let icon = icons.resolve(&file_name, is_dir);
+		
+		// Links in /proc (e.g. /proc/<pid>/fd) are only meaningful through their targets
+		let symlink_target = if pseudo_fs && is_symlink {
+			fs::read_link(&path).ok().map(|target| target.display().to_string())
+		} else {
+			None
+		};
+		let symlink_chain = (config.show_symlink_depth && is_symlink).then(|| follow_symlink_chain(&path));
+		
+		// Pseudo files report size 0 whatever they hold; unreadable directories don't count as empty
+		let is_empty = config.highlight_empty && !pseudo_fs && match kind {
+			FileKind::File => stat.size == 0,
+			FileKind::Directory => fs::read_dir(&path).is_ok_and(|mut entries| entries.next().is_none()),
+			_ => false,
+		};
+		
+		let overlay_marker = if config.show_overlay_markers && !pseudo_fs { OverlayMarker::detect(&path, kind) } else { None };
+		
+		// Flag security-relevant permissions when auditing
+		let audit_flags = if config.audit {
+			audit_entry(mode, is_dir, name_cache.has_user(owner_uid) && name_cache.has_group(group_gid))
+		} else {
+			Vec::new()
+		};
+		
+		FileEntry {
+			name: file_name,
+			path,
+			permissions,
+			mode,
+			owner,
+			group,
+			uid: owner_uid,
+			gid: group_gid,
+			size: stat.size,
+			dev: stat.dev,
+			ino: stat.ino,
+			links: stat.links,
+			blocks: stat.blocks,
+			access: effective_access(mode, owner_uid, group_gid),
+			size_bar: String::new(),
+			percent: String::new(),
+			fstype: if config.show_fstype && is_dir {
+				device_fstypes().and_then(|fstypes| fstypes.get(&stat.dev).cloned())
+			} else {
+				None
+			},
+			aliases: Vec::new(),
+			modified,
+			accessed: stat.accessed,
+			modified_text,
+			time_anomaly,
+			age_secs,
+			acl_summary,
+			capabilities,
+			audit_flags,
+			icon,
+			kind,
+			symlink_target,
+			symlink_chain,
+			is_dir,
+			is_symlink,
+			is_empty,
+			newer_than_dir: false,
+			triggers_rebuild: false,
+			changed_since_last: false,
+			overlay_marker,
+		}
+	}
+	
+	/// Placeholder for an entry whose metadata can't be read, shown instead of skipping it
+	fn unreadable(entry: &SourceEntry, icons: &IconResolver, config: &Config) -> Self {
+		let kind = entry.kind.unwrap_or(FileKind::File);
+		let is_dir = kind == FileKind::Directory;
+		FileEntry {
+			icon: icons.resolve(&entry.name, is_dir),
+			name: entry.name.clone(),
+			path: entry.path.clone(),
+			permissions: "?".to_string(),
+			mode: 0,
+			owner: "?".to_string(),
+			group: "?".to_string(),
+			uid: u32::MAX,
+			gid: u32::MAX,
+			size: 0,
+			dev: 0,
+			ino: entry.ino,
+			links: 0,
+			blocks: 0,
+			access: "???".to_string(),
+			size_bar: String::new(),
+			percent: String::new(),
+			fstype: None,
+			aliases: Vec::new(),
+			modified: None,
+			accessed: None,
+			modified_text: config.unknown_time_text.clone(),
+			time_anomaly: Some(TimeAnomaly::Unknown),
+			age_secs: None,
+			acl_summary: None,
+			capabilities: None,
+			audit_flags: Vec::new(),
+			kind,
+			symlink_target: None,
+			symlink_chain: None,
+			is_dir,
+			is_symlink: kind == FileKind::Symlink,
+			is_empty: false,
+			newer_than_dir: false,
+			triggers_rebuild: false,
+			changed_since_last: false,
+			overlay_marker: None,
+		}
+	}
+	
+	/// Entry with only the name and type the directory listing gave, for `--no-metadata`
+	fn names_only(entry: &SourceEntry, icons: &IconResolver) -> Self {
+		let kind = entry.kind.unwrap_or(FileKind::File);
+		let is_dir = kind == FileKind::Directory;
+		FileEntry {
+			icon: icons.resolve(&entry.name, is_dir),
+			name: entry.name.clone(),
+			path: entry.path.clone(),
+			permissions: String::new(),
+			mode: 0,
+			owner: String::new(),
+			group: String::new(),
+			uid: u32::MAX,
+			gid: u32::MAX,
+			size: 0,
+			dev: 0,
+			ino: entry.ino,
+			links: 0,
+			blocks: 0,
+			access: String::new(),
+			size_bar: String::new(),
+			percent: String::new(),
+			fstype: None,
+			aliases: Vec::new(),
+			modified: None,
+			accessed: None,
+			modified_text: String::new(),
+			time_anomaly: None,
+			age_secs: None,
+			acl_summary: None,
+			capabilities: None,
+			audit_flags: Vec::new(),
+			kind,
+			symlink_target: None,
+			symlink_chain: None,
+			is_dir,
+			is_symlink: kind == FileKind::Symlink,
+			is_empty: false,
+			newer_than_dir: false,
+			triggers_rebuild: false,
+			changed_since_last: false,
+			overlay_marker: None,
+		}
+	}
+	
+	/// Format this entry for display with proper column alignment
+	fn format_display(&self, config: &Config, widths: &ColumnWidths) -> String {
+		let line = if config.output_format == OutputFormat::Columns {
+			// Column format with alignment
+			self.format_columns(config, widths)
+		} else {
+			// Simple list format
+			self.format_simple(config, widths.get("icon"))
+		};
+		self.tint_row(line, config)
+	}
+	
+	/// Wrap a formatted row in its file type's row color when `color_whole_row` is on
+	fn tint_row(&self, line: String, config: &Config) -> String {
+		let [directory, file, symlink, executable] = &config.row_colors;
+		let code = match self.kind {
+			FileKind::Directory => directory,
+			FileKind::Symlink => symlink,
+			FileKind::File if self.mode & 0o111 != 0 => executable,
+			_ => file,
+		};
+		if !config.color_whole_row || code.is_empty() {
+			return line;
+		}
+		// Every cell ends in a reset, so restore the tint after each one and fill to the line end
+		let tint = format!("\x1b[{}m", code);
+		format!("{}{}\x1b[K\x1b[0m", tint, line.replace("\x1b[0m", &format!("\x1b[0m{}", tint)))
+	}
+	
+	/// Format entry in column layout
+	fn format_columns(&self, config: &Config, widths: &ColumnWidths) -> String {
+		visible_columns(config)
+			.into_iter()
+			.map(|column| {
+				let cell = self.cell(column, config);
+				match column {
+					"name" => cell, // Last column, no trailing padding
+					"size" | "links" | "percent" => pad_left_to_display_width(&cell, widths.get(column)),
+					_ => pad_to_display_width(&cell, widths.get(column)),
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(" ")
+	}
+	
+	/// Format entry in simple list layout
+	fn format_simple(&self, config: &Config, max_icon_width: usize) -> String {
+		self.cells(config, max_icon_width).join(" ")
+	}
+	
+	/// Build the unpadded, colored cell for each visible column in configured order
+	fn cells(&self, config: &Config, max_icon_width: usize) -> Vec<String> {
+		visible_columns(config)
+			.into_iter()
+			.map(|column| match column {
+				"icon" => pad_to_display_width(&self.icon, max_icon_width),
+				_ => self.cell(column, config),
+			})
+			.collect()
+	}
+	
+	/// Build the colored, unpadded text of a single column
+	fn cell(&self, column: &str, config: &Config) -> String {
+		match column {
+			"icon" => self.icon.to_string(),
+			"permissions" if config.colorful_permissions && config.permission_style != PermissionStyle::Octal => {
+				colorize_permissions(&self.permissions, config)
+			},
+			"permissions" => format!("{}{}\x1b[0m", palette().permissions, self.permissions),
+			"links" => config.format_number(self.links),
+			"size_bar" => format!("{}{}\x1b[0m", palette().accent, self.size_bar),
+			"percent" => format!("{}{}\x1b[0m", palette().size, self.percent),
+			"fstype" => format!("{}{}\x1b[0m", palette().accent, self.fstype.as_deref().unwrap_or("")),
+			"access" => self.access
+				.chars()
+				.map(|c| match c {
+					'-' => format!("{}-\x1b[0m", palette().muted),
+					_ => format!("\x1b[1m{}\x1b[0m", c),
+				})
+				.collect(),
+			"size" if config.show_sparse && self.is_sparse() => {
+				// Trailing marker so sparse files stand out even without color
+				format!("{}\x1b[1m{}~\x1b[0m", palette().accent, config.format_bytes(self.size))
+			},
+			"size" => {
+				let color = if config.heatmap == Heatmap::Size { size_to_color(self.size) } else { palette().size };
+				format!("{}{}\x1b[0m", color, config.format_bytes(self.size))
+			},
+			// Your own files are the common case, so only other owners are shown when hiding
+			"owner" if config.hide_own_owner && self.uid == process_credentials().uid => String::new(),
+			"group" if config.hide_own_group && self.gid == process_credentials().gid => String::new(),
+			"owner" => format!("{}{}\x1b[0m", owner_color(&self.owner, config), self.owner),
+			"group" => format!("{}{}\x1b[0m", group_color(&self.group, config), self.group),
+			// Honor --no-owner / --no-group and hidden own names within the combined column
+			"owner_group" => match (config.show_owner, config.show_group) {
+				(true, false) => self.cell("owner", config),
+				(false, true) => self.cell("group", config),
+				_ => {
+					let parts = [self.cell("owner", config), self.cell("group", config)];
+					parts.iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<_>>().join(":")
+				},
+			},
+			"modified" => {
+				let color = match (self.time_anomaly, self.age_secs) {
+					(Some(anomaly), _) => anomaly.color(),
+					(None, Some(secs)) if config.heatmap == Heatmap::Time => age_to_color(secs),
+					_ => palette().modified,
+				};
+				format!("{}{}\x1b[0m", color, self.modified_text)
+			},
+			"age" => {
+				let label = self.age_label(config);
+				format!("{}{}\x1b[0m", age_color(label), label)
+			},
+			"info" => self.info_cell(config),
+			"name" => self.name_cell(config),
+			_ => String::new(),
+		}
+	}
+	
+	/// Several fields packed into one cell for narrow terminals, like `4.2K·3h`
+	///
+	/// Each field keeps its own column's color; `time` is the compact relative
+	/// time whatever `relative_time_style` says, since it is the densest form.
+	fn info_cell(&self, config: &Config) -> String {
+		let separator = format!("{}{}\x1b[0m", palette().muted, config.info_separator);
+		config.info_fields
+			.iter()
+			.map(|field| match field.as_str() {
+				"time" => match (self.time_anomaly, self.age_secs) {
+					(None, Some(secs)) => format!("{}{}\x1b[0m", palette().modified, format_compact_seconds(secs).trim_start()),
+					_ => self.cell("modified", config),
+				},
+				field => self.cell(field, config),
+			})
+			.filter(|part| !part.is_empty())
+			.collect::<Vec<_>>()
+			.join(&separator)
+	}
+	
+	/// Name as displayed: the basename, or the path relative to the configured base
+	fn display_name(&self, config: &Config) -> String {
+		let Some(base) = &config.paths_relative_to else {
+			return self.name.clone();
+		};
+		
+		// Fall back to the absolute path when the entry is not under the base
+		let path = std::path::absolute(&self.path).unwrap_or_else(|_| self.path.clone());
+		match path.strip_prefix(base) {
+			// The base itself, e.g. the `.` entry of the listed directory
+			Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+			Ok(relative) => relative.display().to_string(),
+			Err(_) => display_path(&path, config),
+		}
+	}
+	
+	/// The name `display_name` shows, as the exact bytes on disk, for `--format=shell`
+	fn shell_name(&self, config: &Config) -> PathBuf {
+		let Some(base) = &config.paths_relative_to else {
+			// `.` and `..` have no file name of their own in their paths
+			if matches!(self.name.as_str(), "." | "..") {
+				return PathBuf::from(&self.name);
+			}
+			return self.path.file_name().map(PathBuf::from).unwrap_or_else(|| PathBuf::from(&self.name));
+		};
+		
+		// A path outside the base stays absolute; `~` wouldn't expand inside quotes
+		let path = std::path::absolute(&self.path).unwrap_or_else(|_| self.path.clone());
+		match path.strip_prefix(base) {
+			Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+			Ok(relative) => relative.to_path_buf(),
+			Err(_) => path,
+		}
+	}
+	
+	/// Build the colored name cell, including any trailing annotations
+	fn name_cell(&self, config: &Config) -> String {
+		let mut cell = String::new();
+		if self.is_empty {
+			cell.push_str(&format!(" {}(empty)\x1b[0m", palette().muted));
+		}
+		
+		if !self.audit_flags.is_empty() {
+			let labels: Vec<&str> = self.audit_flags.iter().map(|flag| flag.label()).collect();
+			cell.push_str(&format!(" \x1b[31m[{}]\x1b[0m", labels.join(", ")));
+		}
+		
+		if let Some(chain) = &self.symlink_chain {
+			cell.push_str(&chain.annotation());
+		} else if let Some(target) = &self.symlink_target {
+			cell.push_str(&format!(" {}-> {}\x1b[0m", palette().muted, target));
+		} else if self.overlay_marker == Some(OverlayMarker::Whiteout) {
+			cell.push_str(&format!(" {}[whiteout]\x1b[0m", palette().muted));
+		} else if let Some(label) = self.kind.special_label() {
+			cell.push_str(&format!(" {}[{}]\x1b[0m", palette().muted, label));
+		}
+		
+		if self.overlay_marker == Some(OverlayMarker::Opaque) {
+			cell.push_str(&format!(" {}[opaque]\x1b[0m", palette().muted));
+		}
+		
+		if !self.aliases.is_empty() {
+			cell.push_str(&format!(" {}[= {}]\x1b[0m", palette().muted, self.aliases.join(", ")));
+		}
+		
+		if let Some(summary) = &self.acl_summary {
+			cell.push_str(&format!(" {}[acl: {}]\x1b[0m", palette().muted, summary));
+		}
+		
+		match self.capabilities.as_deref() {
+			Some("") => cell.push_str(" \x1b[31m[caps]\x1b[0m"),
+			Some(capabilities) => cell.push_str(&format!(" \x1b[31m[caps: {}]\x1b[0m", capabilities)),
+			None => {},
+		}
+		
+		if self.changed_since_last {
+			cell.push_str(&format!(" {}[changed]\x1b[0m", palette().accent));
+		}
+		
+		if self.triggers_rebuild {
+			cell.push_str(" \x1b[33;1m[would trigger rebuild]\x1b[0m");
+		}
+		
+		if self.newer_than_dir {
+			cell.push_str(&format!(" {}[newer than dir]\x1b[0m", palette().accent));
+		}
+		
+		if config.show_sparse && config.long_format && self.is_sparse() {
+			let allocated = (self.blocks * 512) as f64 / self.size as f64 * 100.0;
+			cell.push_str(&format!(" {}[sparse: {:.0}% allocated]\x1b[0m", palette().muted, allocated));
+		}
+		
+		let mut display_name = self.display_name(config);
+		let hazardous = config.show_control_chars && has_invisible_chars(&display_name);
+		if hazardous {
+			display_name = visible_chars(&display_name);
+		}
+		
+		// Names give way to the annotations after them when the width is limited
+		let limit = match config.max_name_width {
+			MaxNameWidth::Unlimited => None,
+			MaxNameWidth::Fixed(width) => Some(width),
+			MaxNameWidth::Auto => config.name_budget.map(|budget| budget.saturating_sub(display_width(&cell)).max(NAME_MIN_WIDTH)),
+		};
+		if let Some(limit) = limit {
+			display_name = truncate_to_display_width(&display_name, limit);
+		}
+		
+		// Use ANSI escape codes for colors
+		let (name_color, reset) = if hazardous {
+			("\x1b[33;1;4m", "\x1b[0m") // Bold underlined yellow for names with hidden characters
+		} else if self.overlay_marker == Some(OverlayMarker::Whiteout) {
+			("\x1b[9;90m", "\x1b[0m") // Struck through, since the file is deleted in this layer
+		} else {
+			self.name_color()
+		};
+		let mut name = format!("{}{}{}", name_color, display_name, reset);
+		if config.hyperlinks {
+			name = hyperlink(&self.path, &name, config);
+		}
+		
+		// Only pad when something follows, so plain names get no trailing spaces
+		if cell.is_empty() {
+			name
+		} else {
+			pad_to_display_width(&name, config.name_column_width) + &cell
+		}
+	}
+	
+	/// Whether this is the synthetic `.` or `..` entry added by `--all`
+	fn is_dot_entry(&self) -> bool {
+		matches!(self.name.as_str(), "." | "..")
+	}
+	
+	/// Whether this is a regular file using well under half of its logical size on disk
+	///
+	/// Small files are ignored, since filesystems may store them inline with no blocks.
+	fn is_sparse(&self) -> bool {
+		const MIN_HOLE: u64 = 64 * 1024;
+		let allocated = self.blocks * 512;
+		self.kind == FileKind::File && allocated * 2 < self.size && self.size - allocated >= MIN_HOLE
+	}
+	
+	/// Coarse age label for this entry, or "unknown" when the mtime is unavailable
+	fn age_label(&self, config: &Config) -> &'static str {
+		match self.age_secs {
+			Some(secs) => age_bucket(secs, config),
+			None => "unknown",
+		}
+	}
+	
+	/// Section this entry belongs to under `group_by`, as a sort rank and label
+	///
+	/// Types and ages rank in their natural order; other groups sort by label.
+	fn group_key(&self, config: &Config) -> (usize, String) {
+		match config.group_by {
+			GroupBy::None => (0, String::new()),
+			GroupBy::Extension if self.is_dir => (0, "directories".to_string()),
+			GroupBy::Extension => match Path::new(&self.name).extension() {
+				Some(extension) => (1, format!(".{}", extension.to_string_lossy().to_lowercase())),
+				None => (2, "no extension".to_string()),
+			},
+			GroupBy::Owner => (0, self.owner.clone()),
+			GroupBy::Type => {
+				(self.kind.rank(), self.kind.name().replace('_', " "))
+			},
+			GroupBy::Age => {
+				let label = self.age_label(config);
+				let rank = ["today", "this week", "this month", "older"].iter().position(|&known| known == label).unwrap_or(4);
+				(rank, label.to_string())
+			},
+		}
+	}
+	
+	/// Color used for the name of this entry
+	fn name_color(&self) -> (&'static str, &'static str) {
+		if !self.audit_flags.is_empty() {
+			("\x1b[31;1m", "\x1b[0m") // Red bold for flagged entries
+		} else if self.is_empty {
+			(palette().muted, "\x1b[0m") // Dimmed as cleanup candidates
+		} else if self.is_dir {
+			(palette().directory, "\x1b[0m") // Bold blue for directories
+		} else {
+			("", "") // No color for files
+		}
+	}
+}
+
+/// Most symlinks followed before a chain counts as too long, matching Linux's limit
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// How a chain of symlinks ends, with the number of links followed
+#[derive(Debug, Clone)]
+enum SymlinkChain {
+	/// Reached a file that isn't a link, shown by its real path
+	Resolved(usize, PathBuf),
+	/// A link points at something that doesn't exist
+	Broken(usize, PathBuf),
+	/// A link leads back to one already followed
+	Cycle(usize),
+	/// Still linking after `MAX_SYMLINK_HOPS`
+	TooLong,
+}
+
+impl SymlinkChain {
+	/// Name-cell annotation such as ` ->(3) /nix/store/…/bin/git`
+	fn annotation(&self) -> String {
+		match self {
+			SymlinkChain::Resolved(hops, target) => format!(" {}->({}) {}\x1b[0m", palette().muted, hops, target.display()),
+			SymlinkChain::Broken(hops, target) => format!(" {}->({}) {}\x1b[0m \x1b[31m[broken]\x1b[0m", palette().muted, hops, target.display()),
+			SymlinkChain::Cycle(hops) => format!(" {}->({})\x1b[0m \x1b[31m[cycle]\x1b[0m", palette().muted, hops),
+			SymlinkChain::TooLong => format!(" {}->({}+)\x1b[0m \x1b[31m[too many links]\x1b[0m", palette().muted, MAX_SYMLINK_HOPS),
+		}
+	}
+}
+
+/// Follow a symlink one `read_link` at a time until it reaches a non-link
+///
+/// Links are remembered by device and inode, so a cycle is caught however its
+/// paths are spelled; relative targets resolve against the link's directory.
+fn follow_symlink_chain(path: &Path) -> SymlinkChain {
+	let mut visited = HashSet::new();
+	let mut current = path.to_path_buf();
+	for hops in 0..=MAX_SYMLINK_HOPS {
+		let Ok(metadata) = fs::symlink_metadata(&current) else {
+			return SymlinkChain::Broken(hops, current);
+		};
+		if !metadata.file_type().is_symlink() {
+			return SymlinkChain::Resolved(hops, fs::canonicalize(&current).unwrap_or(current));
+		}
+		if !visited.insert((metadata.dev(), metadata.ino())) {
+			return SymlinkChain::Cycle(hops);
+		}
+		let Ok(target) = fs::read_link(&current) else {
+			return SymlinkChain::Broken(hops, current);
+		};
+		current = match current.parent() {
+			Some(parent) => parent.join(target),
+			None => target,
+		};
+	}
+	SymlinkChain::TooLong
+}
+
+/// Format mode bits as an `ls -l` style string, including the file type character
+fn format_symbolic_permissions(mode: u32) -> String {
+	let file_type = match mode & 0o170000 {
+		0o040000 => 'd',
+		0o120000 => 'l',
+		0o010000 => 'p',
+		0o140000 => 's',
+		0o060000 => 'b',
+		0o020000 => 'c',
+		_ => '-',
+	};
+	
+	// Execute slot shows setuid/setgid/sticky as s/S or t/T depending on the x bit
+	let triplet = |shift: u32, special: bool, special_char: char| {
+		let bits = (mode >> shift) & 0o7;
+		let execute = match (bits & 0o1 != 0, special) {
+			(true, true) => special_char,
+			(false, true) => special_char.to_ascii_uppercase(),
+			(true, false) => 'x',
+			(false, false) => '-',
+		};
+		format!(
+			"{}{}{}",
+			if bits & 0o4 != 0 { 'r' } else { '-' },
+			if bits & 0o2 != 0 { 'w' } else { '-' },
+			execute
+		)
+	};
+	
+	format!(
+		"{}{}{}{}",
+		file_type,
+		triplet(6, mode & 0o4000 != 0, 's'),
+		triplet(3, mode & 0o2000 != 0, 's'),
+		triplet(0, mode & 0o1000 != 0, 't')
+	)
+}
+
+/// Security-relevant condition flagged by `--audit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AuditFlag {
+	/// Writable by everyone (directories only when not sticky)
+	WorldWritable,
+	/// Set-user-ID bit on a file
+	Setuid,
+	/// Set-group-ID bit on a file
+	Setgid,
+	/// Owner or group ID has no matching user or group name
+	NoOwner,
+}
+
+impl AuditFlag {
+	/// Short label shown next to flagged names
+	fn label(&self) -> &'static str {
+		match self {
+			AuditFlag::WorldWritable => "world-writable",
+			AuditFlag::Setuid => "setuid",
+			AuditFlag::Setgid => "setgid",
+			AuditFlag::NoOwner => "no owner",
+		}
+	}
+}
+
+/// Determine which audit flags apply to an entry from its mode bits and ownership
+fn audit_entry(mode: u32, is_dir: bool, owner_known: bool) -> Vec<AuditFlag> {
+	let mut flags = Vec::new();
+	let is_symlink = mode & 0o170000 == 0o120000;
+	
+	// Sticky world-writable directories such as /tmp are the expected setup
+	if !is_symlink && mode & 0o002 != 0 && !(is_dir && mode & 0o1000 != 0) {
+		flags.push(AuditFlag::WorldWritable);
+	}
+	if !is_dir && mode & 0o4000 != 0 {
+		flags.push(AuditFlag::Setuid);
+	}
+	if !is_dir && mode & 0o2000 != 0 {
+		flags.push(AuditFlag::Setgid);
+	}
+	if !owner_known {
+		flags.push(AuditFlag::NoOwner);
+	}
+	
+	flags
+}
+
+/// Running totals of audit findings across every listed directory
+#[derive(Debug, Default)]
+struct AuditSummary {
+	world_writable: usize,
+	setuid: usize,
+	setgid: usize,
+	no_owner: usize,
+}
+
+impl AuditSummary {
+	/// Count the flags of a listed entry
+	fn record(&mut self, flags: &[AuditFlag]) {
+		for flag in flags {
+			match flag {
+				AuditFlag::WorldWritable => self.world_writable += 1,
+				AuditFlag::Setuid => self.setuid += 1,
+				AuditFlag::Setgid => self.setgid += 1,
+				AuditFlag::NoOwner => self.no_owner += 1,
+			}
+		}
+	}
+	
+	/// Print the findings summary
+	fn print(&self) {
+		let total = self.world_writable + self.setuid + self.setgid + self.no_owner;
+		let color = if total > 0 { "\x1b[31;1m" } else { "\x1b[32m" };
+		print_line(&format!(
+			"{}Audit: {} world-writable, {} setuid, {} setgid, {} without owner\x1b[0m",
+			color, self.world_writable, self.setuid, self.setgid, self.no_owner
+		));
+	}
+}
+
+/// Effective user and group IDs of this process, plus its supplementary groups
+struct Credentials {
+	uid: u32,
+	gid: u32,
+	groups: Vec<u32>,
+}
+
+/// Credentials of the running process, looked up once
+fn process_credentials() -> &'static Credentials {
+	static CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+	CREDENTIALS.get_or_init(|| {
+		// SAFETY: getgroups with a zero size only reports the count, then fills a buffer of that size
+		let groups = unsafe {
+			let count = libc::getgroups(0, std::ptr::null_mut());
+			let mut groups = vec![0; count.max(0) as usize];
+			let filled = libc::getgroups(count, groups.as_mut_ptr());
+			groups.truncate(filled.max(0) as usize);
+			groups
+		};
+		Credentials {
+			uid: unsafe { libc::geteuid() },
+			gid: unsafe { libc::getegid() },
+			groups,
+		}
+	})
+}
+
+/// What the current process may do with a file, as `rwx` text with `-` for denied
+///
+/// Follows the kernel's order: the owner class applies to the owner, else the
+/// group class to group members, else the other class. Root may always read and
+/// write, and may execute when any execute bit is set. ACLs are not consulted.
+fn effective_access(mode: u32, owner: u32, group: u32) -> String {
+	let credentials = process_credentials();
+	let bits = if credentials.uid == 0 {
+		let execute = if mode & 0o111 != 0 || mode & 0o170000 == 0o040000 { 0o1 } else { 0 };
+		0o6 | execute
+	} else if credentials.uid == owner {
+		(mode >> 6) & 0o7
+	} else if credentials.gid == group || credentials.groups.contains(&group) {
+		(mode >> 3) & 0o7
+	} else {
+		mode & 0o7
+	};
+	
+	[(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+		.iter()
+		.map(|&(bit, c)| if bits & bit != 0 { c } else { '-' })
+		.collect()
+}
+
+/// Color each character of a symbolic permission string by the kind of bit it shows
+fn colorize_permissions(permissions: &str, config: &Config) -> String {
+	let [read, write, execute] = &config.permission_colors;
+	permissions
+		.chars()
+		.map(|c| {
+			let color = match c {
+				'r' => read.as_str(),
+				'w' => write.as_str(),
+				'x' | 's' | 'S' | 't' | 'T' => execute.as_str(),
+				'-' => "90", // Unset bits fade out
+				_ => "33",   // Type character, octal digits and the ACL marker
+			};
+			format!("\x1b[{}m{}\x1b[0m", color, c)
+		})
+		.collect()
+}
+
+/// A single POSIX ACL entry beyond the basic owner/group/other mode bits
+#[derive(Debug)]
+struct AclEntry {
+	tag: u16,
+	perm: u16,
+	id: u32,
+}
+
+/// POSIX ACL tag for a named user entry
+const ACL_USER: u16 = 0x02;
+/// POSIX ACL tag for a named group entry
+const ACL_GROUP: u16 = 0x08;
+/// POSIX ACL tag for the mask entry
+const ACL_MASK: u16 = 0x10;
+
+/// Read the extended ACL entries of a path, or `None` when it has no extended ACL
+///
+/// Decodes the `system.posix_acl_access` xattr (and `system.posix_acl_default`
+/// for directories). Filesystems or platforms without ACL support yield `None`.
+fn read_acl(path: &Path) -> Option<Vec<AclEntry>> {
+	let mut entries = Vec::new();
+	let mut present = false;
+	
+	for name in ["system.posix_acl_access", "system.posix_acl_default"] {
+		let Some(value) = read_xattr(path, name) else { continue };
+		
+		// 4-byte version header followed by 8-byte (tag, perm, id) records
+		for record in value.get(4..).unwrap_or(&[]).chunks_exact(8) {
+			let tag = u16::from_le_bytes([record[0], record[1]]);
+			let perm = u16::from_le_bytes([record[2], record[3]]);
+			let id = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+			if matches!(tag, ACL_USER | ACL_GROUP | ACL_MASK) {
+				entries.push(AclEntry { tag, perm, id });
+				present = true;
+			}
+		}
+		
+		// A default ACL on a directory warrants the marker on its own
+		if name == "system.posix_acl_default" {
+			present = true;
+		}
+	}
+	
+	present.then_some(entries)
+}
+
+/// Capability names by bit number, as in `linux/capability.h`
+const CAPABILITY_NAMES: [&str; 41] = [
+	"chown", "dac_override", "dac_read_search", "fowner", "fsetid", "kill", "setgid", "setuid",
+	"setpcap", "linux_immutable", "net_bind_service", "net_broadcast", "net_admin", "net_raw",
+	"ipc_lock", "ipc_owner", "sys_module", "sys_rawio", "sys_chroot", "sys_ptrace", "sys_pacct",
+	"sys_admin", "sys_boot", "sys_nice", "sys_resource", "sys_time", "sys_tty_config", "mknod",
+	"lease", "audit_write", "audit_control", "setfcap", "mac_override", "mac_admin", "syslog",
+	"wake_alarm", "block_suspend", "audit_read", "perfmon", "bpf", "checkpoint_restore",
+];
+
+/// Read a file's capabilities in `getcap` style, e.g. `cap_net_admin,cap_net_raw=ep`
+///
+/// Decodes the `vfs_cap_data` stored in the `security.capability` xattr.
+/// Returns `None` without capabilities, and an empty string for a revision this doesn't know.
+fn read_capabilities(path: &Path) -> Option<String> {
+	let value = read_xattr(path, "security.capability")?;
+	let word = |offset: usize| value.get(offset..offset + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+	let magic = word(0)?;
+	let effective = magic & 0x1 != 0;
+	// Revision 1 has one 32-bit (permitted, inheritable) pair, 2 and 3 have two; 3 adds a root uid
+	let words = match magic & 0xff00_0000 {
+		0x0100_0000 => 1,
+		0x0200_0000 | 0x0300_0000 => 2,
+		_ => return Some(String::new()),
+	};
+	let (mut permitted, mut inheritable) = (0u64, 0u64);
+	for index in 0..words {
+		let (Some(low), Some(high)) = (word(4 + index * 8), word(8 + index * 8)) else {
+			return Some(String::new());
+		};
+		permitted |= (low as u64) << (32 * index);
+		inheritable |= (high as u64) << (32 * index);
+	}
+	
+	// Consecutive capabilities with the same flags share one clause, like getcap
+	let mut clauses: Vec<(Vec<String>, String)> = Vec::new();
+	for bit in 0..64 {
+		let (p, i) = (permitted >> bit & 1 != 0, inheritable >> bit & 1 != 0);
+		if !p && !i {
+			continue;
+		}
+		let flags = format!("{}{}{}", if effective { "e" } else { "" }, if i { "i" } else { "" }, if p { "p" } else { "" });
+		let name = CAPABILITY_NAMES.get(bit).map_or_else(|| format!("cap_{}", bit), |name| format!("cap_{}", name));
+		match clauses.last_mut() {
+			Some((names, last_flags)) if *last_flags == flags => names.push(name),
+			_ => clauses.push((vec![name], flags)),
+		}
+	}
+	if clauses.is_empty() {
+		return None;
+	}
+	Some(clauses.iter().map(|(names, flags)| format!("{}={}", names.join(","), flags)).collect::<Vec<_>>().join(" "))
+}
+
+/// Summarize ACL entries in getfacl style, e.g. `user:alice:rw-, mask::rwx`
+fn summarize_acl(entries: &[AclEntry], name_cache: &NameCache) -> String {
+	entries
+		.iter()
+		.map(|entry| {
+			let perms = format!(
+				"{}{}{}",
+				if entry.perm & 0o4 != 0 { 'r' } else { '-' },
+				if entry.perm & 0o2 != 0 { 'w' } else { '-' },
+				if entry.perm & 0o1 != 0 { 'x' } else { '-' }
+			);
+			match entry.tag {
+				ACL_USER => format!("user:{}:{}", name_cache.get_user_name(entry.id), perms),
+				ACL_GROUP => format!("group:{}:{}", name_cache.get_group_name(entry.id), perms),
+				_ => format!("mask::{}", perms),
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Read an extended attribute without following symlinks
+#[cfg(target_os = "linux")]
+fn read_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+	use std::ffi::CString;
+	use std::os::unix::ffi::OsStrExt;
+	
+	let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+	let c_name = CString::new(name).ok()?;
+	
+	// First call queries the size, second fills the buffer
+	let size = unsafe { libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0) };
+	if size <= 0 {
+		return None;
+	}
+	
+	let mut buffer = vec![0u8; size as usize];
+	let size = unsafe {
+		libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+	};
+	if size < 0 {
+		return None;
+	}
+	
+	buffer.truncate(size as usize);
+	Some(buffer)
+}
+
+/// Extended attributes are only read on Linux
+#[cfg(not(target_os = "linux"))]
+fn read_xattr(_path: &Path, _name: &str) -> Option<Vec<u8>> {
+	None
+}
+
+/// Color escape for an owner name: the palette's owner color, or a stable per-name color when enabled
+fn owner_color(owner: &str, config: &Config) -> String {
+	if config.hashed_name_colors {
+		format!("\x1b[38;5;{}m", name_to_color(owner))
+	} else {
+		palette().owner.to_string()
+	}
+}
+
+/// Color escape for a group name: the palette's group color, or a stable per-name color when enabled
+fn group_color(group: &str, config: &Config) -> String {
+	if config.hashed_name_colors {
+		format!("\x1b[38;5;{}m", name_to_color(group))
+	} else {
+		palette().group.to_string()
+	}
+}
+
+/// Map a user or group name to a stable 256-color palette index
+///
+/// Hashes the name with FNV-1a and picks from the readable colors of the 6x6x6 color cube.
+fn name_to_color(name: &str) -> u8 {
+	let hash = name.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+	let readable = readable_cube_colors();
+	readable[hash as usize % readable.len()]
+}
+
+/// Colors of the 6x6x6 cube that are neither too dark nor too pale to read on a typical terminal
+static READABLE_CUBE_COLORS: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// The readable cube colors, worked out on first use
+fn readable_cube_colors() -> &'static [u8] {
+	READABLE_CUBE_COLORS.get_or_init(|| {
+		(16..=231u8)
+			.filter(|&color| {
+				let index = color - 16;
+				let (r, g, b) = (index / 36, (index / 6) % 6, index % 6);
+				let brightness = r + g + b;
+				(5..=12).contains(&brightness) && r.max(g).max(b) >= 3
+			})
+			.collect()
+	})
+}
+
+/// Columns from the configured order that are enabled for display
+///
+/// With `icon_attached` the icon moves to just before the name, so it stays
+/// padded to the widest icon and names line up as they do in their own column.
+fn visible_columns(config: &Config) -> Vec<&str> {
+	let mut columns: Vec<&str> = config.column_order
+		.iter()
+		.map(|column| column.as_str())
+		.filter(|column| match *column {
+			"icon" => config.show_icons,
+			"permissions" => config.show_permissions,
+			"size" => config.show_size,
+			// The combined column replaces the separate ones when enabled
+			"owner" => config.show_owner && !config.show_owner_group,
+			"group" => config.show_group && !config.show_owner_group,
+			"owner_group" => config.show_owner_group && (config.show_owner || config.show_group),
+			"modified" => config.show_modified,
+			"age" => config.show_age,
+			"links" => config.show_links,
+			"fstype" => config.show_fstype && device_fstypes().is_some(),
+			"size_bar" => config.show_size_bars,
+			"percent" => config.show_percent,
+			"access" => config.show_access,
+			// Only ever shown by naming it in `column_order`
+			"info" => true,
+			"name" => true,
+			_ => false, // Skip unknown columns
+		})
+		.collect();
+	if config.icon_attached && columns.contains(&"icon") {
+		columns.retain(|column| *column != "icon");
+		let name = columns.iter().position(|column| *column == "name").unwrap_or(columns.len());
+		columns.insert(name, "icon");
+	}
+	columns
+}
+
+/// Narrowest the name column may get before other columns are dropped to make room
+const NAME_MIN_WIDTH: usize = 12;
+
+/// Built-in priority of a column for `fit_columns`; higher priorities are dropped last
+fn default_column_priority(column: &str) -> u32 {
+	match column {
+		"name" => u32::MAX, // Never dropped
+		"icon" => 70,
+		"modified" => 60,
+		"permissions" => 50,
+		"size" => 40,
+		"owner" | "owner_group" => 30,
+		"info" => 45,
+		"group" | "access" => 20,
+		_ => 10, // links, age
+	}
+}
+
+/// Priority of a column, from `priority_<column>` in the config or the built-in default
+fn column_priority(column: &str, config: &Config) -> u32 {
+	if column == "name" {
+		return u32::MAX;
+	}
+	config.column_priorities
+		.get(column)
+		.copied()
+		.unwrap_or_else(|| default_column_priority(column))
+}
+
+/// Width of a rendered listing, counting the name column at no more than `NAME_MIN_WIDTH`
+fn listing_width(entries: &[FileEntry], config: &Config) -> usize {
+	let table = config.output_format == OutputFormat::Table;
+	let columns = visible_columns(config);
+	let cells: usize = columns.iter()
+		.map(|&column| {
+			let cells = entries.iter().map(|entry| display_width(&entry.cell(column, config)));
+			let title = if table { display_width(column_title(column)) } else { 0 };
+			let width = cells.max().unwrap_or(0).max(title);
+			if column == "name" { width.min(NAME_MIN_WIDTH) } else { width }
+		})
+		.sum();
+	
+	if table {
+		cells + 3 * columns.len() + 1 // "│ " before each cell, " " after, closing "│"
+	} else {
+		cells + columns.len().saturating_sub(1)
+	}
+}
+
+/// Copy of the config with `name_budget` set to what `available` cells leave for names after the other columns
+///
+/// Names never get less than `NAME_MIN_WIDTH`; when even that doesn't fit,
+/// lines wrap rather than losing the name.
+fn with_name_budget(entries: &[FileEntry], config: &Config, available: usize) -> Config {
+	let table = config.output_format == OutputFormat::Table;
+	let name_width = entries.iter()
+		.map(|entry| display_width(&entry.cell("name", config)))
+		.max()
+		.unwrap_or(0)
+		.max(if table { display_width(column_title("name")) } else { 0 })
+		.min(NAME_MIN_WIDTH);
+	let others = listing_width(entries, config) - name_width;
+	let mut budgeted = config.clone();
+	budgeted.name_budget = Some(available.saturating_sub(others).max(NAME_MIN_WIDTH));
+	budgeted
+}
+
+/// Copy of the config with the lowest-priority columns removed until the listing fits `available` cells
+///
+/// Widths are recomputed after each drop. The name column is never dropped.
+fn fit_columns(entries: &[FileEntry], config: &Config, available: usize) -> Config {
+	let mut fitted = config.clone();
+	loop {
+		if listing_width(entries, &fitted) <= available {
+			break;
+		}
+		
+		// Among equal priorities, drop the rightmost column first
+		let columns = visible_columns(&fitted);
+		let Some(drop) = columns.iter()
+			.rev()
+			.filter(|&&column| column != "name")
+			.min_by_key(|&&column| column_priority(column, &fitted))
+			.map(|column| column.to_string())
+		else {
+			break; // Only the name is left
+		};
+		fitted.column_order.retain(|column| *column != drop);
+	}
+	fitted
+}
+
+/// Header label shown for a column in table output
+fn column_title(column: &str) -> &'static str {
+	match column {
+		"permissions" => "Perms",
+		"links" => "Links",
+		"fstype" => "FS",
+		"size_bar" => "Relative",
+		"percent" => "Share",
+		"access" => "Access",
+		"size" => "Size",
+		"owner" => "Owner",
+		"group" => "Group",
+		"owner_group" => "Owner:Group",
+		"modified" => "Modified",
+		"age" => "Age",
+		"info" => "Info",
+		"name" => "Name",
+		_ => "",
+	}
+}
+
+/// Render entries as a table with box-drawing borders and a header row
+fn format_table(entries: &[FileEntry], config: &Config, widths: &ColumnWidths) -> Vec<String> {
+	let mut columns = visible_columns(config);
+	let mut rows: Vec<Vec<String>> = entries
+		.iter()
+		.map(|entry| {
+			entry.cells(config, widths.get("icon"))
+		})
+		.collect();
+	
+	// An attached icon shares the name's cell rather than getting its own bordered one
+	if config.icon_attached {
+		if let Some(icon) = columns.iter().position(|column| *column == "icon") {
+			columns.remove(icon);
+			for row in &mut rows {
+				let icon_cell = row.remove(icon);
+				row[icon] = format!("{} {}", icon_cell, row[icon]);
+			}
+		}
+	}
+	
+	// Size each column to its widest visible cell, escape codes excluded
+	let cell_widths: Vec<usize> = columns
+		.iter()
+		.enumerate()
+		.map(|(i, column)| {
+			rows.iter()
+				.map(|row| display_width(&row[i]))
+				.chain(std::iter::once(text_width(column_title(column))))
+				.max()
+				.unwrap_or(0)
+		})
+		.collect();
+	
+	let rule = |left: &str, middle: &str, right: &str| {
+		let segments: Vec<String> = cell_widths.iter().map(|width| "─".repeat(width + 2)).collect();
+		format!("{}{}{}", left, segments.join(middle), right)
+	};
+	let row_line = |cells: &[String]| {
+		let padded: Vec<String> = cells
+			.iter()
+			.zip(&cell_widths)
+			.map(|(cell, width)| format!(" {} ", pad_to_display_width(cell, *width)))
+			.collect();
+		format!("│{}│", padded.join("│"))
+	};
+	
+	let header: Vec<String> = columns
+		.iter()
+		.map(|column| match column_title(column) {
+			"" => String::new(),
+			title => format!("\x1b[1m{}\x1b[0m", title),
+		})
+		.collect();
+	
+	let mut lines = Vec::with_capacity(rows.len() + 4);
+	lines.push(rule("┌", "┬", "┐"));
+	lines.push(row_line(&header));
+	lines.push(rule("├", "┼", "┤"));
+	lines.extend(rows.iter().map(|row| row_line(row)));
+	lines.push(rule("└", "┴", "┘"));
+	lines
+}
+
+/// Spaces between grid columns
+const GRID_SEPARATOR: usize = 2;
+
+/// Render entries as a column-major grid sized to the terminal, like GNU `ls`
+fn format_grid(entries: &[FileEntry], config: &Config, term_width: usize) -> Vec<String> {
+	let cells: Vec<String> = entries
+		.iter()
+		.map(|entry| {
+			if config.show_icons {
+				format!("{} {}", entry.icon, entry.name_cell(config))
+			} else {
+				entry.name_cell(config)
+			}
+		})
+		.collect();
+	let cell_widths: Vec<usize> = cells.iter().map(|cell| display_width(cell)).collect();
+	
+	let columns = fit_grid_columns(&cell_widths, term_width, GRID_SEPARATOR);
+	let rows = cells.len().div_ceil(columns);
+	let column_widths = grid_column_widths(&cell_widths, columns).unwrap_or_default();
+	
+	(0..rows)
+		.map(|row| {
+			let mut line = String::new();
+			for (column, width) in column_widths.iter().enumerate() {
+				let Some(cell) = cells.get(column * rows + row) else { break };
+				// Pad every cell except the last one on the line
+				if (column + 1) * rows + row < cells.len() {
+					line.push_str(&pad_to_display_width(cell, width + GRID_SEPARATOR));
+				} else {
+					line.push_str(cell);
+				}
+			}
+			line
+		})
+		.collect()
+}
+
+/// Per-column widths when `cell_widths` are laid out column-major in `columns` columns
+///
+/// Returns `None` when that column count leaves a trailing column empty.
+fn grid_column_widths(cell_widths: &[usize], columns: usize) -> Option<Vec<usize>> {
+	if columns == 0 || cell_widths.is_empty() {
+		return None;
+	}
+	
+	let rows = cell_widths.len().div_ceil(columns);
+	let widths: Vec<usize> = cell_widths.chunks(rows).map(|column| column.iter().copied().max().unwrap_or(0)).collect();
+	(widths.len() == columns).then_some(widths)
+}
+
+/// Largest column count whose total width fits the terminal, falling back to one column
+///
+/// No column is narrower than the narrowest cell, so counts beyond what the terminal
+/// holds of those are never tried and a long listing isn't measured once per entry.
+fn fit_grid_columns(cell_widths: &[usize], term_width: usize, separator: usize) -> usize {
+	let narrowest = cell_widths.iter().copied().min().unwrap_or(0);
+	let most = cell_widths.len().min(term_width / (narrowest + separator) + 1);
+	(1..=most)
+		.rev()
+		.find(|&columns| {
+			grid_column_widths(cell_widths, columns)
+				.map(|widths| widths.iter().sum::<usize>() + separator * (columns - 1) <= term_width)
+				.unwrap_or(false)
+		})
+		.unwrap_or(1)
+}
+
+/// Width of the terminal the listing goes to, falling back to `$COLUMNS` and then 80
+fn terminal_width() -> usize {
+	output_is_terminal()
+		.then(|| terminal_size(libc::STDOUT_FILENO))
+		.flatten()
+		.map(|(_, columns)| columns)
+		.or_else(|| env::var("COLUMNS").ok().and_then(|value| value.parse().ok()))
+		.unwrap_or(80)
+}
+
+/// Rows and columns of the terminal behind `fd`, if it is one
+fn terminal_size(fd: i32) -> Option<(usize, usize)> {
+	let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+	if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+		Some((size.ws_row as usize, size.ws_col as usize))
+	} else {
+		None
+	}
+}
+
+/// Link template used when `hyperlink_scheme` isn't set
+const DEFAULT_HYPERLINK_SCHEME: &str = "file://{path}";
+
+/// Wrap `text` in an OSC 8 hyperlink to `path`, built from the configured template
+///
+/// `{line}` is accepted for editor schemes and always points at the first line.
+fn hyperlink(path: &Path, text: &str, config: &Config) -> String {
+	let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+	let url = config.hyperlink_scheme
+		.replace("{path}", &percent_encode_path(&path))
+		.replace("{line}", "1");
+	format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Percent-encode a path for a URL, keeping `/` and unreserved characters as they are
+fn percent_encode_path(path: &Path) -> String {
+	use std::os::unix::ffi::OsStrExt;
+	
+	let mut encoded = String::new();
+	for &byte in path.as_os_str().as_bytes() {
+		if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+			encoded.push(byte as char);
+		} else {
+			encoded.push_str(&format!("%{:02X}", byte));
+		}
+	}
+	encoded
+}
+
+/// Remove ANSI escape sequences, including OSC 8 hyperlinks, from a string
+fn strip_ansi(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	
+	while let Some(c) = chars.next() {
+		if c == '\x1b' && chars.peek() == Some(&']') {
+			// Skip the OSC sequence up to its BEL or ESC \ terminator
+			while let Some(c) = chars.next() {
+				if c == '\x07' {
+					break;
+				}
+				if c == '\x1b' {
+					chars.next();
+					break;
+				}
+			}
+		} else if c == '\x1b' {
+			// Skip the CSI sequence up to and including its final letter
+			for c in chars.by_ref() {
+				if c.is_ascii_alphabetic() {
+					break;
+				}
+			}
+		} else {
+			result.push(c);
+		}
+	}
+	
+	result
+}
+
+/// Visible terminal width of a string, ignoring ANSI escape sequences
+fn display_width(text: &str) -> usize {
+	if text.contains('\x1b') {
+		text_width(&strip_ansi(text))
+	} else {
+		text_width(text)
+	}
+}
+
+/// Emoji presentation selector; terminals draw the character before it as a wide emoji
+const VARIATION_SELECTOR_16: char = '\u{FE0F}';
+
+/// Width of ambiguous characters, decided once in main from the config
+static AMBIGUOUS_WIDTH: OnceLock<AmbiguousWidth> = OnceLock::new();
+
+/// Terminal width of one character, honoring the configured ambiguous width
+fn char_width(c: char) -> usize {
+	char_width_as(c, AMBIGUOUS_WIDTH.get().copied().unwrap_or(AmbiguousWidth::Single))
+}
+
+/// Terminal width of one character with ambiguous characters taking `ambiguous` columns
+fn char_width_as(c: char, ambiguous: AmbiguousWidth) -> usize {
+	match ambiguous {
+		AmbiguousWidth::Double => UnicodeWidthChar::width_cjk(c),
+		AmbiguousWidth::Single => UnicodeWidthChar::width(c),
+	}.unwrap_or(0)
+}
+
+/// Terminal width of plain text, counting VS16 emoji such as ⚙️ and 🖼️ as two columns
+///
+/// `unicode-width` reports the base character of these sequences as narrow,
+/// but terminals render them as wide emoji, which made icons drift by one column.
+fn text_width(text: &str) -> usize {
+	text_width_as(text, AMBIGUOUS_WIDTH.get().copied().unwrap_or(AmbiguousWidth::Single))
+}
+
+/// Terminal width of plain text with ambiguous characters taking `ambiguous` columns
+fn text_width_as(text: &str, ambiguous: AmbiguousWidth) -> usize {
+	let mut width = 0;
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		let char_width = char_width_as(c, ambiguous);
+		if chars.next_if_eq(&VARIATION_SELECTOR_16).is_some() {
+			width += char_width.max(2);
+		} else {
+			width += char_width;
+		}
+	}
+	width
+}
+
+/// Pad a string with spaces to reach a target terminal display width
+fn pad_to_display_width(text: &str, target_width: usize) -> String {
+	let current_width = display_width(text);
+	if current_width >= target_width {
+		// Ensure at least original text (no truncation), rely on outer separator for spacing
+		text.to_string()
+	} else {
+		let pad_spaces = target_width - current_width;
+		format!("{}{}", text, " ".repeat(pad_spaces))
+	}
+}
+
+/// Truncate a string to a maximum display width, ending with an ellipsis when cut
+fn truncate_to_display_width(text: &str, max_width: usize) -> String {
+	if text_width(text) <= max_width {
+		return text.to_string();
+	}
+	
+	// Reserve one column for the ellipsis
+	let mut result = String::new();
+	let mut width = 0;
+	for c in text.chars() {
+		let char_width = if c == VARIATION_SELECTOR_16 { 1 } else { char_width(c) };
+		if width + char_width + 1 > max_width {
+			break;
+		}
+		result.push(c);
+		width += char_width;
+	}
+	result.push('…');
+	result
+}
+
+/// Pad a string on the left to right-align it within a target terminal display width
+fn pad_left_to_display_width(text: &str, target_width: usize) -> String {
+	let current_width = display_width(text);
+	if current_width >= target_width {
+		text.to_string()
+	} else {
+		format!("{}{}", " ".repeat(target_width - current_width), text)
+	}
+}
+
+/// Modification times that cannot be shown as a normal duration
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeAnomaly {
+	/// The mtime could not be read or is before the epoch
+	Unknown,
+	/// The mtime lies this many seconds in the future
+	Future(u64),
+}
+
+impl TimeAnomaly {
+	/// Text configured for this anomaly, with `{}` in `future_time_text` replaced by how far ahead the time is
+	fn text(self, config: &Config) -> String {
+		match self {
+			TimeAnomaly::Unknown => config.unknown_time_text.clone(),
+			TimeAnomaly::Future(seconds) => {
+				let ahead = match config.relative_time_style(config.use_fuzzy_time) {
+					RelativeTimeStyle::Compact => format_compact_seconds(seconds).trim_start().to_string(),
+					RelativeTimeStyle::Fractional => format_fractional_seconds(seconds),
+					_ => format_fuzzy_seconds(seconds),
+				};
+				config.future_time_text.replace("{}", &ahead)
+			},
+		}
+	}
+	
+	/// Color distinguishing this anomaly from normal times
+	fn color(self) -> &'static str {
+		match self {
+			TimeAnomaly::Unknown => "\x1b[90m", // Muted gray
+			TimeAnomaly::Future(_) => "\x1b[33;1m", // Bold yellow, likely clock skew
+		}
+	}
+}
+
+/// How `format_duration_since` renders a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelativeTimeStyle {
+	/// Days, hours and minutes since the epoch, like `123d 14h:35m`
+	Timestamp,
+	/// Localized fuzzy text like `3 hours`
+	Fuzzy,
+	/// Fuzzy time as a token always `COMPACT_TIME_WIDTH` wide, like ` 3h`
+	Compact,
+	/// Fuzzy text with one decimal for hours, days and weeks below ten, like `1.5 hours`
+	Fractional,
+}
+
+/// Width of every `RelativeTimeStyle::Compact` time
+const COMPACT_TIME_WIDTH: usize = 4;
+
+/// Format duration since a given time into human-readable fuzzy text
+fn format_duration_since(modified_time: SystemTime, style: RelativeTimeStyle) -> Result<String, TimeAnomaly> {
+	if style == RelativeTimeStyle::Timestamp {
+		// Return simplified timestamp instead of fuzzy time
+		if let Ok(duration) = modified_time.duration_since(std::time::UNIX_EPOCH) {
+			let secs = duration.as_secs();
+			// Simple timestamp format (days since epoch approximation)
+			let days = secs / 86400;
+			let hours = (secs % 86400) / 3600;
+			let minutes = (secs % 3600) / 60;
+			return Ok(format!("{}d {}h:{}m", days % 365, hours, minutes));
+		}
+		return Err(TimeAnomaly::Unknown);
+	}
+	let now = SystemTime::now();
+	
+	// Times in the future are clock skew or bad archives; even a fraction of a second ahead says "1 second"
+	let duration = match now.duration_since(modified_time) {
+		Ok(d) => d,
+		Err(ahead) => return Err(TimeAnomaly::Future(ahead.duration().as_secs().max(1))),
+	};
+	
+	Ok(match style {
+		RelativeTimeStyle::Compact => format_compact_seconds(duration.as_secs()),
+		RelativeTimeStyle::Fractional => format_fractional_seconds(duration.as_secs()),
+		_ => format_fuzzy_seconds(duration.as_secs()),
+	})
+}
+
+/// Seconds as fuzzy text, with tenths (rounded down) for fewer than ten hours, days or weeks
+///
+/// Whole hours drop straight from `59 minutes` to `1 hour`, so the first few
+/// units of these ranges are where a fraction says the most.
+fn format_fractional_seconds(seconds: u64) -> String {
+	let (name, length) = match seconds {
+		s if s < SECS_PER_HOUR => return format_fuzzy_seconds(s),
+		s if s < SECS_PER_DAY => ("hour", SECS_PER_HOUR),
+		s if s < SECS_PER_WEEK => ("day", SECS_PER_DAY),
+		s if s < SECS_PER_MONTH => ("week", SECS_PER_WEEK),
+		s => return format_fuzzy_seconds(s),
+	};
+	let tenths = seconds * 10 / length;
+	if tenths >= 100 {
+		return format_fuzzy_seconds(seconds);
+	}
+	fuzzy_locale().format_tenths(tenths, name)
+}
+
+/// Seconds as a right-aligned count and one-letter unit, `COMPACT_TIME_WIDTH` wide
+///
+/// Weeks run up to a year (`52w`), so minutes keep the only `m`.
+fn format_compact_seconds(seconds: u64) -> String {
+	let (count, unit) = match seconds {
+		s if s < SECS_PER_MINUTE => (s, 's'),
+		s if s < SECS_PER_HOUR => (s / SECS_PER_MINUTE, 'm'),
+		s if s < SECS_PER_DAY => (s / SECS_PER_HOUR, 'h'),
+		s if s < SECS_PER_WEEK => (s / SECS_PER_DAY, 'd'),
+		s if s < SECS_PER_YEAR => (s / SECS_PER_WEEK, 'w'),
+		s => (s / SECS_PER_YEAR, 'y'),
+	};
+	// Only years could overflow the count, and only after a millennium
+	format!("{:>width$}{}", count.min(999), unit, width = COMPACT_TIME_WIDTH - 1)
+}
+
+/// Specifiers understood by `format_timestamp`, listed in warnings
+const TIME_SPECIFIERS: &str = "%Y %y %m %d %e %H %I %M %S %p %a %b %j %%";
+
+/// Abbreviated weekday names, starting on Sunday
+const WEEKDAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Abbreviated month names
+const MONTH_NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Specifiers in a time format that `format_timestamp` does not support, such as `%Z`
+fn unsupported_time_specifiers(format: &str) -> Vec<String> {
+	let mut unsupported = Vec::new();
+	let mut chars = format.chars();
+	while let Some(c) = chars.next() {
+		if c != '%' {
+			continue;
+		}
+		let specifier = match chars.next() {
+			Some(specifier) => format!("%{}", specifier),
+			None => "trailing %".to_string(),
+		};
+		if !TIME_SPECIFIERS.split(' ').any(|known| known == specifier) && !unsupported.contains(&specifier) {
+			unsupported.push(specifier);
+		}
+	}
+	unsupported
+}
+
+/// Civil date (year, month 1-12, day 1-31) of a day count since 1970-01-01
+///
+/// Howard Hinnant's `civil_from_days`, valid for the whole proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719_468;
+	let era = z.div_euclid(146_097);
+	let day_of_era = z.rem_euclid(146_097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+/// Offset of local time from UTC in seconds at the given Unix time, per the `TZ` rules
+fn local_utc_offset(secs: i64) -> i64 {
+	let time = secs as libc::time_t;
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+	// SAFETY: localtime_r only writes to the tm buffer we own
+	if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+		return 0;
+	}
+	tm.tm_gmtoff as i64
+}
+
+/// Format a time in the local time zone with a strftime-style format
+///
+/// Supports the specifiers in `TIME_SPECIFIERS`; others are copied literally.
+/// Returns `None` for times before the epoch.
+fn format_timestamp(time: SystemTime, format: &str) -> Option<String> {
+	let secs = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64;
+	Some(format_unix_time(secs, local_utc_offset(secs), format))
+}
+
+/// Format Unix time `secs`, shifted by `offset` seconds, with a strftime-style format
+fn format_unix_time(secs: i64, offset: i64, format: &str) -> String {
+	let local = secs + offset;
+	let days = local.div_euclid(SECS_PER_DAY as i64);
+	let seconds_of_day = local.rem_euclid(SECS_PER_DAY as i64);
+	let (year, month, day) = civil_from_days(days);
+	let (hour, minute, second) = (seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60);
+	let weekday = (days + 4).rem_euclid(7) as usize; // 1970-01-01 was a Thursday
+	let day_of_year: u32 = (1..month).map(|earlier| days_in_month(year, earlier)).sum::<u32>() + day;
+	
+	let mut formatted = String::with_capacity(format.len() + 8);
+	let mut chars = format.chars();
+	while let Some(c) = chars.next() {
+		if c != '%' {
+			formatted.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('Y') => formatted.push_str(&year.to_string()),
+			Some('y') => formatted.push_str(&format!("{:02}", year.rem_euclid(100))),
+			Some('m') => formatted.push_str(&format!("{:02}", month)),
+			Some('d') => formatted.push_str(&format!("{:02}", day)),
+			Some('e') => formatted.push_str(&format!("{:2}", day)),
+			Some('H') => formatted.push_str(&format!("{:02}", hour)),
+			Some('I') => formatted.push_str(&format!("{:02}", (hour + 11) % 12 + 1)),
+			Some('M') => formatted.push_str(&format!("{:02}", minute)),
+			Some('S') => formatted.push_str(&format!("{:02}", second)),
+			Some('p') => formatted.push_str(if hour < 12 { "AM" } else { "PM" }),
+			Some('a') => formatted.push_str(WEEKDAY_NAMES[weekday]),
+			Some('b') => formatted.push_str(MONTH_NAMES[month as usize - 1]),
+			Some('j') => formatted.push_str(&format!("{:03}", day_of_year)),
+			Some('%') => formatted.push('%'),
+			Some(other) => {
+				formatted.push('%');
+				formatted.push(other);
+			},
+			None => formatted.push('%'),
+		}
+	}
+	formatted
+}
+
+/// Number of days in a month of a proleptic Gregorian year
+fn days_in_month(year: i64, month: u32) -> u32 {
+	match month {
+		2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+/// Seconds in a minute
+const SECS_PER_MINUTE: u64 = 60;
+/// Seconds in an hour
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+/// Seconds in a day
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+/// Seconds in a week
+const SECS_PER_WEEK: u64 = 7 * SECS_PER_DAY;
+/// Seconds in an average Gregorian year (365.2425 days)
+const SECS_PER_YEAR: u64 = SECS_PER_DAY * 3_652_425 / 10_000;
+/// Seconds in an average month: a twelfth of the average year, about 30.44 days (2,629,746 seconds)
+const SECS_PER_MONTH: u64 = SECS_PER_YEAR / 12;
+
+/// Format an elapsed number of seconds as fuzzy text ("5 minutes", "2 months")
+///
+/// Each arm covers a half-open range ending where the next one begins, so every
+/// duration maps to exactly one unit with no gaps between them.
+fn format_fuzzy_seconds(seconds: u64) -> String {
+	let locale = fuzzy_locale();
+	match seconds {
+		0 => locale.now.clone(),
+		s if s < SECS_PER_MINUTE => locale.format(s, "second"),
+		s if s < SECS_PER_HOUR => locale.format(s / SECS_PER_MINUTE, "minute"),
+		s if s < SECS_PER_DAY => locale.format(s / SECS_PER_HOUR, "hour"),
+		s if s < SECS_PER_WEEK => locale.format(s / SECS_PER_DAY, "day"),
+		s if s < SECS_PER_MONTH => locale.format(s / SECS_PER_WEEK, "week"),
+		s if s < SECS_PER_YEAR => locale.format(s / SECS_PER_MONTH, "month"),
+		s => locale.format(s / SECS_PER_YEAR, "year"),
+	}
+}
+
+/// Units a fuzzy time is expressed in, smallest first
+const TIME_UNITS: [&str; 7] = ["second", "minute", "hour", "day", "week", "month", "year"];
+
+/// Grammatical number a count takes, after the CLDR plural categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PluralCategory {
+	One,
+	Few,
+	Many,
+	Other,
+}
+
+impl PluralCategory {
+	/// Category named by the form part of a `lang_<form>_<unit>` key
+	fn from_form(form: &str) -> Option<Self> {
+		match form {
+			"1" | "one" => Some(PluralCategory::One),
+			"few" => Some(PluralCategory::Few),
+			"many" => Some(PluralCategory::Many),
+			"n" | "other" => Some(PluralCategory::Other),
+			_ => None,
+		}
+	}
+}
+
+/// How a language picks the plural form for a count
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PluralRule {
+	/// Singular for 1 only (English, German, Spanish)
+	OneOther,
+	/// Singular for 0 and 1 (French)
+	ZeroOneOther,
+	/// One for 1, 21, 31…; few for 2-4, 22-24…; many otherwise (Russian, Ukrainian)
+	EastSlavic,
+	/// The same form for every count (Japanese, Chinese)
+	Invariant,
+}
+
+impl PluralRule {
+	/// Parse a plural rule name from config
+	fn parse(value: &str) -> Option<Self> {
+		match value.to_lowercase().as_str() {
+			"one_other" | "one" => Some(PluralRule::OneOther),
+			"zero_one_other" | "french" => Some(PluralRule::ZeroOneOther),
+			"east_slavic" | "slavic" => Some(PluralRule::EastSlavic),
+			"invariant" | "none" => Some(PluralRule::Invariant),
+			_ => None,
+		}
+	}
+	
+	/// Plural category of a count with a fractional part, `tenths` tenths of a unit
+	fn fraction_category(self, tenths: u64) -> PluralCategory {
+		match self {
+			// French keeps the singular below 2 even for fractions ("1,5 heure")
+			PluralRule::ZeroOneOther if tenths < 20 => PluralCategory::One,
+			// Russian fractions take the genitive singular, the few form's ending
+			PluralRule::EastSlavic => PluralCategory::Few,
+			_ => PluralCategory::Other,
+		}
+	}
+	
+	/// Plural category of `count`
+	fn category(self, count: u64) -> PluralCategory {
+		match self {
+			PluralRule::OneOther if count == 1 => PluralCategory::One,
+			PluralRule::ZeroOneOther if count <= 1 => PluralCategory::One,
+			PluralRule::EastSlavic => match (count % 10, count % 100) {
+				(1, tens) if tens != 11 => PluralCategory::One,
+				(2..=4, tens) if !(12..=14).contains(&tens) => PluralCategory::Few,
+				_ => PluralCategory::Many,
+			},
+			_ => PluralCategory::Other,
+		}
+	}
+}
+
+/// Words for fuzzy times in one language: templates per unit and plural form, `{n}` the count
+#[derive(Debug, Clone)]
+struct FuzzyLocale {
+	plural: PluralRule,
+	now: String,
+	forms: HashMap<(&'static str, PluralCategory), String>,
+}
+
+/// Bundled fuzzy-time words: language, plural rule, "now", then per unit the forms in
+/// `PluralRule` order (one and other, or one, few and many for East Slavic)
+const FUZZY_LOCALES: [(&str, PluralRule, &str, [&[&str]; 7]); 5] = [
+	("en", PluralRule::OneOther, "now", [
+		&["{n} second", "{n} seconds"], &["{n} minute", "{n} minutes"], &["{n} hour", "{n} hours"],
+		&["{n} day", "{n} days"], &["{n} week", "{n} weeks"], &["{n} month", "{n} months"], &["{n} year", "{n} years"],
+	]),
+	("de", PluralRule::OneOther, "jetzt", [
+		&["{n} Sekunde", "{n} Sekunden"], &["{n} Minute", "{n} Minuten"], &["{n} Stunde", "{n} Stunden"],
+		&["{n} Tag", "{n} Tage"], &["{n} Woche", "{n} Wochen"], &["{n} Monat", "{n} Monate"], &["{n} Jahr", "{n} Jahre"],
+	]),
+	("es", PluralRule::OneOther, "ahora", [
+		&["{n} segundo", "{n} segundos"], &["{n} minuto", "{n} minutos"], &["{n} hora", "{n} horas"],
+		&["{n} día", "{n} días"], &["{n} semana", "{n} semanas"], &["{n} mes", "{n} meses"], &["{n} año", "{n} años"],
+	]),
+	("fr", PluralRule::ZeroOneOther, "maintenant", [
+		&["{n} seconde", "{n} secondes"], &["{n} minute", "{n} minutes"], &["{n} heure", "{n} heures"],
+		&["{n} jour", "{n} jours"], &["{n} semaine", "{n} semaines"], &["{n} mois", "{n} mois"], &["{n} an", "{n} ans"],
+	]),
+	("ru", PluralRule::EastSlavic, "сейчас", [
+		&["{n} секунда", "{n} секунды", "{n} секунд"], &["{n} минута", "{n} минуты", "{n} минут"],
+		&["{n} час", "{n} часа", "{n} часов"], &["{n} день", "{n} дня", "{n} дней"],
+		&["{n} неделя", "{n} недели", "{n} недель"], &["{n} месяц", "{n} месяца", "{n} месяцев"],
+		&["{n} год", "{n} года", "{n} лет"],
+	]),
+];
+
+impl FuzzyLocale {
+	/// Bundled words for a language code such as `de`, if there are any
+	fn bundled(language: &str) -> Option<Self> {
+		let (_, plural, now, units) = FUZZY_LOCALES.iter().find(|(code, ..)| *code == language)?;
+		let categories: &[PluralCategory] = match plural {
+			PluralRule::EastSlavic => &[PluralCategory::One, PluralCategory::Few, PluralCategory::Many],
+			_ => &[PluralCategory::One, PluralCategory::Other],
+		};
+		let mut forms = HashMap::new();
+		for (unit, templates) in TIME_UNITS.iter().zip(units) {
+			for (category, template) in categories.iter().zip(templates.iter()) {
+				forms.insert((*unit, *category), template.to_string());
+			}
+		}
+		Some(FuzzyLocale { plural: *plural, now: now.to_string(), forms })
+	}
+	
+	/// The English words yal has always used
+	fn english() -> Self {
+		Self::bundled("en").expect("English fuzzy times are bundled")
+	}
+	
+	/// Apply one `lang_*` override, keyed without the prefix; unknown keys are ignored
+	fn set(&mut self, key: &str, value: &str) {
+		match key {
+			"now" => self.now = value.to_string(),
+			"plural_rule" => {
+				if let Some(rule) = PluralRule::parse(value) {
+					self.plural = rule;
+				}
+			},
+			_ => {
+				let Some((form, unit)) = key.split_once('_') else { return };
+				let (Some(category), Some(unit)) = (PluralCategory::from_form(form), TIME_UNITS.iter().find(|known| **known == unit)) else {
+					return;
+				};
+				self.forms.insert((unit, category), value.to_string());
+			},
+		}
+	}
+	
+	/// `count` units in words, falling back to the other form, then to English
+	fn format(&self, count: u64, unit: &'static str) -> String {
+		let category = self.plural.category(count);
+		let template = self.forms.get(&(unit, category))
+			.or_else(|| self.forms.get(&(unit, PluralCategory::Other)))
+			.cloned()
+			.unwrap_or_else(|| pluralize(count, unit));
+		template.replace("{n}", &count.to_string())
+	}
+	
+	/// `tenths` tenths of a unit in words with one decimal, like `1.5 hours`, or whole when it's round
+	fn format_tenths(&self, tenths: u64, unit: &'static str) -> String {
+		if tenths.is_multiple_of(10) {
+			return self.format(tenths / 10, unit);
+		}
+		let count = format!("{}.{}", tenths / 10, tenths % 10);
+		self.forms.get(&(unit, self.plural.fraction_category(tenths)))
+			.or_else(|| self.forms.get(&(unit, PluralCategory::Other)))
+			.map_or_else(|| format!("{} {}s", count, unit), |template| template.replace("{n}", &count))
+	}
+}
+
+/// Fuzzy-time words, set once in main from the config
+static FUZZY_LOCALE: OnceLock<FuzzyLocale> = OnceLock::new();
+
+/// Active fuzzy-time words, English until main has picked them
+fn fuzzy_locale() -> &'static FuzzyLocale {
+	FUZZY_LOCALE.get_or_init(FuzzyLocale::english)
+}
+
+/// Language code from `LC_ALL`, `LC_MESSAGES` or `LANG`, such as `de` for `de_DE.UTF-8`
+fn environment_language() -> String {
+	let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+		.filter_map(|name| env::var(name).ok())
+		.find(|value| !value.is_empty())
+		.unwrap_or_default();
+	let language = locale.split(['_', '.', '@']).next().unwrap_or("").to_lowercase();
+	match language.as_str() {
+		"" | "c" | "posix" => "en".to_string(),
+		_ => language,
+	}
+}
+
+/// Draw `size` as a bar `width` cells wide, full at `largest`, with eighth-cell resolution
+fn size_bar(size: u64, largest: u64, width: usize) -> String {
+	const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+	if largest == 0 {
+		return " ".repeat(width);
+	}
+	
+	let mut eighths = (size as f64 / largest as f64 * (width * 8) as f64).round() as usize;
+	if size > 0 {
+		eighths = eighths.max(1); // Keep non-empty files visible
+	}
+	let full = eighths / 8;
+	let mut bar = "█".repeat(full);
+	if full < width {
+		bar.push(PARTIAL[eighths % 8]);
+		bar.push_str(&" ".repeat(width - full - 1));
+	}
+	bar
+}
+
+/// Parse a size such as `4096`, `1K`, `4KiB`, `1MB` or `M` into bytes
+///
+/// Suffixes K, M, G, T, P and E (optionally followed by `iB`) are powers of
+/// 1024; with a `B` (KB, MB, ...) they are powers of 1000, as in GNU tools.
+fn parse_size(value: &str) -> Option<u64> {
+	let value = value.trim();
+	let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+	let (number, suffix) = value.split_at(split);
+	let number: u64 = if number.is_empty() { 1 } else { number.parse().ok()? };
+	
+	let suffix = suffix.to_uppercase();
+	let (unit, base) = match suffix.as_str() {
+		"" => return Some(number).filter(|&bytes| bytes > 0),
+		_ if suffix.ends_with("IB") => (&suffix[..suffix.len() - 2], 1024u64),
+		_ if suffix.len() == 2 && suffix.ends_with('B') => (&suffix[..1], 1000u64),
+		_ => (suffix.as_str(), 1024u64),
+	};
+	let exponent = ["K", "M", "G", "T", "P", "E"].iter().position(|&known| known == unit)? as u32 + 1;
+	number.checked_mul(base.checked_pow(exponent)?).filter(|&bytes| bytes > 0)
+}
+
+/// Parse a `block_size` value: `human` for readable units, otherwise a size for `parse_size`
+fn parse_block_size(value: &str) -> Option<Option<u64>> {
+	if value.eq_ignore_ascii_case("human") {
+		return Some(None);
+	}
+	parse_size(value).map(Some)
+}
+
+/// Parse a `number_format` value: `none` or a separator name or character
+fn parse_digit_separator(value: &str) -> Option<Option<char>> {
+	match value.to_lowercase().as_str() {
+		"none" | "off" => Some(None),
+		"comma" | "," => Some(Some(',')),
+		"period" | "dot" | "." => Some(Some('.')),
+		"underscore" | "_" => Some(Some('_')),
+		"space" => Some(Some(' ')),
+		_ => None,
+	}
+}
+
+/// Insert a separator between each group of three digits (1234567 -> 1,234,567)
+fn group_digits(number: u64, separator: char) -> String {
+	let digits = number.to_string();
+	let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+	for (i, digit) in digits.chars().enumerate() {
+		if i > 0 && (digits.len() - i).is_multiple_of(3) {
+			grouped.push(separator);
+		}
+		grouped.push(digit);
+	}
+	grouped
+}
+
+/// Format a byte count as human-readable text in `units` (512B, 1.5K, 23.0M)
+///
+/// `precision` is the number of decimal places for scaled values; with zero
+/// no decimal point is shown (1536 bytes renders as 2K, 1.5K or 1.50K).
+///
+/// The unit is picked from the exact size, before rounding: a size moves to the
+/// next unit only once it is at least 1 of it, so 1023 bytes stays `1023B` and
+/// 1,048,575 bytes is `1024.0K` when rounded up, never a premature `1.0M`.
+fn format_size(bytes: u64, units: SizeUnits, precision: usize, rounding: SizeRounding, suffixes: &[String]) -> String {
+	let base = units.base();
+	if bytes < base {
+		return format!("{}{}", bytes, suffixes[0]);
+	}
+	
+	let mut value = bytes as f64 / base as f64;
+	let mut unit = 1;
+	while value >= base as f64 && unit < suffixes.len() - 1 {
+		value /= base as f64;
+		unit += 1;
+	}
+	
+	format!("{:.*}{}", precision, rounding.apply(value, precision), suffixes[unit])
+}
+
+/// Default unit labels for human-readable sizes, from bytes to exbibytes
+const SIZE_SUFFIXES: [&str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
+
+/// Classify an elapsed number of seconds into a coarse age label using the configured thresholds
+fn age_bucket(secs: u64, config: &Config) -> &'static str {
+	if secs < config.age_today_secs {
+		"today"
+	} else if secs < config.age_week_secs {
+		"this week"
+	} else if secs < config.age_month_secs {
+		"this month"
+	} else {
+		"older"
+	}
+}
+
+/// Color escape for an age label: fresher files are brighter
+fn age_color(label: &str) -> &'static str {
+	match label {
+		"today" => "\x1b[32;1m",   // Bright green
+		"this week" => "\x1b[32m", // Green
+		"this month" => "\x1b[33m",// Yellow
+		_ => "\x1b[90m",           // Gray for older or unknown
+	}
+}
+
+/// Heatmap steps for ages: upper bound in seconds and legend label, colored by `Palette::heat_time`
+const AGE_HEAT: [(u64, &str); 6] = [
+	(SECS_PER_HOUR, "< 1 hour"),
+	(SECS_PER_DAY, "< 1 day"),
+	(SECS_PER_WEEK, "< 1 week"),
+	(SECS_PER_MONTH, "< 1 month"),
+	(SECS_PER_YEAR, "< 1 year"),
+	(u64::MAX, "older"),
+];
+
+/// Heatmap steps for sizes: upper bound in bytes and legend label, colored by `Palette::heat_size`
+const SIZE_HEAT: [(u64, &str); 6] = [
+	(1 << 10, "< 1K"),
+	(1 << 20, "< 1M"),
+	(10 << 20, "< 10M"),
+	(100 << 20, "< 100M"),
+	(1 << 30, "< 1G"),
+	(u64::MAX, ">= 1G"),
+];
+
+/// Look up the color of the first heatmap step whose bound exceeds the value
+fn heat_color(steps: &[(u64, &str)], colors: &[&'static str], value: u64) -> &'static str {
+	let step = steps.iter().position(|(limit, _)| value < *limit).unwrap_or(steps.len() - 1);
+	colors.get(step).copied().unwrap_or("")
+}
+
+/// Heatmap color for a file age in seconds: fresh is bright green, old fades to gray
+fn age_to_color(secs: u64) -> &'static str {
+	heat_color(&AGE_HEAT, &palette().heat_time, secs)
+}
+
+/// Heatmap color for a size in bytes: small is gray, huge is red
+fn size_to_color(bytes: u64) -> &'static str {
+	heat_color(&SIZE_HEAT, &palette().heat_size, bytes)
+}
+
+/// Whether ANSI colors are written, decided once in main from the color mode
+static COLORS: OnceLock<bool> = OnceLock::new();
+
+/// The `--output` file the listing goes to instead of stdout, opened once in main
+static OUTPUT: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Listing output: the `--output` file when one was given, stdout otherwise
+///
+/// Each write takes the file on its own, so holding an `Output` never blocks other printing.
+struct Output;
+
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match OUTPUT.get() {
+			Some(file) => file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).write(buf),
+			None => io::stdout().write(buf),
+		}
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		match OUTPUT.get() {
+			Some(file) => file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).flush(),
+			None => io::stdout().flush(),
+		}
+	}
+}
+
+/// Whether the listing goes to a terminal, for auto colors, fitting and prompts
+fn output_is_terminal() -> bool {
+	OUTPUT.get().is_none() && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+}
+
+/// Print a line of listing output, dropping its color escapes when colors are off
+fn print_line(line: &str) {
+	let _ = write_line(&mut Output, line);
+}
+
+/// Print a directory header, on stderr when the listing itself goes to an `--output` file
+fn print_header(line: &str) {
+	if OUTPUT.get().is_some() {
+		let _ = write_line(&mut io::stderr(), line);
+	} else {
+		print_line(line);
+	}
+}
+
+/// Write a line of listing output to `out`, dropping its color escapes when colors are off
+fn write_line(out: &mut impl Write, line: &str) -> io::Result<()> {
+	if COLORS.get().copied().unwrap_or(true) {
+		writeln!(out, "{}", line)
+	} else {
+		writeln!(out, "{}", strip_ansi(line))
+	}
+}
+
+/// Print a color key for the active heatmap, one swatch per step
+fn print_legend(config: &Config) {
+	let (title, steps, colors) = match config.heatmap {
+		Heatmap::Time => ("Modified", &AGE_HEAT, &palette().heat_time),
+		Heatmap::Size => ("Size", &SIZE_HEAT, &palette().heat_size),
+		Heatmap::Off => return,
+	};
+	let swatches: Vec<String> = steps.iter()
+		.zip(colors)
+		.map(|((_, label), color)| format!("{}██\x1b[0m {}", color, label))
+		.collect();
+	print_line(&format!(" {}: {}", title, swatches.join("  ")));
+}
+
+/// Parse a duration such as `90`, `45m`, `12h`, `7d` or `2w` into seconds
+fn parse_duration(value: &str) -> Option<u64> {
+	let value = value.trim().to_lowercase();
+	let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+	let (number, unit) = value.split_at(split);
+	let number: u64 = number.parse().ok()?;
+	
+	let multiplier = match unit.trim() {
+		"" | "s" => 1,
+		"m" => SECS_PER_MINUTE,
+		"h" => SECS_PER_HOUR,
+		"d" => SECS_PER_DAY,
+		"w" => SECS_PER_WEEK,
+		"mo" => SECS_PER_MONTH,
+		"y" => SECS_PER_YEAR,
+		_ => return None,
+	};
+	
+	number.checked_mul(multiplier)
+}
+
+/// Format a count with its unit, pluralizing the unit when needed ("1 hour", "3 hours")
+fn pluralize(count: u64, unit: &str) -> String {
+	if count == 1 {
+		format!("1 {}", unit)
+	} else {
+		format!("{} {}s", count, unit)
+	}
+}
+
+/// Resolves file icons, caching the result per extension for the whole run
+///
+/// Custom icons from the config take priority over the built-in table. The
+/// cache pays off once custom icons are in play, since the same extension is
+/// looked up for every file sharing it.
+struct IconResolver {
+	custom: HashMap<String, Arc<str>>,
+	cache: Mutex<HashMap<String, Option<Arc<str>>>>,
+}
+
+impl IconResolver {
+	/// Create a resolver using the custom icons from the configuration
+	fn new(config: &Config) -> Self {
+		IconResolver {
+			// Config icons come last so they win over the theme's
+			custom: config.theme_file.iter()
+				.flat_map(|theme| &theme.icons)
+				.chain(&config.custom_icons)
+				.map(|(extension, icon)| (extension.to_lowercase(), Arc::from(icon.as_str())))
+				.collect(),
+			cache: Mutex::new(HashMap::new()),
+		}
+	}
+	
+	/// Get an appropriate icon for the file type
+	fn resolve(&self, filename: &str, is_dir: bool) -> Arc<str> {
+		if is_dir {
+			return self.custom.get("directory").cloned().unwrap_or_else(|| Arc::from("📁"));
+		}
+		
+		// Get file extension
+		let extension = Path::new(filename)
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.unwrap_or("")
+			.to_lowercase();
+		
+		let cached = self.cache.lock().ok().and_then(|cache| cache.get(&extension).cloned());
+		let icon = cached.unwrap_or_else(|| {
+			let icon = self.custom.get(&extension).cloned().or_else(|| extension_icon(&extension).map(Arc::from));
+			if let Ok(mut cache) = self.cache.lock() {
+				cache.insert(extension, icon.clone());
+			}
+			icon
+		});
+		
+		icon.unwrap_or_else(|| {
+			if filename.starts_with('.') {
+				Arc::from("󰘓") // nf-fa-eye_slash (hidden)
+			} else {
+				Arc::from("📄") // nf-fa-file_o or generic file emoji
+			}
+		})
+	}
+}
+
+/// Built-in icon for a lowercase file extension, if there is one
+fn extension_icon(extension: &str) -> Option<&'static str> {
+	let icon = match extension {
+		"rs" => "🦀",          // nf-dev-rust / Rust crab
+		"py" => "🐍",          // nf-dev-python / Python snake
+		"js" => "󰌞",          // nf-dev-javascript
+		"ts" => "󰛦",          // nf-dev-typescript
+		"html" | "htm" => "󰌝",// nf-dev-html5
+		"css" => "󰌜",         // nf-dev-css3
+		"json" => "󰘦",        // nf-mdi-code_json
+		"md" | "markdown" => "󰍔", // nf-dev-markdown
+		"txt" => "󰈙",         // nf-fa-file_text_o
+		"pdf" => "󰈦",         // nf-fa-file_pdf_o
+		"zip" | "tar" | "gz" | "rar" => "🗜️", // nf-fa-file_archive_o
+		"jpg" | "jpeg" | "png" | "gif" | "bmp" | "svg" => "🖼️", // nf-fa-file_image_o
+		"mp3" | "wav" | "flac" | "ogg" => "🎵", // nf-fa-file_audio_o
+		"mp4" | "mkv" | "avi" | "mov" => "🎬", // nf-fa-file_video_o
+		"exe" | "bin" => "⚙️",  // nf-mdi-application
+		"toml" | "yaml" | "yml" | "ini" | "conf" => "⚙️", // nf-mdi-settings
+		"c" | "h" => "󰙱",      // nf-custom-c
+		"cpp" | "cc" | "cxx" | "hpp" => "󰙲", // nf-custom-cpp
+		"java" => "󰬷",        // nf-dev-java
+		"php" => "󰌟",         // nf-dev-php
+		"rb" => "󰴭",          // nf-dev-ruby
+		"go" => "󰟓",          // nf-dev-go or "🐹" for gopher
+		"sh" | "bash" | "zsh" => "󰆍", // nf-dev-terminal
+		"sql" => "󰆼",         // nf-dev-database
+		"xml" => "󰗀",         // nf-mdi-xml
+		"log" => "󰌱",         // nf-fa-file_text_o
+		"lock" => "󰌾",        // nf-fa-lock
+		"dockerfile" => "🐳",  // Docker whale
+		"vue" => "󰡄",         // nf-mdi-vuejs
+		"react" | "jsx" | "tsx" => "󰜈", // nf-dev-react
+		"git" => "󰊢",         // nf-dev-git
+		"node" => "󰎙",        // nf-dev-nodejs_small
+		"npm" => "󰎙",         // nf-dev-nodejs_small
+		"yarn" => "󰬷",        // nf-seti-yarn
+		"docker" => "🐳",     // Docker whale
+		_ => return None,
+	};
+	Some(icon)
+}
+
+/// Normalize a `group_by` section label for `group_sort` lookups
+///
+/// `.jpg`, `jpg` and `JPG` all name the same extension group, and spaces in
+/// labels like `this week` or `char device` may be written as underscores.
+fn group_sort_label(label: &str) -> String {
+	label.trim_start_matches('.').to_lowercase().replace(' ', "_")
+}
+
+/// Sort entries by the configured key, keeping directories first when enabled
+fn sort_entries(entries: &mut [FileEntry], config: &Config) {
+	sort_entries_by(entries, config.sort_key, config);
+}
+
+/// Sort entries by the given key, with every other sort setting from the config
+fn sort_entries_by(entries: &mut [FileEntry], sort_key: SortKey, config: &Config) {
+	// Unsorted listings keep directory order, with no grouping or reversal
+	if sort_key == SortKey::None {
+		return;
+	}
+	entries.sort_by(|a, b| {
+		// Names equal once leading characters are ignored fall back to the full names
+		let by_name = || {
+			compare_names(sort_name(&a.name, config), sort_name(&b.name, config), config.collation)
+				.then_with(|| compare_names(&a.name, &b.name, config.collation))
+		};
+		let mut ordering = match sort_key {
+			SortKey::Name => by_name(),
+			SortKey::Size => a.size.cmp(&b.size)
+				.then_with(|| match config.size_tiebreak {
+					SizeTiebreak::Name => Ordering::Equal,
+					SizeTiebreak::Mtime => a.modified.cmp(&b.modified),
+					SizeTiebreak::Type => a.kind.rank().cmp(&b.kind.rank()),
+				})
+				.then_with(by_name),
+			SortKey::Time => a.modified.cmp(&b.modified).then_with(by_name),
+			SortKey::Accessed => a.accessed.cmp(&b.accessed).then_with(by_name),
+			SortKey::Owner => a.owner.cmp(&b.owner).then_with(by_name),
+			SortKey::Group => a.group.cmp(&b.group).then_with(by_name),
+			SortKey::Extension if config.group_related => {
+				let ((a_stem, a_extension), (b_stem, b_extension)) = (related_key(&a.name), related_key(&b.name));
+				a_extension.to_lowercase().cmp(&b_extension.to_lowercase())
+					.then_with(|| compare_names(a_stem, b_stem, config.collation))
+					.then_with(by_name)
+			},
+			SortKey::Extension => {
+				let extension = |name: &str| Path::new(name).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+				extension(&a.name).cmp(&extension(&b.name)).then_with(by_name)
+			},
+			SortKey::None => Ordering::Equal,
+		};
+		if config.reverse_sort {
+			ordering = ordering.reverse();
+		}
+		
+		// Hidden files are grouped outside the reversal too, but inside the directory grouping
+		let (a_hidden, b_hidden) = (a.name.starts_with('.'), b.name.starts_with('.'));
+		ordering = match (sort_key, config.dotfile_position) {
+			(SortKey::Name, DotfilePosition::First) => b_hidden.cmp(&a_hidden).then(ordering),
+			(SortKey::Name, DotfilePosition::Last) => a_hidden.cmp(&b_hidden).then(ordering),
+			_ => ordering,
+		};
+		
+		if config.sort_dirs_first {
+			// Directory grouping is applied outside the reversal so dirs stay on top,
+			// unless reversing is meant to flip them to the bottom as well
+			let grouping = b.is_dir.cmp(&a.is_dir);
+			if config.reverse_sort && !config.reverse_keeps_dirs_first {
+				grouping.reverse().then(ordering)
+			} else {
+				grouping.then(ordering)
+			}
+		} else {
+			ordering
+		}
+	});
+}
+
+/// Stem and first extension of a name, which related files share under `group_related`
+///
+/// Every extension after the first is stripped, so sidecars such as `main.rs.bak`
+/// and `photo.jpg.thumb` key like `main.rs` and `photo.jpg`. Leading dots
+/// belong to the stem, so `.bashrc` has no extension.
+fn related_key(name: &str) -> (&str, &str) {
+	let body_start = name.len() - name.trim_start_matches('.').len();
+	match name[body_start..].find('.') {
+		Some(dot) => {
+			let dot = body_start + dot;
+			let extension = &name[dot + 1..];
+			(&name[..dot], extension.split('.').next().unwrap_or(extension))
+		},
+		None => (name, ""),
+	}
+}
+
+/// Name used as the sort key: `sort_ignore_leading` characters are stripped, unless that leaves nothing
+fn sort_name<'a>(name: &'a str, config: &Config) -> &'a str {
+	match name.trim_start_matches(|c| config.sort_ignore_leading.contains(c)) {
+		"" => name,
+		stripped => stripped,
+	}
+}
+
+/// Compare two file names under the given collation
+fn compare_names(a: &str, b: &str, collation: Collation) -> Ordering {
+	match collation {
+		Collation::Ascii => a.to_lowercase().cmp(&b.to_lowercase()),
+		Collation::Natural => natural_cmp(&a.to_lowercase(), &b.to_lowercase()),
+		Collation::Bytes => a.as_bytes().cmp(b.as_bytes()),
+		Collation::Unicode => {
+			// Primary: base letters only, so "é" files sit next to "e" files
+			let primary = |name: &str| -> String {
+				name.nfd().filter(|&c| !is_combining_mark(c)).flat_map(char::to_lowercase).collect()
+			};
+			// Secondary: canonical form, so NFC and NFD spellings of a name compare equal
+			let secondary = |name: &str| -> String { name.nfc().flat_map(char::to_lowercase).collect() };
+			natural_cmp(&primary(a), &primary(b)).then_with(|| secondary(a).cmp(&secondary(b)))
+		},
+	}
+}
+
+/// Compare strings treating runs of ASCII digits as numbers
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let mut a = a.chars().peekable();
+	let mut b = b.chars().peekable();
+	loop {
+		match (a.peek().copied(), b.peek().copied()) {
+			(None, None) => return Ordering::Equal,
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+				let take_digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+					let mut digits = String::new();
+					while let Some(c) = chars.next_if(char::is_ascii_digit) {
+						digits.push(c);
+					}
+					digits
+				};
+				let x_digits = take_digits(&mut a);
+				let y_digits = take_digits(&mut b);
+				let x_value = x_digits.trim_start_matches('0');
+				let y_value = y_digits.trim_start_matches('0');
+				// Longer significant runs are bigger; equal values fall back to fewer leading zeros first
+				let ordering = x_value.len().cmp(&y_value.len())
+					.then_with(|| x_value.cmp(y_value))
+					.then_with(|| x_digits.len().cmp(&y_digits.len()));
+				if ordering != Ordering::Equal {
+					return ordering;
+				}
+			},
+			(Some(x), Some(y)) => {
+				if x != y {
+					return x.cmp(&y);
+				}
+				a.next();
+				b.next();
+			},
+		}
+	}
+}
+
+/// Position of the first `pin_names` pattern matching a name, if any
+fn pin_rank(name: &str, config: &Config) -> Option<usize> {
+	config.pin_names.iter().position(|pattern| glob_match(pattern, name))
+}
+
+/// Match a name against a shell-style glob supporting `*`, `?` and `[...]` classes
+///
+/// Works left to right, remembering only the most recent `*`: on a mismatch that
+/// star takes one more character and matching resumes after it. An earlier star
+/// never needs to take more, so matching takes at most pattern × name steps.
+fn glob_match(pattern: &str, name: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+	let (mut p, mut n) = (0, 0);
+	// Pattern position after the last `*`, and the name position it has consumed up to
+	let mut star: Option<(usize, usize)> = None;
+	
+	while n < name.len() {
+		if pattern.get(p) == Some(&'*') {
+			p += 1;
+			star = Some((p, n));
+			continue;
+		}
+		if let Some(length) = glob_token_match(&pattern[p..], name[n]) {
+			p += length;
+			n += 1;
+			continue;
+		}
+		let Some((after_star, consumed)) = star else {
+			return false;
+		};
+		star = Some((after_star, consumed + 1));
+		p = after_star;
+		n = consumed + 1;
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Length of the glob token starting `pattern` if it matches `c`: one for `?` and
+/// literals, the whole class for `[...]`; `None` if it doesn't match or there is none
+fn glob_token_match(pattern: &[char], c: char) -> Option<usize> {
+	match pattern.first()? {
+		'?' => Some(1),
+		'[' => {
+			let Some(close) = pattern.iter().skip(1).position(|&c| c == ']').map(|i| i + 1) else {
+				// Unterminated class, treat '[' literally
+				return (c == '[').then_some(1);
+			};
+			
+			let class = &pattern[1..close];
+			let (negated, class) = match class.first() {
+				Some('!') | Some('^') => (true, &class[1..]),
+				_ => (false, class),
+			};
+			let mut matched = false;
+			let mut i = 0;
+			while i < class.len() {
+				if i + 2 < class.len() && class[i + 1] == '-' {
+					matched |= class[i] <= c && c <= class[i + 2];
+					i += 3;
+				} else {
+					matched |= class[i] == c;
+					i += 1;
+				}
+			}
+			(matched != negated).then_some(close + 1)
+		},
+		&literal => (literal == c).then_some(1),
+	}
+}
+
+/// Main function - lists the requested directories (or the current one) with aligned columns
+fn main() -> std::io::Result<()> {
+	let started = Instant::now();
+	
+	// Load configuration, then let command line arguments override it
+	let mut config = Config::load();
+	let args: Vec<String> = env::args().skip(1).collect();
+	let mut directories = match config.apply_args(&args) {
+		Ok(directories) => directories,
+		Err(message) => {
+			eprintln!("yal: {}", message);
+			std::process::exit(2);
+		}
+	};
+	// Applied after every other source so its overrides always win
+	if config.stable {
+		config.apply_stable();
+	}
+	if config.no_metadata {
+		config.apply_no_metadata();
+	}
+	if config.explain_config {
+		print_config_origins(&config);
+		return Ok(());
+	}
+	if directories.is_empty() {
+		if !config.batch_file {
+			directories.push(logical_current_dir()?);
+		} else if config.select_mode {
+			return Ok(()); // An empty batch leaves nothing to choose from
+		}
+	}
+	if config.physical {
+		// Keep the path as given when it can't be resolved, so the error names it
+		for directory in &mut directories {
+			if let Ok(real) = fs::canonicalize(&*directory) {
+				*directory = real;
+			}
+		}
+	}
+	// Opened before colors are decided, so auto color sees a file like `> FILE` would
+	if let Some(path) = &config.output_file {
+		match fs::File::create(path) {
+			Ok(file) => {
+				let _ = OUTPUT.set(Mutex::new(Box::new(io::LineWriter::new(file))));
+			},
+			Err(error) => {
+				eprintln!("yal: cannot write '{}': {}", path.display(), error);
+				std::process::exit(2);
+			},
+		}
+	}
+	COLORS.get_or_init(|| config.color.enabled());
+	AMBIGUOUS_WIDTH.get_or_init(|| config.ambiguous_width);
+	FUZZY_LOCALE.get_or_init(|| config.fuzzy_locale());
+	if COLORS.get().copied().unwrap_or(false) {
+		// Merged once here, with any theme file's colors laid over its base
+		let palette: &'static Palette = match &config.theme_file {
+			Some(theme) => Box::leak(Box::new(theme.base.unwrap_or(config.theme).palette().with_theme(theme))),
+			None => config.theme.palette(),
+		};
+		PALETTE.get_or_init(|| palette);
+	}
+	if config.stats {
+		if let Ok(mut stats) = STATS.lock() {
+			*stats = Some(Stats::default());
+		}
+	}
+	if config.sort_key == SortKey::Accessed {
+		warn_unreliable_atime(&directories);
+	}
+	
+	// Create name cache for user/group resolution
+	let names_started = Instant::now();
+	let name_cache = NameCache::new(&config.passwd_file, &config.group_file);
+	record_phase(Phase::Names, names_started.elapsed());
+	let icons = IconResolver::new(&config);
+	
+	if config.diff_mode {
+		if directories.len() != 2 {
+			eprintln!("yal: --diff requires exactly two directories");
+			std::process::exit(2);
+		}
+		return diff_directories(&directories[0], &directories[1], &config, &name_cache, &icons);
+	}
+	
+	if config.complete_mode {
+		for directory in &directories {
+			for entry in collect_entries(directory, &config, &name_cache, &icons)? {
+				let _ = writeln!(Output, "{}{}", entry.name, if entry.is_dir { "/" } else { "" });
+			}
+		}
+		return Ok(());
+	}
+	
+	if config.select_mode {
+		if directories.len() > 1 {
+			eprintln!("yal: --select takes at most one directory");
+			std::process::exit(2);
+		}
+		let entries = collect_entries(&directories[0], &config, &name_cache, &icons)?;
+		match select_entries(&entries, &config, &directories[0])? {
+			Some(selected) => {
+				for path in selected {
+					let _ = writeln!(Output, "{}", path.display());
+				}
+				return Ok(());
+			},
+			None => std::process::exit(130), // Aborted, like an interrupted command
+		}
+	}
+	
+	// JSON lines can be written as each entry is read, so --stream keeps streaming them
+	if config.stream && config.output_format == OutputFormat::JsonLines && !config.flat {
+		let mut state = ListingState::default();
+		for directory in &directories {
+			list_stream(&RealFiles, directory, &config, &name_cache, &icons, &mut state);
+		}
+		print_stats(started);
+		if state.failed {
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+	
+	if config.output_format.is_machine_readable() {
+		let mut entries = Vec::new();
+		let mut sections = Vec::new();
+		let mut failed = false;
+		for directory in &directories {
+			let start = entries.len();
+			if config.flat {
+				entries.append(&mut collect_flat(directory, &config, &name_cache, &icons, &mut failed));
+			} else {
+				collect_tree(directory, &config, &name_cache, &icons, &mut entries, &mut failed);
+			}
+			sections.push((directory.clone(), start..entries.len()));
+		}
+		let format_started = Instant::now();
+		let _ = print_machine_readable(&mut io::BufWriter::new(Output), &entries, &sections, &config);
+		record_phase(Phase::Format, format_started.elapsed());
+		print_stats(started);
+		if failed {
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+	
+	let mut state = ListingState {
+		multiple: directories.len() > 1 || (config.recursive && !config.flat),
+		..ListingState::default()
+	};
+	for directory in &directories {
+		if config.dirs_sizes {
+			list_dirs_sizes(directory, &config, &mut state);
+		} else if config.tree {
+			list_tree(directory, &config, &name_cache, &icons, &mut state);
+		} else if config.flat {
+			list_flat(directory, &config, &name_cache, &icons, &mut state);
+		} else if config.stream {
+			list_stream(&RealFiles, directory, &config, &name_cache, &icons, &mut state);
+		} else {
+			list_recursive(directory, &config, &name_cache, &icons, &mut state);
+		}
+	}
+	
+	if config.show_legend {
+		if config.heatmap == Heatmap::Off {
+			eprintln!("yal: --legend has no effect without --heatmap");
+		} else {
+			print_line("");
+			print_legend(&config);
+		}
+	}
+	
+	if config.audit {
+		print_line("");
+		state.audit.print();
+	}
+	
+	if config.combined_total && state.combined.sections > 0 {
+		print_line("");
+		state.combined.print(&config);
+	}
+	
+	print_stats(started);
+	if state.failed {
+		std::process::exit(1);
+	}
+	
+	Ok(())
+}
+
+/// Collect the entries of a directory into one list, descending into subdirectories when recursive
+fn collect_tree(root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, entries: &mut Vec<FileEntry>, failed: &mut bool) {
+	walk_directories(root, config, |directory| match collect_entries(directory, config, name_cache, icons) {
+		Ok(mut collected) => {
+			let subdirectories = if config.recursive {
+				subdirectories(directory, &collected, config)
+			} else {
+				Vec::new()
+			};
+			if let Some(limit) = config.max_entries {
+				collected.truncate(limit);
+			}
+			entries.append(&mut collected);
+			subdirectories
+		},
+		Err(error) => {
+			eprintln!("yal: {}: {}", directory.display(), error);
+			*failed = true;
+			Vec::new()
+		}
+	});
+}
+
+/// Visit a directory and, when recursive, the subdirectories `visit` returns, in the configured traversal order
+fn walk_directories(root: &Path, config: &Config, mut visit: impl FnMut(&Path) -> Vec<PathBuf>) {
+	let mut pending = VecDeque::from([root.to_path_buf()]);
+	while let Some(directory) = pending.pop_front() {
+		let subdirectories = visit(&directory);
+		if !config.recursive {
+			break;
+		}
+		match config.traversal {
+			// Children go ahead of the remaining siblings, keeping their own order
+			Traversal::DepthFirst => {
+				for subdirectory in subdirectories.into_iter().rev() {
+					pending.push_front(subdirectory);
+				}
+			},
+			Traversal::BreadthFirst => pending.extend(subdirectories),
+		}
+	}
+}
+
+/// Progress shared across every directory listed in one run
+#[derive(Debug, Default)]
+struct ListingState {
+	/// Whether several sections are printed, so each needs its own header
+	multiple: bool,
+	/// Number of sections printed so far
+	sections: usize,
+	/// Whether any directory failed to list
+	failed: bool,
+	/// Audit findings accumulated across sections
+	audit: AuditSummary,
+	/// Entries and sizes accumulated across sections for `--combined-total`
+	combined: CombinedTotal,
+}
+
+/// Running totals of every listed section, for the `--combined-total` line
+#[derive(Debug, Default)]
+struct CombinedTotal {
+	sections: u64,
+	entries: u64,
+	bytes: u64,
+}
+
+impl CombinedTotal {
+	/// Count the entries a section showed, with sizes counted as in `summary_line`
+	fn record(&mut self, entries: &[FileEntry], config: &Config) {
+		self.sections += 1;
+		self.entries += entries.len() as u64;
+		self.bytes += entries.iter()
+			.filter(|entry| config.recursive_size || !entry.is_dir)
+			.map(|entry| entry.size)
+			.sum::<u64>();
+	}
+	
+	/// Print the grand total, e.g. ` Combined: 3 directories, 120 entries, 4.5M total`
+	fn print(&self, config: &Config) {
+		let directories = if self.sections == 1 { "directory" } else { "directories" };
+		let entries = if self.entries == 1 { "entry" } else { "entries" };
+		print_line(&format!(
+			" \x1b[1mCombined:\x1b[0m {} {}, {} {}, {}{}\x1b[0m total",
+			config.format_number(self.sections), directories, config.format_number(self.entries), entries,
+			palette().size, config.format_bytes(self.bytes)
+		));
+	}
+}
+
+/// List a directory and, in recursive mode, each of its subdirectories in turn
+fn list_recursive(root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) {
+	walk_directories(root, config, |directory| match list_directory(directory, config, name_cache, icons, state) {
+		Ok(subdirectories) => subdirectories,
+		Err(error) => {
+			eprintln!("yal: {}: {}", directory.display(), error);
+			state.failed = true;
+			Vec::new()
+		}
+	});
+}
+
+/// List a single directory: collect, sort and display its entries
+///
+/// Returns the subdirectories (not following symlinks) for recursive listing.
+fn list_directory(directory: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) -> std::io::Result<Vec<PathBuf>> {
+	let (mut file_entries, omitted) = match collect_counted_entries(directory, config, name_cache, icons)? {
+		Collected::Entries(entries, omitted) => (entries, omitted),
+		// Sorting and aligning need every entry in memory, so huge directories are streamed instead
+		Collected::OverThreshold(limit) => {
+			eprintln!("yal: {}: {}; listing them unsorted as they are read", directory.display(), over_threshold_message(limit, config));
+			return Ok(stream_directory(&RealFiles, directory, config, name_cache, icons, state));
+		},
+	};
+	let subdirectories = if config.recursive {
+		subdirectories(directory, &file_entries, config)
+	} else {
+		Vec::new()
+	};
+	
+	let limited;
+	let mut paged = false;
+	let config = match large_listing_choice(directory, file_entries.len(), config) {
+		// Skipped at the prompt; recursion still continues below it
+		None => return Ok(subdirectories),
+		Some(LargeListing::Off | LargeListing::Prompt) => config,
+		Some(LargeListing::Page) => {
+			paged = true;
+			config
+		},
+		Some(LargeListing::Truncate) => {
+			let limit = config.max_entries.map_or(config.large_listing_threshold, |limit| limit.min(config.large_listing_threshold));
+			limited = Config { max_entries: Some(limit), ..config.clone() };
+			&limited
+		},
+	};
+	
+	let smart;
+	let config = if config.smart_defaults != SmartDefaults::default() {
+		smart = smart_config(directory, &file_entries, config);
+		if smart.sort_key != config.sort_key || smart.reverse_sort != config.reverse_sort {
+			file_entries = order_entries(file_entries, &smart);
+		}
+		&smart
+	} else {
+		config
+	};
+	let format_started = Instant::now();
+	if paged {
+		let section = || print_section(directory, file_entries, &omitted, config, state);
+		if let Err(error) = with_pager(section) {
+			eprintln!("yal: cannot start pager: {}", error);
+		}
+	} else {
+		print_section(directory, file_entries, &omitted, config, state);
+	}
+	record_phase(Phase::Format, format_started.elapsed());
+	Ok(subdirectories)
+}
+
+/// Entries a directory can have before `large_listing` steps in
+const DEFAULT_LARGE_LISTING_THRESHOLD: usize = 1000;
+
+/// How to show a directory of `count` entries, asking first with `prompt`; `None` skips it
+///
+/// Only applies when the listing goes to a terminal, so pipes and `--output` files always get everything.
+fn large_listing_choice(directory: &Path, count: usize, config: &Config) -> Option<LargeListing> {
+	if config.large_listing == LargeListing::Off
+		|| count <= config.large_listing_threshold
+		|| !output_is_terminal()
+	{
+		return Some(LargeListing::Off);
+	}
+	if config.large_listing != LargeListing::Prompt {
+		return Some(config.large_listing);
+	}
+	
+	let Ok(tty) = fs::File::open("/dev/tty") else {
+		return Some(LargeListing::Off);
+	};
+	eprint!(
+		"yal: {} has {} entries. Show [a]ll, [p]age, [t]runcate to {}, or [s]kip? ",
+		display_path(directory, config), config.format_number(count as u64), config.format_number(config.large_listing_threshold as u64)
+	);
+	let mut answer = String::new();
+	let _ = BufReader::new(tty).read_line(&mut answer);
+	match answer.trim().to_lowercase().chars().next() {
+		Some('p') => Some(LargeListing::Page),
+		Some('t') => Some(LargeListing::Truncate),
+		Some('s' | 'q' | 'n') => None,
+		_ => Some(LargeListing::Off),
+	}
+}
+
+/// Run `print` with stdout piped into `$PAGER` (default `less -R`), then restore stdout
+///
+/// Quitting the pager early only makes the remaining writes of `print` fail, which
+/// `print_line` ignores.
+fn with_pager(print: impl FnOnce()) -> io::Result<()> {
+	use std::os::fd::AsRawFd;
+	use std::process::{Command, Stdio};
+	
+	let pager = env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty()).unwrap_or_else(|| "less -R".to_string());
+	let mut child = Command::new("sh").arg("-c").arg(&pager).stdin(Stdio::piped()).spawn()?;
+	let Some(input) = child.stdin.take() else {
+		return Err(io::Error::other("pager has no input"));
+	};
+	
+	io::stdout().flush()?;
+	let saved = unsafe { libc::dup(libc::STDOUT_FILENO) };
+	if saved == -1 || unsafe { libc::dup2(input.as_raw_fd(), libc::STDOUT_FILENO) } == -1 {
+		return Err(io::Error::last_os_error());
+	}
+	// Only stdout holds the pipe now, so restoring it tells the pager the input ended
+	drop(input);
+	print();
+	let _ = io::stdout().flush();
+	unsafe {
+		libc::dup2(saved, libc::STDOUT_FILENO);
+		libc::close(saved);
+	}
+	child.wait()?;
+	Ok(())
+}
+
+/// Directory names that usually collect downloads, where the newest files matter most
+const DOWNLOAD_FOLDERS: [&str; 5] = ["downloads", "download", "desktop", "tmp", "temp"];
+
+/// Copy of the config adjusted by the enabled smart-default heuristics for one directory
+///
+/// A heuristic never overrides an option the user set explicitly.
+fn smart_config(directory: &Path, entries: &[FileEntry], config: &Config) -> Config {
+	let smart = config.smart_defaults;
+	let mut adjusted = config.clone();
+	
+	// Many short names read better reflowed than as one long column
+	if smart.grid && !config.is_explicit(&["output_format", "column_format"]) && entries.len() > 30 {
+		let longest = entries.iter().map(|entry| display_width(&entry.name)).max().unwrap_or(0);
+		if longest <= 16 {
+			adjusted.output_format = OutputFormat::Grid;
+		}
+	}
+	
+	if smart.fit && !config.is_explicit(&["fit_columns"]) && terminal_width() < 80 {
+		adjusted.fit_columns = true;
+	}
+	
+	// Resolve "." and ".." so `yal` inside ~/Downloads is recognized too
+	let folder = fs::canonicalize(directory)
+		.ok()
+		.and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_lowercase()));
+	if smart.time_sort && !config.is_explicit(&["sort", "reverse"]) && folder.is_some_and(|name| DOWNLOAD_FOLDERS.contains(&name.as_str())) {
+		adjusted.sort_key = SortKey::Time;
+		adjusted.reverse_sort = true;
+	}
+	
+	adjusted
+}
+
+/// List every file below a directory as one flat, globally sorted section
+fn list_flat(root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) {
+	if let Err(error) = fs::read_dir(root) {
+		eprintln!("yal: {}: {}", root.display(), error);
+		state.failed = true;
+		return;
+	}
+	let entries = collect_flat(root, config, name_cache, icons, &mut state.failed);
+	let format_started = Instant::now();
+	print_section(root, entries, &Omitted::default(), config, state);
+	record_phase(Phase::Format, format_started.elapsed());
+}
+
+/// Entries written between flushes in stream mode
+const STREAM_FLUSH_EVERY: usize = 256;
+
+/// List a directory by printing each entry as soon as it is read, without sorting
+///
+/// Nothing is collected, so huge directories start printing at once and use
+/// little memory. Widths can't be known up front, so lines use the simple
+/// format, and options that need the whole directory (size bars, inode dedup,
+/// recursive sizes, the total line) are skipped. Recursion follows read order.
+fn list_stream(source: &dyn FileSource, root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) {
+	walk_directories(root, config, |directory| stream_directory(source, directory, config, name_cache, icons, state));
+}
+
+/// Stream the entries of one directory, returning its subdirectories in read order
+fn stream_directory(source: &dyn FileSource, directory: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) -> Vec<PathBuf> {
+	let entries = match source.read_dir(directory) {
+		Ok(entries) => entries,
+		Err(error) => {
+			eprintln!("yal: {}: {}", directory.display(), error);
+			state.failed = true;
+			return Vec::new();
+		}
+	};
+	let pseudo_fs = is_pseudo_filesystem(directory);
+	let recurse_hidden = config.recurse_hidden.unwrap_or(config.show_hidden);
+	// JSON lines carry each entry's path, so they need no headers and are flushed one by one
+	let json_lines = config.output_format == OutputFormat::JsonLines;
+	let format = |entry: &FileEntry| if json_lines { json_object(entry) } else { entry.format_simple(config, 2) };
+	
+	if !json_lines {
+		if state.sections > 0 {
+			print_line("");
+		}
+		state.sections += 1;
+		print_header(&format!(" {}", display_path(directory, config)));
+		if config.header_blank_line {
+			print_header("");
+		}
+	}
+	
+	let mut out = io::BufWriter::new(Output);
+	let mut subdirectories = Vec::new();
+	let mut printed = 0;
+	if config.show_dot_entries && config.name_prefix.is_none() {
+		for dot_entry in dot_entries(directory, name_cache, icons, config) {
+			let _ = write_line(&mut out, &format(&dot_entry));
+		}
+	}
+	for entry in entries.map_while(Result::ok) {
+		let file_name = &entry.name;
+		if config.ignore_patterns.iter().any(|pattern| glob_match(pattern, file_name)) {
+			continue;
+		}
+		let hidden = file_name.starts_with('.');
+		let is_dir = entry.kind == Some(FileKind::Directory);
+		if config.recursive && (recurse_hidden || !hidden) && is_dir {
+			subdirectories.push(entry.path.clone());
+		}
+		
+		if config.max_entries.is_some_and(|limit| printed >= limit)
+			|| !is_listed(file_name, config, &mut Omitted::default())
+			|| !(is_dir || passes_filter(file_name, config))
+		{
+			continue;
+		}
+		
+		let file_entry = match FileEntry::new(&entry, source, name_cache, icons, config, pseudo_fs) {
+			Ok(file_entry) => file_entry,
+			Err(error) => {
+				report_stat_error(&entry.path, &error, config);
+				if !pseudo_fs && error.kind() == io::ErrorKind::NotFound {
+					continue;
+				}
+				FileEntry::unreadable(&entry, icons, config)
+			},
+		};
+		if config.newer_than.is_some_and(|reference| file_entry.modified.is_none_or(|modified| modified <= reference))
+			|| (config.skip_special && file_entry.kind.is_special())
+		{
+			continue;
+		}
+		
+		// Icons are one or two cells wide, so a fixed width of two keeps names in line
+		if write_line(&mut out, &format(&file_entry)).is_err() {
+			return Vec::new(); // Closed pipe, e.g. piped into head
+		}
+		state.audit.record(&file_entry.audit_flags);
+		printed += 1;
+		if (json_lines || printed % STREAM_FLUSH_EVERY == 0) && out.flush().is_err() {
+			return Vec::new();
+		}
+	}
+	let _ = out.flush();
+	subdirectories
+}
+
+/// Directory and file totals printed under a tree
+#[derive(Default)]
+struct TreeCounts {
+	directories: usize,
+	files: usize,
+	/// Directories the tree would have entered but for `--max-depth`
+	beyond_depth: usize,
+}
+
+/// Print a directory as an indented tree, like `tree`
+fn list_tree(root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, state: &mut ListingState) {
+	if state.sections > 0 {
+		print_line("");
+	}
+	state.sections += 1;
+	
+	print_header(&format!(" {}", display_path(root, config)));
+	let mut counts = TreeCounts::default();
+	let mut branch = TreeBranch {
+		real_paths: vec![tree_real_path(root, config)],
+		..TreeBranch::default()
+	};
+	print_tree_level(root, &mut branch, config, name_cache, icons, &mut counts, state);
+	
+	print_line("");
+	let directories = if counts.directories == 1 { "directory" } else { "directories" };
+	let files = if counts.files == 1 { "file" } else { "files" };
+	let mut totals = if config.tree_dirs_only {
+		format!(" {} {}", config.format_number(counts.directories as u64), directories)
+	} else {
+		format!(" {} {}, {} {}", config.format_number(counts.directories as u64), directories, config.format_number(counts.files as u64), files)
+	};
+	// Says the tree is incomplete, so nobody takes the cut-off levels for empty ones
+	if counts.beyond_depth > 0 {
+		let not_entered = if counts.beyond_depth == 1 { "directory" } else { "directories" };
+		totals.push_str(&format!(" {}({} {} not entered, beyond --max-depth {})\x1b[0m",
+			palette().muted, config.format_number(counts.beyond_depth as u64), not_entered, config.max_depth.unwrap_or(0)));
+	}
+	print_line(&totals);
+}
+
+/// Print each immediate subdirectory of `root` with its recursive size, largest first
+///
+/// A one-level `du | sort -h`: subdirectories are walked in parallel and
+/// totals marked partial when something below couldn't be read.
+fn list_dirs_sizes(root: &Path, config: &Config, state: &mut ListingState) {
+	let entries = match fs::read_dir(root) {
+		Ok(entries) => entries,
+		Err(error) => {
+			eprintln!("yal: {}: {}", root.display(), error);
+			state.failed = true;
+			return;
+		}
+	};
+	let mut omitted = Omitted::default();
+	let directories: Vec<(String, PathBuf)> = entries.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+		.map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+		.filter(|(name, _)| is_listed(name, config, &mut omitted))
+		.collect();
+	
+	let progress = Mutex::new(Progress::new(config));
+	let next = atomic::AtomicUsize::new(0);
+	let threads = config.max_threads.min(directories.len()).max(1);
+	let mut sizes: Vec<(&str, SubtreeSize)> = std::thread::scope(|scope| {
+		let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+			let mut sizes = Vec::new();
+			while let Some((name, path)) = directories.get(next.fetch_add(1, atomic::Ordering::Relaxed)) {
+				sizes.push((name.as_str(), subtree_size(path, &progress)));
+			}
+			sizes
+		})).collect();
+		workers.into_iter()
+			.flat_map(|worker| worker.join().expect("size worker panicked"))
+			.collect()
+	});
+	progress.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+	sizes.sort_by(|(a_name, a), (b_name, b)| b.bytes.cmp(&a.bytes).then_with(|| a_name.cmp(b_name)));
+	
+	if state.sections > 0 {
+		print_line("");
+	}
+	state.sections += 1;
+	print_header(&format!(" {}{}", display_path(root, config), omitted.note(config)));
+	
+	let palette = palette();
+	let largest = sizes.first().map_or(0, |(_, size)| size.bytes);
+	let texts: Vec<String> = sizes.iter().map(|(_, size)| config.format_bytes(size.bytes)).collect();
+	let width = texts.iter().map(|text| text.len()).max().unwrap_or(0);
+	for ((name, size), text) in sizes.iter().zip(&texts) {
+		let mut line = format!(" {}{:>width$}\x1b[0m", palette.size, text);
+		if config.show_size_bars {
+			line.push_str(&format!(" {}{}\x1b[0m", palette.accent, size_bar(size.bytes, largest, config.size_bar_width)));
+		}
+		line.push_str(&format!("  {}{}/\x1b[0m", palette.directory, name));
+		if size.partial {
+			line.push_str(&format!(" {}(partial)\x1b[0m", palette.muted));
+		}
+		print_line(&line);
+	}
+	
+	let total = sizes.iter().fold(SubtreeSize::default(), |total, (_, size)| SubtreeSize {
+		bytes: total.bytes + size.bytes,
+		partial: total.partial || size.partial,
+	});
+	let directories = if sizes.len() == 1 { "directory" } else { "directories" };
+	print_line("");
+	print_line(&format!(" {} in {} {}{}", config.format_bytes(total.bytes), config.format_number(sizes.len() as u64), directories,
+		if total.partial { " (partial)" } else { "" }));
+}
+
+/// Print the entries of one tree level, recursing into subdirectories
+///
+/// `ancestors` holds, for each enclosing level, whether more siblings follow it,
+/// which decides between a `│` continuation and blank space in the prefix.
+fn print_tree_level(directory: &Path, branch: &mut TreeBranch, config: &Config, name_cache: &NameCache, icons: &IconResolver, counts: &mut TreeCounts, state: &mut ListingState) {
+	let mut entries = match collect_entries(directory, config, name_cache, icons) {
+		Ok(entries) => entries,
+		Err(error) => {
+			eprintln!("yal: {}: {}", directory.display(), error);
+			state.failed = true;
+			return;
+		}
+	};
+	// Like `tree -a`, the tree shows hidden files but never `.` and `..`
+	entries.retain(|entry| !entry.is_dot_entry());
+	if config.tree_dirs_only {
+		// Links to directories only belong on the map when they are followed
+		entries.retain(|entry| {
+			(entry.is_dir && !entry.is_symlink)
+				|| (config.tree_follow_symlinks > 0 && entry.is_symlink && fs::metadata(&entry.path).is_ok_and(|metadata| metadata.is_dir()))
+		});
+	}
+	
+	let recurse_hidden = config.recurse_hidden.unwrap_or(config.show_hidden);
+	let can_descend = config.max_depth.is_none_or(|depth| branch.more_below.len() + 1 < depth);
+	let prefix = tree_prefix(&branch.more_below, config.tree_max_prefix_width);
+	for (index, entry) in entries.iter().enumerate() {
+		let last = index + 1 == entries.len();
+		let connector = if last { "└── " } else { "├── " };
+		let icon = if config.show_icons { format!("{} ", entry.icon) } else { String::new() };
+		let mut line = format!("{}{}{}{}", prefix, connector, icon, entry.name_cell(config));
+		state.audit.record(&entry.audit_flags);
+		
+		// A followed link shows where it leads, since its branch lives somewhere else
+		let link_target = (config.tree_follow_symlinks > 0 && entry.is_symlink)
+			.then(|| fs::canonicalize(&entry.path).ok().filter(|real| real.is_dir()))
+			.flatten();
+		let cycle = link_target.as_ref().is_some_and(|real| branch.real_paths.contains(real));
+		if let Some(real) = &link_target {
+			line.push_str(&format!(" {}⇒ {}\x1b[0m", palette().accent, real.display()));
+			if cycle {
+				line.push_str(" \x1b[31m[cycle]\x1b[0m");
+			}
+		}
+		print_line(&line);
+		
+		let hidden = entry.name.starts_with('.');
+		if entry.is_dir && !entry.is_symlink {
+			counts.directories += 1;
+			if (recurse_hidden || !hidden) && can_descend {
+				branch.enter(!last, tree_real_path(&entry.path, config), false);
+				print_tree_level(&entry.path, branch, config, name_cache, icons, counts, state);
+				branch.leave(false);
+			} else if recurse_hidden || !hidden {
+				counts.beyond_depth += 1;
+			}
+		} else if let Some(real) = link_target {
+			counts.directories += 1;
+			let followed = !cycle && branch.symlinks < config.tree_follow_symlinks && (recurse_hidden || !hidden);
+			if followed && can_descend {
+				branch.enter(!last, real, true);
+				print_tree_level(&entry.path, branch, config, name_cache, icons, counts, state);
+				branch.leave(true);
+			} else if followed {
+				counts.beyond_depth += 1;
+			}
+		} else if entry.is_dir {
+			counts.directories += 1; // A symlinked directory under -L, never descended
+		} else {
+			counts.files += 1;
+		}
+	}
+}
+
+/// Symlinked directories `--follow-symlink-dirs-in-tree` follows along one branch
+const DEFAULT_TREE_FOLLOW_SYMLINKS: usize = 3;
+
+/// Where the tree recursion is: the drawn levels, plus what following symlinks needs
+#[derive(Default)]
+struct TreeBranch {
+	/// Whether each enclosing level has more entries below, which draws its `│`
+	more_below: Vec<bool>,
+	/// Real paths of the directories from the root down, to catch links back into the branch
+	real_paths: Vec<PathBuf>,
+	/// How many symlinked directories the branch went through
+	symlinks: usize,
+}
+
+impl TreeBranch {
+	/// Step into a directory, reached through a symlink or not
+	fn enter(&mut self, more_below: bool, real_path: PathBuf, via_symlink: bool) {
+		self.more_below.push(more_below);
+		self.real_paths.push(real_path);
+		self.symlinks += via_symlink as usize;
+	}
+	
+	/// Step back out of the directory entered last
+	fn leave(&mut self, via_symlink: bool) {
+		self.more_below.pop();
+		self.real_paths.pop();
+		self.symlinks -= via_symlink as usize;
+	}
+}
+
+/// Real path of a tree directory for cycle checks, only resolved when links are followed
+fn tree_real_path(path: &Path, config: &Config) -> PathBuf {
+	if config.tree_follow_symlinks > 0 {
+		fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+	} else {
+		path.to_path_buf()
+	}
+}
+
+/// Indentation before a tree connector, condensed to `…N` plus the innermost levels once it would exceed `max_width`
+fn tree_prefix(ancestors: &[bool], max_width: usize) -> String {
+	const SEGMENT_WIDTH: usize = 4;
+	let segment = |&more: &bool| if more { "│   " } else { "    " };
+	
+	// The limit covers the prefix plus the connector that follows it
+	if max_width == 0 || (ancestors.len() + 1) * SEGMENT_WIDTH <= max_width {
+		return ancestors.iter().map(segment).collect();
+	}
+	
+	// A fixed-width indicator keeps condensed levels aligned with each other
+	let indicator = pad_to_display_width(&format!("\x1b[90m…{}\x1b[0m", ancestors.len()), SEGMENT_WIDTH);
+	let room = max_width.saturating_sub(display_width(&indicator) + SEGMENT_WIDTH) / SEGMENT_WIDTH;
+	let kept = &ancestors[ancestors.len() - room.min(ancestors.len())..];
+	let segments: String = kept.iter().map(segment).collect();
+	format!("{}{}", indicator, segments)
+}
+
+/// Print one directory section: separator, header, entries and any truncation notice
+fn print_section(directory: &Path, mut file_entries: Vec<FileEntry>, omitted: &Omitted, config: &Config, state: &mut ListingState) {
+	// Always separate sections, even when the header blank line is disabled
+	if state.sections > 0 {
+		print_line("");
+	}
+	state.sections += 1;
+	
+	let note = if config.show_filtered_count { omitted.note(config) } else { String::new() };
+	if file_entries.is_empty() {
+		if state.multiple {
+			print_header(&format!(" {}: Empty directory{}", display_path(directory, config), note));
+		} else {
+			print_header(&format!(" Empty directory{}", note));
+		}
+		state.combined.record(&[], config);
+		return;
+	}
+	
+	// Display header
+	print_header(&format!(" {} ({} items){}", display_path(directory, config), config.format_number(file_entries.len() as u64), note));
+	if config.header_blank_line {
+		print_header("");
+	}
+	
+	// Like `ls -l`, the total covers every entry, including ones cut by the entry limit,
+	// and each entry is rounded up to whole blocks (1K unless --block-size says otherwise)
+	if config.show_total {
+		let block_size = config.block_size.unwrap_or(1024);
+		let total: u64 = file_entries.iter().map(|entry| (entry.blocks * 512).div_ceil(block_size)).sum();
+		print_line(&format!("total {}", config.format_number(total)));
+	}
+	
+	// Apply the entry limit after sorting so the most relevant entries remain
+	let hidden_count = match config.max_entries {
+		Some(limit) if file_entries.len() > limit => {
+			let hidden = file_entries.len() - limit;
+			file_entries.truncate(limit);
+			hidden
+		},
+		_ => 0,
+	};
+	
+	// Columns with one value for every entry are said once below the listing instead
+	let mut uniform = Vec::new();
+	let deduplicated;
+	let config = if config.auto_hide_uniform_columns
+		&& file_entries.len() > 1
+		&& matches!(config.output_format, OutputFormat::Columns | OutputFormat::Simple | OutputFormat::Table)
+	{
+		uniform = uniform_columns(&file_entries, config);
+		let mut trimmed = config.clone();
+		trimmed.column_order.retain(|column| !uniform.iter().any(|(hidden, _)| hidden == column));
+		deduplicated = trimmed;
+		&deduplicated
+	} else {
+		config
+	};
+	
+	// Drop low-priority columns on narrow terminals
+	let fitted;
+	let config = if config.fit_columns && matches!(config.output_format, OutputFormat::Columns | OutputFormat::Table) {
+		fitted = fit_columns(&file_entries, config, terminal_width());
+		&fitted
+	} else {
+		config
+	};
+	
+	// Cut names just enough for lines to fit the terminal, once the other columns are known
+	let budgeted;
+	let config = if config.max_name_width == MaxNameWidth::Auto
+		&& matches!(config.output_format, OutputFormat::Columns | OutputFormat::Simple | OutputFormat::Table)
+		&& output_is_terminal()
+	{
+		budgeted = with_name_budget(&file_entries, config, terminal_width());
+		&budgeted
+	} else {
+		config
+	};
+	
+	// Calculate column widths for perfect alignment
+	let widths = ColumnWidths::compute(&file_entries, config);
+	
+	if config.group_by == GroupBy::None {
+		print_entries(&file_entries, config, &widths);
+	} else {
+		// Grouping after the entry limit keeps the most relevant entries; the stable
+		// sort keeps the configured order within each group
+		file_entries.sort_by_cached_key(|entry| entry.group_key(config));
+		let mut groups = Vec::new();
+		let mut start = 0;
+		while let Some(first) = file_entries.get(start) {
+			let key = first.group_key(config);
+			let length = file_entries[start..].iter().position(|entry| entry.group_key(config) != key).unwrap_or(file_entries.len() - start);
+			groups.push((key.1, start..start + length));
+			start += length;
+		}
+		
+		for (index, (label, range)) in groups.into_iter().enumerate() {
+			let group = &mut file_entries[range];
+			if let Some(&sort_key) = config.group_sort.get(&group_sort_label(&label)) {
+				sort_entries_by(group, sort_key, config);
+				// Pinned names still lead their group, in pin order
+				group.sort_by_key(|entry| pin_rank(&entry.name, config).unwrap_or(usize::MAX));
+			}
+			if index > 0 {
+				print_line(""); // Blank line between groups
+			}
+			print_line(&format!("\x1b[1m{}\x1b[0m {}({})\x1b[0m", label, palette().muted, config.format_number(group.len() as u64)));
+			print_entries(group, config, &widths);
+		}
+	}
+	
+	if hidden_count > 0 {
+		print_line(&format!("\x1b[90m… and {} more\x1b[0m", config.format_number(hidden_count as u64)));
+	}
+	
+	// Only the entries shown above are counted, so the summary follows every filter and limit
+	let uniform = uniform_note(&uniform);
+	if config.show_summary || uniform.is_some() {
+		print_line("");
+	}
+	if config.show_summary {
+		print_line(&summary_line(&file_entries, config));
+	}
+	if let Some(uniform) = uniform {
+		print_line(&format!(" {}{}\x1b[0m", palette().muted, uniform));
+	}
+	
+	for entry in &file_entries {
+		state.audit.record(&entry.audit_flags);
+	}
+	state.combined.record(&file_entries, config);
+}
+
+/// Metadata columns showing the same text for every entry, with that text, in display order
+///
+/// The name and the decorative columns (icon, size bar, share) are never hidden.
+/// Columns blank for every entry, like the owner under `hide_own_owner`, are
+/// hidden too but have nothing to note.
+fn uniform_columns(entries: &[FileEntry], config: &Config) -> Vec<(String, String)> {
+	visible_columns(config)
+		.into_iter()
+		.filter(|column| !matches!(*column, "name" | "icon" | "size_bar" | "percent"))
+		.filter_map(|column| {
+			let first = strip_ansi(&entries.first()?.cell(column, config));
+			entries.iter()
+				.all(|entry| strip_ansi(&entry.cell(column, config)) == first)
+				.then(|| (column.to_string(), first))
+		})
+		.collect()
+}
+
+/// Line noting the values of hidden uniform columns, e.g. `Same for all entries: owner alice, group staff`
+///
+/// Columns that were blank for every entry have nothing to note.
+fn uniform_note(uniform: &[(String, String)]) -> Option<String> {
+	let values: Vec<String> = uniform.iter()
+		.filter(|(_, value)| !value.trim().is_empty())
+		.map(|(column, value)| format!("{} {}", column_title(column).to_lowercase(), value.trim()))
+		.collect();
+	(!values.is_empty()).then(|| format!("Same for all entries: {}", values.join(", ")))
+}
+
+/// One-line breakdown of entries by kind with their total size, e.g. `3 dirs, 12 files, 450K total`
+///
+/// Directory sizes only count towards the total with `recursive_size`, where
+/// they cover their contents; otherwise only the size of the directory inode
+/// would be added.
+fn summary_line(entries: &[FileEntry], config: &Config) -> String {
+	let count = |kinds: &[FileKind]| entries.iter().filter(|entry| kinds.contains(&entry.kind)).count() as u64;
+	let groups = [
+		(count(&[FileKind::Directory]), "dir", palette().directory),
+		(count(&[FileKind::File]), "file", ""),
+		(count(&[FileKind::Symlink]), "symlink", palette().accent),
+		(count(&[FileKind::Fifo, FileKind::Socket, FileKind::CharDevice, FileKind::BlockDevice]), "special file", palette().muted),
+	];
+	let total: u64 = entries.iter()
+		.filter(|entry| config.recursive_size || !entry.is_dir)
+		.map(|entry| entry.size)
+		.sum();
+	
+	let mut parts: Vec<String> = groups.iter()
+		.filter(|(count, _, _)| *count > 0)
+		.map(|&(count, unit, color)| {
+			let unit = if count == 1 { unit.to_string() } else { format!("{}s", unit) };
+			format!("{}{}\x1b[0m {}", color, config.format_number(count), unit)
+		})
+		.collect();
+	parts.push(format!("{}{}\x1b[0m total", palette().size, config.format_bytes(total)));
+	format!(" {}", parts.join(", "))
+}
+
+/// Print entries in the configured layout, aligned to `widths`
+fn print_entries(entries: &[FileEntry], config: &Config, widths: &ColumnWidths) {
+	if config.output_format == OutputFormat::Table {
+		for line in format_table(entries, config, widths) {
+			print_line(&line);
+		}
+	} else if config.output_format == OutputFormat::Grid {
+		for line in format_grid(entries, config, terminal_width()) {
+			print_line(&line);
+		}
+	} else {
+		for entry in entries {
+			print_line(&entry.format_display(config, widths));
+		}
+	}
+}
+
+/// Phases of a listing timed by `--stats`
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+	/// Opening and iterating directories
+	Read,
+	/// Building entries, including their metadata syscalls
+	Metadata,
+	/// Loading user and group names
+	Names,
+	/// Ordering entries
+	Sort,
+	/// Laying out and printing the listing
+	Format,
+}
+
+impl Phase {
+	/// Every phase in the order they are reported
+	const ALL: [Phase; 5] = [Phase::Read, Phase::Metadata, Phase::Names, Phase::Sort, Phase::Format];
+	
+	/// Label used in the stats summary
+	fn label(self) -> &'static str {
+		match self {
+			Phase::Read => "read",
+			Phase::Metadata => "metadata",
+			Phase::Names => "names",
+			Phase::Sort => "sort",
+			Phase::Format => "format",
+		}
+	}
+}
+
+/// Time spent per phase and entries built, accumulated across every directory
+#[derive(Debug, Default)]
+struct Stats {
+	times: [Duration; 5],
+	entries: usize,
+}
+
+/// Stats for `--stats`, `None` unless it was requested in main
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+/// Add time spent in a phase when `--stats` is active
+fn record_phase(phase: Phase, elapsed: Duration) {
+	if let Ok(mut stats) = STATS.lock() {
+		if let Some(stats) = stats.as_mut() {
+			stats.times[phase as usize] += elapsed;
+		}
+	}
+}
+
+/// Count entries built when `--stats` is active
+fn record_entries(count: usize) {
+	if let Ok(mut stats) = STATS.lock() {
+		if let Some(stats) = stats.as_mut() {
+			stats.entries += count;
+		}
+	}
+}
+
+/// Print the `--stats` summary to stderr, with the total measured from `started`
+fn print_stats(started: Instant) {
+	let Ok(stats) = STATS.lock() else { return };
+	let Some(stats) = stats.as_ref() else { return };
+	let phases: Vec<String> = Phase::ALL.iter()
+		.map(|&phase| format!("{} {:.1?}", phase.label(), stats.times[phase as usize]))
+		.collect();
+	eprintln!("yal: stats: {}, total {:.1?} ({} entries)", phases.join(", "), started.elapsed(), stats.entries);
+}
+
+/// Single-line stderr progress counter for long-running scans
+struct Progress {
+	enabled: bool,
+	count: u64,
+	last_draw: Instant,
+	frame: usize,
+	separator: Option<char>,
+	drawn: bool,
+}
+
+impl Progress {
+	/// Frames of the spinner shown before the count
+	const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+	/// Minimum time between redraws, so counting stays cheap
+	const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+	
+	/// Create a progress counter, disabled in quiet mode or when stderr isn't a terminal
+	fn new(config: &Config) -> Self {
+		Progress {
+			enabled: !config.quiet && unsafe { libc::isatty(libc::STDERR_FILENO) } == 1,
+			count: 0,
+			last_draw: Instant::now(),
+			frame: 0,
+			separator: config.digit_separator,
+			drawn: false,
+		}
+	}
+	
+	/// Count processed files, redrawing the line if enough time has passed
+	fn advance(&mut self, files: u64) {
+		self.count += files;
+		if self.enabled && self.last_draw.elapsed() >= Self::REDRAW_INTERVAL {
+			let count = match self.separator {
+				Some(separator) => group_digits(self.count, separator),
+				None => self.count.to_string(),
+			};
+			eprint!("\r{} {} files scanned", Self::SPINNER[self.frame % Self::SPINNER.len()], count);
+			self.frame += 1;
+			let _ = io::stderr().flush();
+			self.last_draw = Instant::now();
+			self.drawn = true;
+		}
+	}
+	
+	/// Erase the progress line so normal output starts on a clean line
+	fn clear(&mut self) {
+		if self.drawn {
+			eprint!("\r\x1b[K");
+			let _ = io::stderr().flush();
+			self.drawn = false;
+		}
+	}
+}
+
+/// Apparent size of everything below a directory
+#[derive(Debug, Clone, Copy, Default)]
+struct SubtreeSize {
+	bytes: u64,
+	/// Some directory below couldn't be read, so `bytes` is a lower bound
+	partial: bool,
+}
+
+/// Subtree sizes already walked in this run, by the directory's device and inode
+///
+/// Directories reached twice, e.g. `--dirs-sizes a a/b` or `--recursive-size`
+/// over a tree that `-R` also descends, are only walked once.
+static SUBTREE_SIZES: OnceLock<Mutex<HashMap<(u64, u64), SubtreeSize>>> = OnceLock::new();
+
+/// Total apparent size of everything below a directory, without following symlinks
+fn subtree_size(directory: &Path, progress: &Mutex<Progress>) -> SubtreeSize {
+	let Ok(metadata) = fs::symlink_metadata(directory) else {
+		return SubtreeSize { bytes: 0, partial: true };
+	};
+	let key = (metadata.dev(), metadata.ino());
+	let cache = SUBTREE_SIZES.get_or_init(|| Mutex::new(HashMap::new()));
+	if let Some(&size) = cache.lock().ok().and_then(|sizes| sizes.get(&key).copied()).as_ref() {
+		return size;
+	}
+	
+	let Ok(entries) = fs::read_dir(directory) else {
+		return SubtreeSize { bytes: 0, partial: true };
+	};
+	let mut size = SubtreeSize::default();
+	let mut files = 0;
+	for entry in entries.map_while(Result::ok) {
+		let Ok(metadata) = entry.metadata() else { continue };
+		files += 1;
+		if metadata.is_dir() {
+			let below = subtree_size(&entry.path(), progress);
+			size.bytes += below.bytes;
+			size.partial |= below.partial;
+		} else {
+			size.bytes += metadata.len();
+		}
+	}
+	// Counted per directory so parallel walkers rarely wait on the lock
+	if let Ok(mut progress) = progress.lock() {
+		progress.advance(files);
+	}
+	if let Ok(mut sizes) = cache.lock() {
+		sizes.insert(key, size);
+	}
+	size
+}
+
+/// Kernel pseudo filesystems whose entries are listed conservatively
+const PSEUDO_FILESYSTEMS: [&str; 11] = [
+	"proc", "sysfs", "debugfs", "tracefs", "securityfs", "cgroup", "cgroup2",
+	"configfs", "bpf", "pstore", "efivarfs",
+];
+
+/// Filesystem type of the mount containing a path, from /proc/mounts
+fn mount_fstype(path: &Path) -> Option<String> {
+	mount_info(path).map(|(fstype, _)| fstype)
+}
+
+/// Filesystem type and mount options of the mount containing a path, from /proc/mounts
+fn mount_info(path: &Path) -> Option<(String, Vec<String>)> {
+	let path = fs::canonicalize(path).ok()?;
+	let mounts = fs::read_to_string("/proc/mounts").ok()?;
+	
+	// The longest mount point that prefixes the path is the one it lives on
+	mounts.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let _device = fields.next()?;
+			let mount_point = unescape_mount_field(fields.next()?);
+			let fstype = fields.next()?;
+			let options = fields.next().unwrap_or("").split(',').map(str::to_string).collect();
+			path.starts_with(&mount_point).then(|| (mount_point.len(), (fstype.to_string(), options)))
+		})
+		.max_by_key(|(length, _)| *length)
+		.map(|(_, info)| info)
+}
+
+/// Tell the user once when access times can't be trusted for `--sort=accessed`
+///
+/// With `noatime` reads never update atime, so the order reflects creation or
+/// the last explicit touch. The kernel's default `relatime` still updates it at
+/// least daily, which is close enough not to warn about.
+fn warn_unreliable_atime(directories: &[PathBuf]) {
+	let unreliable = directories.iter().find(|directory| {
+		mount_info(directory).is_some_and(|(_, options)| options.iter().any(|option| option == "noatime"))
+	});
+	if let Some(directory) = unreliable {
+		eprintln!("yal: note: {} is on a filesystem mounted noatime, so access times are not updated when files are read", directory.display());
+	}
+}
+
+/// Map of device number to filesystem type for every mount, or `None` without /proc/mounts
+fn device_fstypes() -> Option<&'static HashMap<u64, String>> {
+	static FSTYPES: OnceLock<Option<HashMap<u64, String>>> = OnceLock::new();
+	FSTYPES.get_or_init(|| {
+		let mounts = fs::read_to_string("/proc/mounts").ok()?;
+		let mut fstypes = HashMap::new();
+		for line in mounts.lines() {
+			let mut fields = line.split_whitespace();
+			let (Some(_device), Some(mount_point), Some(fstype)) = (fields.next(), fields.next(), fields.next()) else {
+				continue;
+			};
+			// Later mounts hide earlier ones at the same place, so they win
+			if let Ok(metadata) = fs::metadata(unescape_mount_field(mount_point)) {
+				fstypes.insert(metadata.dev(), fstype.to_string());
+			}
+		}
+		Some(fstypes)
+	}).as_ref()
+}
+
+/// Decode the octal escapes (`\040` for space) used in /proc/mounts fields
+fn unescape_mount_field(field: &str) -> String {
+	let mut result = String::new();
+	let mut chars = field.chars();
+	while let Some(c) = chars.next() {
+		if c == '\\' {
+			let digits: String = chars.clone().take(3).collect();
+			if let Ok(byte) = u8::from_str_radix(&digits, 8) {
+				result.push(byte as char);
+				chars.nth(2);
+				continue;
+			}
+		}
+		result.push(c);
+	}
+	result
+}
+
+/// Whether a directory lives on a kernel pseudo filesystem such as /proc or /sys
+fn is_pseudo_filesystem(directory: &Path) -> bool {
+	mount_fstype(directory).is_some_and(|fstype| PSEUDO_FILESYSTEMS.contains(&fstype.as_str()))
+}
+
+/// Subdirectories to descend into, in display order
+///
+/// Hidden directories follow `recurse_hidden` rather than `show_hidden`, so they
+/// can be shown but not walked (e.g. `.git`) or walked without being shown.
+fn subdirectories(directory: &Path, entries: &[FileEntry], config: &Config) -> Vec<PathBuf> {
+	let recurse_hidden = config.recurse_hidden.unwrap_or(config.show_hidden);
+	let mut subdirectories: Vec<PathBuf> = entries
+		.iter()
+		.filter(|entry| entry.is_dir && !entry.is_symlink && !entry.is_dot_entry())
+		.filter(|entry| recurse_hidden || !entry.name.starts_with('.'))
+		.map(|entry| entry.path.clone())
+		.collect();
+	
+	// Hidden directories weren't collected for display, so find them separately
+	if recurse_hidden && !config.show_hidden {
+		let mut hidden: Vec<String> = fs::read_dir(directory)
+			.into_iter()
+			.flatten()
+			.map_while(Result::ok)
+			.filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+			.map(|entry| entry.file_name().to_string_lossy().to_string())
+			.filter(|name| name.starts_with('.'))
+			.collect();
+		hidden.sort_by(|a, b| compare_names(a, b, config.collation));
+		subdirectories.extend(hidden.into_iter().map(|name| directory.join(name)));
+	}
+	
+	subdirectories
+}
+
+/// Read a directory into filtered, sorted entries ready for display
+fn collect_entries(directory: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver) -> std::io::Result<Vec<FileEntry>> {
+	match collect_counted_entries(directory, config, name_cache, icons)? {
+		Collected::Entries(entries, _) => Ok(entries),
+		// Only plain listings can fall back to streaming; everything else needs the entries
+		Collected::OverThreshold(limit) => Err(io::Error::other(over_threshold_message(limit, config))),
+	}
+}
+
+/// Outcome of collecting a directory
+#[derive(Debug)]
+enum Collected {
+	/// The listed entries and the ones left out
+	Entries(Vec<FileEntry>, Omitted),
+	/// More entries than `stream_threshold` allows; the directory has to be streamed instead
+	OverThreshold(usize),
+}
+
+/// Entries left out of a listing, reported by `show_filtered_count`
+#[derive(Debug, Default)]
+struct Omitted {
+	/// Dotfiles skipped because hidden files aren't shown
+	hidden: usize,
+	/// Entries removed by the name prefix, glob or modification time filters
+	filtered: usize,
+}
+
+impl Omitted {
+	/// Header note such as ` (5 hidden, 3 filtered)`, empty when nothing was left out
+	fn note(&self, config: &Config) -> String {
+		let parts: Vec<String> = [(self.hidden, "hidden"), (self.filtered, "filtered")]
+			.iter()
+			.filter(|(count, _)| *count > 0)
+			.map(|(count, label)| format!("{} {}", config.format_number(*count as u64), label))
+			.collect();
+		if parts.is_empty() {
+			return String::new();
+		}
+		format!(" {}({})\x1b[0m", palette().muted, parts.join(", "))
+	}
+}
+
+/// Like `collect_entries`, also counting the entries that were left out
+fn collect_counted_entries(directory: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver) -> std::io::Result<Collected> {
+	collect_source_entries(&RealFiles, directory, config, name_cache, icons)
+}
+
+/// Collect, filter and sort the entries `source` lists for a directory, counting the ones left out
+fn collect_source_entries(source: &dyn FileSource, directory: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver) -> std::io::Result<Collected> {
+	let mut omitted = Omitted::default();
+	let read_started = Instant::now();
+	// Taken before reading, so changes made while listing show up next time
+	let listed_at = SystemTime::now();
+	let pseudo_fs = is_pseudo_filesystem(directory);
+	
+	// Collect and sort entries
+	let mut file_entries = Vec::new();
+	let mut metadata_time = Duration::ZERO;
+	if config.cache && !pseudo_fs {
+		let stats = cached_stats(source, directory, config)?;
+		if let Some(limit) = over_stream_threshold(stats.len(), config) {
+			return Ok(Collected::OverThreshold(limit));
+		}
+		for (file_name, stat) in stats {
+			if is_listed(&file_name, config, &mut omitted) {
+				let path = directory.join(&file_name);
+				file_entries.push(FileEntry::from_stat(file_name, path, &stat, name_cache, icons, config, false));
+			}
+		}
+	} else {
+		// Hidden and prefix filters are checked before any metadata is read
+		let mut listed = Vec::new();
+		// Kept open until the entries are built, like `DirEntry`, so /proc/self/fd still shows its own descriptor
+		let mut entries = source.read_dir(directory)?;
+		for entry in &mut entries {
+			let entry = entry?;
+			if is_listed(&entry.name, config, &mut omitted) {
+				listed.push(entry);
+				if let Some(limit) = over_stream_threshold(listed.len(), config) {
+					return Ok(Collected::OverThreshold(limit));
+				}
+			}
+		}
+		
+		let metadata_started = Instant::now();
+		// `DirEntry::file_type` comes with the listing on most filesystems, so no stat is made at all
+		if config.no_metadata {
+			file_entries.extend(listed.iter().map(|entry| FileEntry::names_only(entry, icons)));
+		} else {
+			let stats = stat_entries(source, &listed, config);
+			for (entry, stat) in listed.into_iter().zip(stats) {
+				match stat {
+					Ok(stat) => {
+						file_entries.push(FileEntry::from_stat(entry.name, entry.path, &stat, name_cache, icons, config, pseudo_fs));
+					},
+					// Files deleted since read_dir listed them are dropped; others are shown unreadable, like `ls`
+					Err(error) => {
+						report_stat_error(&entry.path, &error, config);
+						if pseudo_fs || error.kind() != io::ErrorKind::NotFound {
+							file_entries.push(FileEntry::unreadable(&entry, icons, config));
+						}
+					},
+				}
+			}
+		}
+		metadata_time = metadata_started.elapsed();
+	}
+	record_phase(Phase::Read, read_started.elapsed().saturating_sub(metadata_time));
+	record_phase(Phase::Metadata, metadata_time);
+	record_entries(file_entries.len());
+	
+	// Like `find -newer`, entries without a readable mtime never pass
+	if let Some(reference) = config.newer_than {
+		let before = file_entries.len();
+		file_entries.retain(|entry| entry.modified.is_some_and(|modified| modified > reference));
+		omitted.filtered += before - file_entries.len();
+	}
+	
+	if config.skip_special {
+		let before = file_entries.len();
+		file_entries.retain(|entry| !entry.kind.is_special());
+		omitted.filtered += before - file_entries.len();
+	}
+	
+	// Directories always pass so recursion still reaches matching files below them
+	if !config.filter_patterns.is_empty() {
+		let before = file_entries.len();
+		file_entries.retain(|entry| entry.is_dir || passes_filter(&entry.name, config));
+		omitted.filtered += before - file_entries.len();
+	}
+	
+	// Replace directory sizes with the total of their contents before sorting
+	if config.recursive_size && !pseudo_fs {
+		let progress = Mutex::new(Progress::new(config));
+		for entry in file_entries.iter_mut().filter(|entry| entry.is_dir) {
+			entry.size = subtree_size(&entry.path, &progress).bytes;
+		}
+		progress.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+	}
+	
+	// Adding, removing or renaming entries updates the directory's mtime, but
+	// writing to a file in place doesn't, so newer files had their content changed since
+	if config.highlight_newer_than_dir {
+		if let Ok(directory_modified) = fs::metadata(directory).and_then(|metadata| metadata.modified()) {
+			for entry in &mut file_entries {
+				entry.newer_than_dir = entry.modified.is_some_and(|modified| modified > directory_modified);
+			}
+		}
+	}
+	
+	// The first run has nothing to compare against, so everything is shown unmarked
+	if config.since_last != SinceLast::Off && !pseudo_fs {
+		if let Some(last_run) = last_listed(directory) {
+			for entry in &mut file_entries {
+				entry.changed_since_last = entry.modified.is_some_and(|modified| modified > last_run);
+			}
+			if config.since_last == SinceLast::Filter {
+				let before = file_entries.len();
+				file_entries.retain(|entry| entry.is_dir || entry.changed_since_last);
+				omitted.filtered += before - file_entries.len();
+			}
+		}
+		let _ = record_listed(directory, listed_at); // Best effort, like the cache
+	}
+	
+	// Like `make`, a missing target is rebuilt whatever its prerequisites' times;
+	// directories aren't marked, since their mtime only tracks their entries
+	if let Some(target) = &config.rebuild_target {
+		let target_modified = fs::metadata(target).and_then(|metadata| metadata.modified()).ok();
+		for entry in file_entries.iter_mut().filter(|entry| !entry.is_dir) {
+			entry.triggers_rebuild = match target_modified {
+				Some(target_modified) => entry.modified.is_some_and(|modified| modified > target_modified),
+				None => true,
+			};
+		}
+	}
+	
+	// Bars are scaled against the largest entry, so they need every final size
+	if config.show_size_bars {
+		let largest = file_entries.iter().map(|entry| entry.size).max().unwrap_or(0);
+		for entry in &mut file_entries {
+			entry.size_bar = size_bar(entry.size, largest, config.size_bar_width);
+		}
+	}
+	
+	// Directory sizes are only their own blocks unless `recursive_size` totals their contents
+	if config.show_percent {
+		let counts = |entry: &FileEntry| !entry.is_dir || (config.recursive_size && !pseudo_fs);
+		let total: u64 = file_entries.iter().filter(|entry| counts(entry)).map(|entry| entry.size).sum();
+		for entry in &mut file_entries {
+			entry.percent = match total {
+				_ if !counts(entry) => "-".to_string(),
+				0 => "0%".to_string(),
+				_ if entry.size > 0 && entry.size * 100 < total => "<1%".to_string(),
+				_ => format!("{:.0}%", entry.size as f64 * 100.0 / total as f64),
+			};
+		}
+	}
+	
+	let sort_started = Instant::now();
+	let mut entries = order_entries(file_entries, config);
+	if config.dedup_inodes {
+		entries = dedup_hard_links(entries);
+	}
+	record_phase(Phase::Sort, sort_started.elapsed());
+	
+	// `.` and `..` stay on top whatever the sort order, as in `ls -a`
+	if config.show_dot_entries && config.name_prefix.is_none() {
+		entries.splice(0..0, dot_entries(directory, name_cache, icons, config));
+	}
+	Ok(Collected::Entries(entries, omitted))
+}
+
+/// Synthetic `.` and `..` entries for a directory; at the root `..` is the root itself
+fn dot_entries(directory: &Path, name_cache: &NameCache, icons: &IconResolver, config: &Config) -> Vec<FileEntry> {
+	let is_root = fs::canonicalize(directory).is_ok_and(|path| path.parent().is_none());
+	let parent = if is_root { directory.join(".") } else { directory.join("..") };
+	[(".", directory.join(".")), ("..", parent)]
+		.into_iter()
+		.filter_map(|(name, path)| FileEntry::dot_entry(name, path, name_cache, icons, config).ok())
+		.collect()
+}
+
+/// Entries a directory may have before it is streamed rather than collected and sorted
+///
+/// A collected entry takes a few hundred bytes, so this keeps a listing to a few hundred MB.
+const DEFAULT_STREAM_THRESHOLD: usize = 500_000;
+
+/// The `stream_threshold` a directory of `count` entries so far has gone over, if any
+fn over_stream_threshold(count: usize, config: &Config) -> Option<usize> {
+	config.stream_threshold.filter(|&limit| count > limit)
+}
+
+/// Why a directory over `limit` entries isn't collected
+fn over_threshold_message(limit: usize, config: &Config) -> String {
+	format!("more than {} entries, over --stream-threshold", config.format_number(limit as u64))
+}
+
+/// Fewest entries worth handing to each extra stat thread; smaller directories stay on one
+const MIN_ENTRIES_PER_THREAD: usize = 64;
+
+/// Stat directory entries in order, spreading the calls over up to `max_threads` workers
+///
+/// Each stat is a round trip on network filesystems like NFS, so overlapping them
+/// hides most of the latency; on local disks the gain is smaller but still real.
+fn stat_entries(source: &dyn FileSource, entries: &[SourceEntry], config: &Config) -> Vec<std::io::Result<Stat>> {
+	let threads = config.max_threads.min(entries.len() / MIN_ENTRIES_PER_THREAD).max(1);
+	if threads == 1 {
+		return entries.iter().map(|entry| source.stat(entry, config)).collect();
+	}
+	
+	let chunk_size = entries.len().div_ceil(threads);
+	std::thread::scope(|scope| {
+		let workers: Vec<_> = entries.chunks(chunk_size)
+			.map(|chunk| scope.spawn(move || chunk.iter().map(|entry| source.stat(entry, config)).collect::<Vec<_>>()))
+			.collect();
+		workers.into_iter()
+			.flat_map(|worker| worker.join().expect("stat worker panicked"))
+			.collect()
+	})
+}
+
+/// Report an entry whose metadata couldn't be read, telling a vanished file from an unreadable one
+///
+/// Busy directories like /tmp lose files between `read_dir` and the stat, so
+/// this is only printed with `--verbose`.
+fn report_stat_error(path: &Path, error: &io::Error, config: &Config) {
+	if !config.verbose {
+		return;
+	}
+	if error.kind() == io::ErrorKind::NotFound {
+		eprintln!("yal: note: {}: removed while listing", path.display());
+	} else {
+		eprintln!("yal: {}: cannot read metadata: {}", path.display(), error);
+	}
+}
+
+/// Whether a file name passes `--filter`, taking `--invert` into account
+fn passes_filter(file_name: &str, config: &Config) -> bool {
+	config.filter_patterns.is_empty()
+		|| config.filter_patterns.iter().any(|pattern| glob_match(pattern, file_name)) != config.invert_filter
+}
+
+/// Whether a name passes the hidden-file setting, ignore patterns and the completion prefix, counting it when not
+fn is_listed(file_name: &str, config: &Config, omitted: &mut Omitted) -> bool {
+	if !config.show_hidden && file_name.starts_with('.') {
+		omitted.hidden += 1;
+		false
+	} else if config.ignore_patterns.iter().any(|pattern| glob_match(pattern, file_name))
+		|| config.name_prefix.as_ref().is_some_and(|prefix| !file_name.starts_with(prefix.as_str()))
+	{
+		omitted.filtered += 1;
+		false
+	} else {
+		true
+	}
+}
+
+/// Version written at the top of cache files; others are ignored
+const CACHE_VERSION: &str = "yal-cache 2";
+
+/// Directories modified this recently are not cached, since another change
+/// within the same timestamp tick would go unnoticed
+const CACHE_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Stats of every entry in a directory, from the cache when the directory's mtime is unchanged
+///
+/// The cache is only invalidated by changes to the directory itself (entries
+/// added, removed or renamed). A file modified in place keeps its cached size
+/// and time until then.
+fn cached_stats(source: &dyn FileSource, directory: &Path, config: &Config) -> std::io::Result<Vec<(String, Stat)>> {
+	let directory_modified = fs::metadata(directory)?.modified()?;
+	let directory = fs::canonicalize(directory)?;
+	let key = format!("{}\t{}\t{}\t{}", CACHE_VERSION, config.dereference, encode_time(Some(directory_modified)), escape_cache_field(&directory.display().to_string()));
+	let cache_file = cache_directory().map(|cache| cache.join(format!("{:016x}", path_hash(&directory))));
+	
+	if let Some(stats) = cache_file.as_ref().and_then(|file| read_cache(file, &key)) {
+		return Ok(stats);
+	}
+	
+	let entries = source.read_dir(&directory)?.collect::<std::io::Result<Vec<_>>>()?;
+	let stats: Vec<(String, Stat)> = entries.iter()
+		.zip(stat_entries(source, &entries, config))
+		.filter_map(|(entry, stat)| {
+			let stat = stat.map_err(|error| report_stat_error(&entry.path, &error, config)).ok()?;
+			Some((entry.name.clone(), stat))
+		})
+		.collect();
+	
+	let settled = directory_modified.elapsed().is_ok_and(|age| age >= CACHE_SETTLE_TIME);
+	if let (Some(file), true) = (cache_file, settled) {
+		let _ = write_cache(&file, &key, &stats); // The cache is best effort
+	}
+	Ok(stats)
+}
+
+/// Directory for `--since-last` records: `$XDG_STATE_HOME/yal`, else `~/.local/state/yal`
+fn state_directory() -> Option<PathBuf> {
+	let base = env::var("XDG_STATE_HOME")
+		.ok()
+		.filter(|value| !value.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var("HOME").ok().map(|home| PathBuf::from(home).join(".local/state")))?;
+	Some(base.join("yal"))
+}
+
+/// Version written at the top of `--since-last` records; others are ignored
+const SINCE_LAST_VERSION: &str = "yal-since-last 1";
+
+/// File recording when a directory was last listed, named by a hash of its real path
+fn since_last_file(directory: &Path) -> Option<(PathBuf, String)> {
+	let directory = fs::canonicalize(directory).ok()?;
+	let key = format!("{}\t{}", SINCE_LAST_VERSION, escape_cache_field(&directory.display().to_string()));
+	Some((state_directory()?.join(format!("{:016x}", path_hash(&directory))), key))
+}
+
+/// When yal last listed a directory with `since_last` on, if it ever did
+fn last_listed(directory: &Path) -> Option<SystemTime> {
+	let (file, key) = since_last_file(directory)?;
+	let contents = fs::read_to_string(file).ok()?;
+	let mut lines = contents.lines();
+	if lines.next()? != key {
+		return None;
+	}
+	decode_time(lines.next()?)?
+}
+
+/// Record that a directory was listed at `time`, replacing the file atomically
+fn record_listed(directory: &Path, time: SystemTime) -> std::io::Result<()> {
+	let Some((file, key)) = since_last_file(directory) else {
+		return Ok(());
+	};
+	if let Some(parent) = file.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let temporary = file.with_extension(format!("{}.tmp", std::process::id()));
+	fs::write(&temporary, format!("{}\n{}\n", key, encode_time(Some(time))))?;
+	fs::rename(&temporary, file)
+}
+
+/// 64-bit FNV-1a hash of a path, naming its cache and state files
+fn path_hash(path: &Path) -> u64 {
+	path.as_os_str().as_encoded_bytes().iter()
+		.fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Directory for cache files: `$XDG_CACHE_HOME/yal`, else `~/.cache/yal`
+fn cache_directory() -> Option<PathBuf> {
+	let base = env::var("XDG_CACHE_HOME")
+		.ok()
+		.filter(|value| !value.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var("HOME").ok().map(|home| PathBuf::from(home).join(".cache")))?;
+	Some(base.join("yal"))
+}
+
+/// Read cached stats, if the file exists and was written for exactly this key
+fn read_cache(file: &Path, key: &str) -> Option<Vec<(String, Stat)>> {
+	let contents = fs::read_to_string(file).ok()?;
+	let mut lines = contents.lines();
+	if lines.next()? != key {
+		return None;
+	}
+	
+	lines.map(|line| {
+		let fields: Vec<&str> = line.split('\t').collect();
+		let [name, mode, uid, gid, size, dev, ino, links, blocks, modified, accessed, is_symlink] = fields[..] else {
+			return None;
+		};
+		let stat = Stat {
+			mode: mode.parse().ok()?,
+			uid: uid.parse().ok()?,
+			gid: gid.parse().ok()?,
+			size: size.parse().ok()?,
+			dev: dev.parse().ok()?,
+			ino: ino.parse().ok()?,
+			links: links.parse().ok()?,
+			blocks: blocks.parse().ok()?,
+			modified: decode_time(modified)?,
+			accessed: decode_time(accessed)?,
+			is_symlink: is_symlink == "1",
+		};
+		Some((unescape_cache_field(name), stat))
+	}).collect()
+}
+
+/// Write stats under `key`, replacing the cache file atomically
+fn write_cache(file: &Path, key: &str, stats: &[(String, Stat)]) -> std::io::Result<()> {
+	let mut contents = format!("{}\n", key);
+	for (name, stat) in stats {
+		contents.push_str(&format!(
+			"{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+			escape_cache_field(name), stat.mode, stat.uid, stat.gid, stat.size, stat.dev, stat.ino,
+			stat.links, stat.blocks, encode_time(stat.modified), encode_time(stat.accessed),
+			if stat.is_symlink { 1 } else { 0 }
+		));
+	}
+	
+	if let Some(parent) = file.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let temporary = file.with_extension(format!("{}.tmp", std::process::id()));
+	fs::write(&temporary, contents)?;
+	fs::rename(&temporary, file)
+}
+
+/// Encode a time as signed seconds and nanoseconds since the epoch, `-` when unknown
+fn encode_time(time: Option<SystemTime>) -> String {
+	match time.map(|time| time.duration_since(std::time::UNIX_EPOCH)) {
+		Some(Ok(after)) => format!("{}.{:09}", after.as_secs(), after.subsec_nanos()),
+		Some(Err(before)) => format!("-{}.{:09}", before.duration().as_secs(), before.duration().subsec_nanos()),
+		None => "-".to_string(),
+	}
+}
+
+/// Decode a time written by `encode_time`; the outer `None` marks a malformed value
+fn decode_time(value: &str) -> Option<Option<SystemTime>> {
+	if value == "-" {
+		return Some(None);
+	}
+	let (before_epoch, value) = match value.strip_prefix('-') {
+		Some(rest) => (true, rest),
+		None => (false, value),
+	};
+	let (secs, nanos) = value.split_once('.')?;
+	let offset = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+	let time = if before_epoch {
+		std::time::UNIX_EPOCH.checked_sub(offset)?
+	} else {
+		std::time::UNIX_EPOCH.checked_add(offset)?
+	};
+	Some(Some(time))
+}
+
+/// Escape backslashes, tabs and newlines so a name fits in one tab-separated field
+fn escape_cache_field(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+/// Reverse `escape_cache_field`
+fn unescape_cache_field(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			result.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('t') => result.push('\t'),
+			Some('n') => result.push('\n'),
+			Some(other) => result.push(other),
+			None => result.push('\\'),
+		}
+	}
+	result
+}
+
+/// Float pinned names to the top in their configured order, then sort the rest
+fn order_entries(entries: Vec<FileEntry>, config: &Config) -> Vec<FileEntry> {
+	let (mut pinned, mut rest): (Vec<FileEntry>, Vec<FileEntry>) = entries
+		.into_iter()
+		.partition(|entry| pin_rank(&entry.name, config).is_some());
+	pinned.sort_by(|a, b| {
+		pin_rank(&a.name, config).cmp(&pin_rank(&b.name, config))
+			.then_with(|| compare_names(&a.name, &b.name, config.collation))
+	});
+	sort_entries(&mut rest, config);
+	pinned.append(&mut rest);
+	pinned
+}
+
+/// Collect every non-directory below a root into one globally sorted list named by relative path
+fn collect_flat(root: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver, failed: &mut bool) -> Vec<FileEntry> {
+	// Limits apply to the combined list, not to each directory along the way
+	let tree_config = Config { recursive: true, max_entries: None, ..config.clone() };
+	let mut entries = Vec::new();
+	collect_tree(root, &tree_config, name_cache, icons, &mut entries, failed);
+	
+	entries.retain(|entry| !entry.is_dir);
+	for entry in &mut entries {
+		if let Ok(relative) = entry.path.strip_prefix(root) {
+			entry.name = relative.display().to_string();
+		}
+	}
+	
+	let entries = order_entries(entries, config);
+	if config.dedup_inodes {
+		// Hard links in different directories are only seen together here
+		return dedup_hard_links(entries);
+	}
+	entries
+}
+
+/// Collapse hard links to the same inode into the first entry, recording the other names as aliases
+fn dedup_hard_links(entries: Vec<FileEntry>) -> Vec<FileEntry> {
+	let mut first_seen: HashMap<(u64, u64), usize> = HashMap::new();
+	let mut kept: Vec<FileEntry> = Vec::with_capacity(entries.len());
+	for entry in entries {
+		if entry.links > 1 && !entry.is_dir {
+			if let Some(&index) = first_seen.get(&(entry.dev, entry.ino)) {
+				kept[index].aliases.push(entry.name);
+				continue;
+			}
+			first_seen.insert((entry.dev, entry.ino), kept.len());
+		}
+		kept.push(entry);
+	}
+	kept
+}
+
+/// Restores the terminal's original mode when dropped
+struct RawModeGuard {
+	fd: i32,
+	original: libc::termios,
+}
+
+impl RawModeGuard {
+	/// Put the terminal behind `fd` into raw mode (no echo, no line buffering, no signals)
+	fn enable(fd: i32) -> std::io::Result<Self> {
+		let mut original: libc::termios = unsafe { std::mem::zeroed() };
+		if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		
+		let mut raw = original;
+		unsafe { libc::cfmakeraw(&mut raw) };
+		raw.c_cc[libc::VMIN] = 1;
+		raw.c_cc[libc::VTIME] = 0;
+		if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		
+		Ok(RawModeGuard { fd, original })
+	}
+}
+
+impl Drop for RawModeGuard {
+	fn drop(&mut self) {
+		unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+	}
+}
+
+/// A key press understood by the interactive selector
+#[derive(Debug, PartialEq)]
+enum Key {
+	Up,
+	Down,
+	Toggle,
+	Confirm,
+	Abort,
+	Other,
+}
+
+/// Read one key press from the raw-mode terminal
+fn read_key(tty: &mut fs::File) -> std::io::Result<Key> {
+	use std::io::Read;
+	
+	let mut byte = [0u8; 1];
+	tty.read_exact(&mut byte)?;
+	
+	Ok(match byte[0] {
+		b'\r' | b'\n' => Key::Confirm,
+		b' ' => Key::Toggle,
+		b'k' => Key::Up,
+		b'j' => Key::Down,
+		b'q' | 0x03 => Key::Abort, // q or Ctrl-C
+		0x1b => {
+			// A lone Esc aborts; an escape sequence follows immediately for arrow keys
+			let mut poll_fd = libc::pollfd { fd: std::os::unix::io::AsRawFd::as_raw_fd(tty), events: libc::POLLIN, revents: 0 };
+			if unsafe { libc::poll(&mut poll_fd, 1, 30) } <= 0 {
+				return Ok(Key::Abort);
+			}
+			let mut sequence = [0u8; 2];
+			tty.read_exact(&mut sequence)?;
+			match sequence {
+				[b'[', b'A'] | [b'O', b'A'] => Key::Up,
+				[b'[', b'B'] | [b'O', b'B'] => Key::Down,
+				_ => Key::Other,
+			}
+		},
+		_ => Key::Other,
+	})
+}
+
+/// Number of rows of the terminal behind `fd`, defaulting to 24
+fn terminal_rows(fd: i32) -> usize {
+	terminal_size(fd).map(|(rows, _)| rows).filter(|&rows| rows > 0).unwrap_or(24)
+}
+
+/// Let the user pick entries interactively on the controlling terminal
+///
+/// Arrow keys (or j/k) move, space toggles, enter confirms and Esc, q or
+/// Ctrl-C abort. The UI is drawn on `/dev/tty` so stdout stays clean for
+/// command substitution. Returns `None` when aborted.
+fn select_entries(entries: &[FileEntry], config: &Config, directory: &Path) -> std::io::Result<Option<Vec<PathBuf>>> {
+	use std::io::Write;
+	use std::os::unix::io::AsRawFd;
+	
+	if entries.is_empty() {
+		return Ok(Some(Vec::new()));
+	}
+	
+	let mut tty = fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+	let fd = tty.as_raw_fd();
+	let widths = ColumnWidths::compute(entries, config);
+	let lines: Vec<String> = entries.iter().map(|entry| entry.format_display(config, &widths)).collect();
+	
+	let mut selected = vec![false; entries.len()];
+	let mut cursor = 0;
+	let mut scroll = 0;
+	
+	// Alternate screen and hidden cursor while selecting
+	write!(tty, "\x1b[?1049h\x1b[?25l")?;
+	let result = (|| -> std::io::Result<Option<Vec<PathBuf>>> {
+		let _raw_mode = RawModeGuard::enable(fd)?;
+		
+		loop {
+			// Keep the cursor inside the visible window, leaving a row for the header
+			let visible = terminal_rows(fd).saturating_sub(1).max(1);
+			if cursor < scroll {
+				scroll = cursor;
+			} else if cursor >= scroll + visible {
+				scroll = cursor + 1 - visible;
+			}
+			
+			let mut frame = format!(
+				"\x1b[H\x1b[2J\x1b[1m {} \x1b[0m\x1b[90m(space: toggle, enter: confirm, esc: abort)\x1b[0m\r\n",
+				display_path(directory, config)
+			);
+			for (index, line) in lines.iter().enumerate().skip(scroll).take(visible) {
+				let pointer = if index == cursor { "\x1b[7m›\x1b[0m" } else { " " };
+				let mark = if selected[index] { "\x1b[32m●\x1b[0m" } else { "○" };
+				frame.push_str(&format!("{} {} {}\r\n", pointer, mark, line));
+			}
+			tty.write_all(frame.as_bytes())?;
+			tty.flush()?;
+			
+			match read_key(&mut tty)? {
+				Key::Up => cursor = cursor.saturating_sub(1),
+				Key::Down => cursor = (cursor + 1).min(entries.len() - 1),
+				Key::Toggle => selected[cursor] = !selected[cursor],
+				Key::Confirm => {
+					// With nothing toggled, confirm the entry under the cursor
+					if !selected.contains(&true) {
+						selected[cursor] = true;
+					}
+					let paths = entries
+						.iter()
+						.zip(&selected)
+						.filter(|(_, &chosen)| chosen)
+						.map(|(entry, _)| entry.path.clone())
+						.collect();
+					return Ok(Some(paths));
+				},
+				Key::Abort => return Ok(None),
+				Key::Other => {},
+			}
+		}
+	})();
+	
+	// Restore the main screen and cursor even when selection failed
+	write!(tty, "\x1b[?25h\x1b[?1049l")?;
+	tty.flush()?;
+	
+	result
+}
+
+/// Print which entries are only in `old`, only in `new`, or in both
+///
+/// Lines are prefixed `-` (only in old), `+` (only in new), `~` (present in
+/// both but changed type, size or mtime) or left unmarked when identical.
+fn diff_directories(old: &Path, new: &Path, config: &Config, name_cache: &NameCache, icons: &IconResolver) -> std::io::Result<()> {
+	let mut old_entries: HashMap<String, FileEntry> = collect_entries(old, config, name_cache, icons)?
+		.into_iter()
+		.map(|entry| (entry.name.clone(), entry))
+		.collect();
+	let new_entries = collect_entries(new, config, name_cache, icons)?;
+	
+	let mut names: Vec<String> = old_entries.keys().cloned().collect();
+	names.extend(new_entries.iter().filter(|entry| !old_entries.contains_key(&entry.name)).map(|entry| entry.name.clone()));
+	names.sort_by(|a, b| compare_names(a, b, config.collation));
+	let mut new_entries: HashMap<String, FileEntry> = new_entries
+		.into_iter()
+		.map(|entry| (entry.name.clone(), entry))
+		.collect();
+	
+	print_header(&format!(" {} → {}", display_path(old, config), display_path(new, config)));
+	if config.header_blank_line {
+		print_header("");
+	}
+	
+	let (mut removed, mut added, mut changed, mut same) = (0, 0, 0, 0);
+	for name in &names {
+		match (old_entries.remove(name), new_entries.remove(name)) {
+			(Some(entry), None) => {
+				removed += 1;
+				print_line(&format!("\x1b[31m- {}{}\x1b[0m", entry.name, if entry.is_dir { "/" } else { "" }));
+			},
+			(None, Some(entry)) => {
+				added += 1;
+				print_line(&format!("\x1b[32m+ {}{}\x1b[0m", entry.name, if entry.is_dir { "/" } else { "" }));
+			},
+			(Some(before), Some(after)) => {
+				let differences = diff_entry(&before, &after, config);
+				if differences.is_empty() {
+					same += 1;
+					print_line(&format!("  {}{}", after.name, if after.is_dir { "/" } else { "" }));
+				} else {
+					changed += 1;
+					print_line(&format!("\x1b[33m~ {}{}\x1b[0m \x1b[90m({})\x1b[0m", after.name, if after.is_dir { "/" } else { "" }, differences.join(", ")));
+				}
+			},
+			(None, None) => {},
+		}
+	}
+	
+	print_line("");
+	print_line(&format!(
+		"\x1b[31m{} removed\x1b[0m, \x1b[32m{} added\x1b[0m, \x1b[33m{} changed\x1b[0m, {} unchanged",
+		removed, added, changed, same
+	));
+	
+	Ok(())
+}
+
+/// Describe how an entry present in both directories differs
+fn diff_entry(before: &FileEntry, after: &FileEntry, config: &Config) -> Vec<String> {
+	let kind = |entry: &FileEntry| if entry.is_dir { "directory" } else { "file" };
+	
+	// A type change makes size and time comparisons meaningless
+	if before.is_dir != after.is_dir {
+		return vec![format!("{} → {}", kind(before), kind(after))];
+	}
+	
+	let mut differences = Vec::new();
+	if !before.is_dir && before.size != after.size {
+		differences.push(format!(
+			"size {} → {}",
+			config.format_bytes(before.size),
+			config.format_bytes(after.size)
+		));
+	}
+	match (before.modified, after.modified) {
+		(Some(old_time), Some(new_time)) if new_time > old_time => differences.push("newer".to_string()),
+		(Some(old_time), Some(new_time)) if new_time < old_time => differences.push("older".to_string()),
+		_ => {},
+	}
+	
+	differences
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	/// A directory of fixed entries, so listings can be checked without touching the disk
+	struct MockFiles {
+		entries: Vec<(String, Stat)>,
+	}
+	
+	impl MockFiles {
+		/// Regular files and directories with the given size, age in seconds and owner UID
+		fn new(entries: &[(&str, bool, u64, u64, u32)]) -> Self {
+			let now = SystemTime::now();
+			MockFiles {
+				entries: entries.iter().enumerate().map(|(index, &(name, is_dir, size, age, uid))| {
+					let stat = Stat {
+						mode: if is_dir { 0o040755 } else { 0o100644 },
+						uid,
+						gid: uid,
+						size,
+						dev: 1,
+						ino: index as u64 + 1,
+						links: 1,
+						blocks: size.div_ceil(512),
+						modified: Some(now - Duration::from_secs(age)),
+						accessed: Some(now - Duration::from_secs(age)),
+						is_symlink: false,
+					};
+					(name.to_string(), stat)
+				}).collect(),
+			}
+		}
+	}
+	
+	impl FileSource for MockFiles {
+		fn read_dir(&self, directory: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<SourceEntry>>>> {
+			let entries: Vec<io::Result<SourceEntry>> = self.entries.iter()
+				.map(|(name, stat)| Ok(SourceEntry {
+					name: name.clone(),
+					path: directory.join(name),
+					kind: Some(FileKind::from_mode(stat.mode)),
+					ino: stat.ino,
+				}))
+				.collect();
+			Ok(Box::new(entries.into_iter()))
+		}
+		
+		fn stat(&self, entry: &SourceEntry, _config: &Config) -> io::Result<Stat> {
+			self.entries.iter()
+				.find(|(name, _)| *name == entry.name)
+				.map(|(_, stat)| stat.clone())
+				.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+		}
+	}
+	
+	/// Owners `alice` (1000) and `bob` (1001), in both the user and group tables
+	fn names() -> NameCache {
+		let table: HashMap<u32, String> = [(1000, "alice".to_string()), (1001, "bob".to_string())].into();
+		NameCache { users: table.clone(), groups: table }
+	}
+	
+	/// The sample directory most listing tests run against
+	fn sample() -> MockFiles {
+		MockFiles::new(&[
+			("notes.txt", false, 1536, 120, 1000),
+			("src", true, 4096, 3600, 1000),
+			("Build.log", false, 10, 7200, 1001),
+			(".hidden", false, 5, 60, 1000),
+			("archive.tar", false, 1_048_576, 86400, 1001),
+		])
+	}
+	
+	/// Entries `collect_source_entries` lists for a mock directory, with the ones left out
+	fn collect_mock(source: &MockFiles, config: &Config) -> (Vec<FileEntry>, Omitted) {
+		match collect_source_entries(source, Path::new("/mock"), config, &names(), &IconResolver::new(config)).unwrap() {
+			Collected::Entries(entries, omitted) => (entries, omitted),
+			Collected::OverThreshold(limit) => panic!("over the stream threshold of {}", limit),
+		}
+	}
+	
+	/// Entries listed for the sample directory, with the ones left out
+	fn collect_sample(config: &Config) -> (Vec<FileEntry>, Omitted) {
+		collect_mock(&sample(), config)
+	}
+	
+	/// Names of the entries listed for the sample directory
+	fn listed(config: &Config) -> Vec<String> {
+		collect_sample(config).0.into_iter().map(|entry| entry.name).collect()
+	}
+	
+	#[test]
+	fn mock_listing_sorts_by_name_with_directories_first() {
+		assert_eq!(listed(&Config::default()), ["src", "archive.tar", "Build.log", "notes.txt"]);
+	}
+	
+	#[test]
+	fn mock_listing_sorts_by_size_and_reverses() {
+		let config = Config { sort_key: SortKey::Size, sort_dirs_first: false, ..Config::default() };
+		assert_eq!(listed(&config), ["Build.log", "notes.txt", "src", "archive.tar"]);
+		let config = Config { reverse_sort: true, ..config };
+		assert_eq!(listed(&config), ["archive.tar", "src", "notes.txt", "Build.log"]);
+	}
+	
+	#[test]
+	fn mock_listing_sorts_by_time() {
+		let config = Config { sort_key: SortKey::Time, sort_dirs_first: false, ..Config::default() };
+		assert_eq!(listed(&config), ["archive.tar", "Build.log", "src", "notes.txt"]);
+	}
+	
+	#[test]
+	fn mock_listing_filters_hidden_ignored_and_unmatched_names() {
+		let config = Config { show_hidden: true, ..Config::default() };
+		assert_eq!(listed(&config), ["src", ".hidden", "archive.tar", "Build.log", "notes.txt"]);
+		
+		let config = Config { ignore_patterns: vec!["*.log".to_string()], ..Config::default() };
+		let (entries, omitted) = collect_sample(&config);
+		assert_eq!(entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), ["src", "archive.tar", "notes.txt"]);
+		assert_eq!((omitted.hidden, omitted.filtered), (1, 1));
+		
+		// Directories pass `--filter` so recursion can still reach matches below them
+		let config = Config { filter_patterns: vec!["*.txt".to_string()], ..Config::default() };
+		assert_eq!(listed(&config), ["src", "notes.txt"]);
+	}
+	
+	#[test]
+	fn mock_listing_formats_owner_size_and_time() {
+		let config = Config::default();
+		let (entries, _) = collect_sample(&config);
+		let archive = entries.iter().find(|entry| entry.name == "archive.tar").unwrap();
+		assert_eq!(strip_ansi(&archive.cell("owner", &config)), "bob");
+		assert_eq!(strip_ansi(&archive.cell("size", &config)), "1.0M");
+		assert_eq!(strip_ansi(&archive.cell("modified", &config)), "1 day");
+		assert_eq!(strip_ansi(&archive.cell("permissions", &config)), "644");
+	}
+	
+	#[test]
+	fn over_stream_threshold_is_reported_as_its_own_outcome() {
+		let config = Config { stream_threshold: Some(3), ..Config::default() };
+		let collected = collect_source_entries(&sample(), Path::new("/mock"), &config, &names(), &IconResolver::new(&config)).unwrap();
+		assert!(matches!(collected, Collected::OverThreshold(3)));
+		
+		let config = Config { stream_threshold: Some(4), ..Config::default() };
+		assert_eq!(listed(&config).len(), 4);
+	}
+	
+	#[test]
+	fn fuzzy_time_boundaries_fall_into_the_next_unit() {
+		let cases = [
+			(0, "now"),
+			(1, "1 second"),
+			(59, "59 seconds"),
+			(60, "1 minute"),
+			(3599, "59 minutes"),
+			(3600, "1 hour"),
+			(SECS_PER_DAY - 1, "23 hours"),
+			(SECS_PER_DAY, "1 day"),
+			(SECS_PER_WEEK - 1, "6 days"),
+			(SECS_PER_WEEK, "1 week"),
+			(SECS_PER_MONTH - 1, "4 weeks"),
+			(SECS_PER_MONTH, "1 month"),
+			(SECS_PER_YEAR - 1, "11 months"),
+			(SECS_PER_YEAR, "1 year"),
+		];
+		for (seconds, expected) in cases {
+			assert_eq!(format_fuzzy_seconds(seconds), expected, "{} seconds", seconds);
+		}
+		assert_eq!(SECS_PER_MONTH * 12, SECS_PER_YEAR);
+	}
+	
+	/// The default size labels, as `Config` holds them
+	fn default_suffixes() -> Vec<String> {
+		SIZE_SUFFIXES.iter().map(|suffix| suffix.to_string()).collect()
+	}
+	
+	#[test]
+	fn si_units_count_in_thousands() {
+		let suffixes = default_suffixes();
+		assert_eq!(format_size(1000, SizeUnits::Iec, 1, SizeRounding::Round, &suffixes), "1000B");
+		assert_eq!(format_size(1000, SizeUnits::Si, 1, SizeRounding::Round, &suffixes), "1.0K");
+		assert_eq!(format_size(1_500_000, SizeUnits::Si, 1, SizeRounding::Round, &suffixes), "1.5M");
+		
+		let mut config = Config::default();
+		config.apply_args(&["--si".to_string()]).unwrap();
+		assert_eq!(config.size_units, SizeUnits::Si);
+		config.apply_args(&["--iec".to_string()]).unwrap();
+		assert_eq!(config.size_units, SizeUnits::Iec);
+	}
+	
+	#[test]
+	fn config_origins_track_the_winning_source() {
+		let mut config = Config::default();
+		config.parse_config("show_icons = false\nsize_precision = 2\nnumber_format = bogus\nfrobnicate = 1\nshow_group = true\n", "yal.conf");
+		config.set_option_from("size_precision", "0", "env YAL_SIZE_PRECISION");
+		config.set_option_from("sort", "name", "env YAL_SORT");
+		config.apply_args(&["--no-owner".to_string(), "--si".to_string(), "--biggest=5".to_string()]).unwrap();
+		
+		assert_eq!(config.origins["show_icons"].source, "yal.conf");
+		assert_eq!(config.origins["size_precision"].source, "env YAL_SIZE_PRECISION");
+		assert_eq!(config.origins["size_precision"].overridden, ["yal.conf"]);
+		assert_eq!(config.origins["show_owner"].source, "command line");
+		assert!(!config.origins.contains_key("number_format"));
+		// Setting the value already in effect still counts, so it wins over lower sources
+		assert_eq!(config.origins["show_group"].source, "yal.conf");
+		// Values are kept in config syntax, under config keys
+		assert_eq!(config.origins["size_units"].value, "si");
+		assert_eq!(config.origins["sort"].value, "size");
+		assert_eq!(config.origins["sort"].overridden, ["env YAL_SORT"]);
+		assert_eq!(config.origins["max_entries"].value, "5");
+		
+		let ignored: Vec<(&str, bool)> = config.ignored_options.iter().map(|ignored| (ignored.setting.as_str(), ignored.known)).collect();
+		assert_eq!(ignored, [("number_format = bogus", true), ("frobnicate = 1", false)]);
+	}
+	
+	#[test]
+	fn config_defaults_match_the_built_in_config() {
+		let default = Config::default();
+		for (key, value) in CONFIG_DEFAULTS {
+			let mut config = default.clone();
+			assert!(config.set_option(key, value), "{} = {}", key, value);
+			config.explicit = default.explicit.clone();
+			assert_eq!(format!("{:?}", config), format!("{:?}", default), "{} = {}", key, value);
+		}
+	}
+	
+	#[test]
+	fn grid_fits_the_most_columns_the_terminal_holds() {
+		// Five cells of 4 plus separators of 2 need 28 columns on one line
+		assert_eq!(fit_grid_columns(&[4; 5], 28, GRID_SEPARATOR), 5);
+		assert_eq!(fit_grid_columns(&[4; 5], 27, GRID_SEPARATOR), 3);
+		assert_eq!(fit_grid_columns(&[4; 5], 3, GRID_SEPARATOR), 1);
+		assert_eq!(fit_grid_columns(&[], 80, GRID_SEPARATOR), 1);
+		
+		// One wide cell widens only its own column
+		let widths = [20, 2, 2, 2, 2, 2];
+		assert_eq!(grid_column_widths(&widths, 3), Some(vec![20, 2, 2]));
+		assert_eq!(fit_grid_columns(&widths, 28, GRID_SEPARATOR), 3);
+		
+		// Four columns of five cells would leave the last one empty
+		assert_eq!(grid_column_widths(&[1; 5], 4), None);
+		
+		// Thousands of short names still settle on the terminal's width
+		let many = vec![3; 10_000];
+		assert_eq!(fit_grid_columns(&many, 80, GRID_SEPARATOR), 16);
+	}
+	
+	#[test]
+	fn grid_lays_entries_out_column_major() {
+		let config = Config { show_icons: false, ..Config::default() };
+		let entries: Vec<FileEntry> = collect_sample(&config).0;
+		let lines: Vec<String> = format_grid(&entries, &config, 25).iter().map(|line| strip_ansi(line)).collect();
+		assert_eq!(lines, ["src          Build.log", "archive.tar  notes.txt"]);
+	}
+	
+	#[test]
+	fn batch_file_keeps_spaces_and_may_be_empty() {
+		let path = env::temp_dir().join(format!("yal-batch-{}", std::process::id()));
+		fs::write(&path, "# comment\r\n\r\n trailing space \r\nplain\n").unwrap();
+		let listed = read_batch_file(path.to_str().unwrap());
+		assert_eq!(listed, Ok(vec![PathBuf::from(" trailing space "), PathBuf::from("plain")]));
+		
+		fs::write(&path, "# nothing to list\n").unwrap();
+		let mut config = Config::default();
+		let directories = config.apply_args(&["--batch-file".to_string(), path.display().to_string()]).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert!(directories.is_empty());
+		assert!(config.batch_file);
+	}
+	
+	#[test]
+	fn size_precision_sets_the_decimal_places() {
+		let suffixes = default_suffixes();
+		let size = |bytes, precision| format_size(bytes, SizeUnits::Iec, precision, SizeRounding::Round, &suffixes);
+		assert_eq!(size(1536, 0), "2K");
+		assert_eq!(size(1536, 1), "1.5K");
+		assert_eq!(size(1536, 2), "1.50K");
+		// Bytes are whole numbers whatever the precision
+		assert_eq!(size(512, 2), "512B");
+		
+		let mut config = Config::default();
+		config.set_option("size_precision", "3");
+		assert_eq!(config.size_precision, 1);
+		config.set_option("size_precision", "0");
+		assert_eq!(config.format_bytes(1536), "2K");
+	}
+	
+	#[test]
+	fn size_rounding_modes_around_one_kibibyte() {
+		let suffixes = default_suffixes();
+		let cases = [
+			(1023, ["1023B", "1023B", "1023B"]),
+			(1024, ["1.0K", "1.0K", "1.0K"]),
+			(1025, ["1.0K", "1.0K", "1.1K"]),
+			(2047, ["1.9K", "2.0K", "2.0K"]),
+			(1_048_575, ["1023.9K", "1024.0K", "1024.0K"]),
+		];
+		for (bytes, expected) in cases {
+			let rendered = [SizeRounding::Floor, SizeRounding::Round, SizeRounding::Ceil]
+				.map(|rounding| format_size(bytes, SizeUnits::Iec, 1, rounding, &suffixes));
+			assert_eq!(rendered, expected, "{} bytes", bytes);
+		}
+	}
+	
+	#[test]
+	fn custom_size_suffixes_replace_the_labels() {
+		let mut config = Config::default();
+		config.set_option("size_suffixes", "b, KiB,MiB,GiB,TiB,PiB,EiB");
+		assert_eq!(config.format_bytes(512), "512b");
+		assert_eq!(config.format_bytes(1536), "1.5KiB");
+		assert_eq!(config.format_bytes(3 << 30), "3.0GiB");
+		assert_eq!(config.format_bytes(u64::MAX), "16.0EiB");
+		
+		// Units stay powers of 1024 whatever they are called, or 1000 with size_units = si
+		config.set_option("size_suffixes", "bytes,kB,MB,GB,TB,PB,EB");
+		assert_eq!(config.format_bytes(1000), "1000bytes");
+		config.set_option("size_units", "si");
+		assert_eq!(config.format_bytes(1000), "1.0kB");
+		
+		// A list of the wrong length leaves the labels as they were
+		config.set_option("size_suffixes", "b,k,m");
+		assert_eq!(config.size_suffixes.len(), SIZE_SUFFIXES.len());
+		assert_eq!(config.size_suffixes[1], "kB");
+	}
+	
+	#[test]
+	fn group_digits_at_group_boundaries() {
+		let cases = [
+			(0, "0"),
+			(999, "999"),
+			(1000, "1,000"),
+			(10_000, "10,000"),
+			(100_000, "100,000"),
+			(1_000_000, "1,000,000"),
+			(u64::MAX, "18,446,744,073,709,551,615"),
+		];
+		for (number, expected) in cases {
+			assert_eq!(group_digits(number, ','), expected);
+		}
+		assert_eq!(group_digits(1_234_567, ' '), "1 234 567");
+		
+		let mut config = Config::default();
+		config.set_option("number_format", "underscore");
+		assert_eq!(config.format_number(1_000_000), "1_000_000");
+		config.set_option("number_format", "bogus");
+		assert_eq!(config.digit_separator, Some('_'));
+	}
+	
+	#[test]
+	fn shell_quote_keeps_names_intact_and_on_one_line() {
+		use std::os::unix::ffi::OsStrExt;
+		
+		let quote = |name: &str| shell_quote(OsStr::new(name));
+		assert_eq!(quote("notes.txt"), "notes.txt");
+		assert_eq!(quote("it's"), "'it'\\''s'");
+		assert_eq!(quote("two words"), "'two words'");
+		assert_eq!(quote(""), "''");
+		assert_eq!(quote("=cmd"), "'=cmd'");
+		assert_eq!(quote("-rf"), "./-rf");
+		assert_eq!(quote("-a b"), "./'-a b'");
+		assert_eq!(quote("line\nbreak"), "$'line\\nbreak'");
+		assert_eq!(quote("tab\t'quote'\\"), "$'tab\\t\\'quote\\'\\\\'");
+		assert_eq!(quote("bell\u{7}"), "$'bell\\x07'");
+		assert_eq!(quote("café"), "'café'");
+		assert_eq!(shell_quote(OsStr::from_bytes(b"caf\xe9")), "$'caf\\xe9'");
+	}
+	
+	#[test]
+	fn fit_columns_drops_the_lowest_priority_first() {
+		let config = Config::default();
+		let entries = collect_sample(&config).0;
+		let fitted = |available| visible_columns(&fit_columns(&entries, &config, available)).join(" ");
+		assert_eq!(listing_width(&entries, &config), 40);
+		assert_eq!(fitted(80), "icon permissions owner group modified name");
+		assert_eq!(fitted(40), "icon permissions owner group modified name");
+		assert_eq!(fitted(39), "icon permissions owner modified name");
+		assert_eq!(fitted(30), "icon permissions modified name");
+		assert_eq!(fitted(25), "icon modified name");
+		assert_eq!(fitted(20), "icon name");
+		// The name is never dropped, even when it alone doesn't fit
+		assert_eq!(fitted(5), "name");
+	}
+	
+	#[test]
+	fn fit_columns_follows_configured_priorities() {
+		let mut config = Config::default();
+		config.set_option("priority_group", "90");
+		config.set_option("priority_modified", "5");
+		let entries = collect_sample(&config).0;
+		let fitted = |available| visible_columns(&fit_columns(&entries, &config, available)).join(" ");
+		assert_eq!(fitted(39), "icon permissions owner group name");
+		assert_eq!(fitted(25), "icon permissions group name");
+		assert_eq!(fitted(20), "icon group name");
+	}
+	
+	#[test]
+	fn name_budget_is_what_the_other_columns_leave() {
+		let config = Config::default();
+		let entries = collect_sample(&config).0;
+		// Every column but the name takes 29 cells, separators included
+		assert_eq!(with_name_budget(&entries, &config, 60).name_budget, Some(31));
+		assert_eq!(with_name_budget(&entries, &config, 30).name_budget, Some(NAME_MIN_WIDTH));
+	}
+	
+	#[test]
+	fn icon_widths_count_terminal_columns() {
+		let single = |text| text_width_as(text, AmbiguousWidth::Single);
+		let double = |text| text_width_as(text, AmbiguousWidth::Double);
+		// Emoji are wide, and so is a narrow symbol turned emoji by VS16
+		assert_eq!(single("📁"), 2);
+		assert_eq!(single("\u{2699}"), 1);
+		assert_eq!(single("\u{2699}\u{FE0F}"), 2);
+		// Nerd Font glyphs live in the private use area, which is ambiguous
+		assert_eq!(single("\u{F15B}"), 1);
+		assert_eq!(double("\u{F15B}"), 2);
+		assert_eq!(single("○"), 1);
+		assert_eq!(double("○"), 2);
+		// Wide emoji are wide however ambiguous characters are counted
+		assert_eq!(double("📁"), 2);
+		assert_eq!(single("\u{1b}[34m📁\u{1b}[0m"), 9);
+		assert_eq!(display_width("\u{1b}[34m📁\u{1b}[0m"), 2);
+	}
+	
+	#[test]
+	fn names_line_up_after_icons_of_mixed_widths() {
+		let mut config = Config::default();
+		config.custom_icons.insert("log".to_string(), "\u{F15B}".to_string());
+		config.custom_icons.insert("tar".to_string(), "\u{2699}\u{FE0F}".to_string());
+		let entries = collect_sample(&config).0;
+		let widths = ColumnWidths::compute(&entries, &config);
+		
+		let icons: Vec<usize> = entries.iter().map(|entry| display_width(&entry.icon)).collect();
+		assert!(icons.contains(&1) && icons.contains(&2), "icon widths {:?}", icons);
+		let starts: Vec<usize> = entries.iter()
+			.map(|entry| {
+				let line = strip_ansi(&entry.format_display(&config, &widths));
+				display_width(&line[..line.find(entry.name.as_str()).unwrap()])
+			})
+			.collect();
+		assert!(starts.iter().all(|&start| start == starts[0]), "names start at {:?}", starts);
+	}
+	
+	#[test]
+	fn owner_and_group_sorts_use_resolved_names() {
+		// UID 4242 has no name, so it shows and sorts as its number
+		let shared = MockFiles::new(&[
+			("zeta", false, 1, 60, 1000),
+			("alpha", false, 1, 60, 1001),
+			("beta", false, 1, 60, 1000),
+			("orphan", false, 1, 60, 4242),
+			("docs", true, 1, 60, 1001),
+		]);
+		let names = |config: &Config| -> Vec<String> {
+			collect_mock(&shared, config).0.into_iter().map(|entry| format!("{}:{}", entry.owner, entry.name)).collect()
+		};
+		
+		let config = Config { sort_key: SortKey::Owner, ..Config::default() };
+		assert_eq!(names(&config), ["bob:docs", "4242:orphan", "alice:beta", "alice:zeta", "bob:alpha"]);
+		let config = Config { sort_dirs_first: false, ..config };
+		assert_eq!(names(&config), ["4242:orphan", "alice:beta", "alice:zeta", "bob:alpha", "bob:docs"]);
+		let config = Config { reverse_sort: true, ..config };
+		assert_eq!(names(&config), ["bob:docs", "bob:alpha", "alice:zeta", "alice:beta", "4242:orphan"]);
+		
+		let mut config = Config { sort_dirs_first: false, ..Config::default() };
+		config.apply_args(&["--sort=group".to_string()]).unwrap();
+		let groups: Vec<String> = collect_mock(&shared, &config).0.into_iter().map(|entry| entry.group).collect();
+		assert_eq!(groups, ["4242", "alice", "alice", "bob", "bob"]);
+	}
+	
+	#[test]
+	fn time_format_renders_known_epochs() {
+		let utc = |secs, format| format_unix_time(secs, 0, format);
+		assert_eq!(utc(0, "%Y-%m-%d %H:%M:%S"), "1970-01-01 00:00:00");
+		assert_eq!(utc(0, "%a %b %e %y"), "Thu Jan  1 70");
+		assert_eq!(utc(-1, "%Y-%m-%d %H:%M:%S %a"), "1969-12-31 23:59:59 Wed");
+		assert_eq!(utc(1_700_000_000, "%a %d %b %Y %I:%M %p"), "Tue 14 Nov 2023 10:13 PM");
+		// Leap day, and the day of the year after it
+		assert_eq!(utc(951_782_400, "%Y-%m-%d %j"), "2000-02-29 060");
+		assert_eq!(utc(978_220_800, "%j"), "366");
+		// Twelve-hour clock at midnight and noon
+		assert_eq!(utc(0, "%I %p"), "12 AM");
+		assert_eq!(utc(43_200, "%I %p"), "12 PM");
+		// The offset moves the civil time, across a day boundary here
+		assert_eq!(format_unix_time(1_700_000_000, 2 * 3600, "%d %H:%M"), "15 00:13");
+		assert_eq!(utc(0, "100%% %Q"), "100% %Q");
+	}
+	
+	#[test]
+	fn time_format_reports_unsupported_specifiers_once() {
+		assert!(unsupported_time_specifiers("%Y-%m-%d %H:%M:%S %a %b %e %I %p %j %y %%").is_empty());
+		assert_eq!(unsupported_time_specifiers("%Z %F %Z %"), ["%Z", "%F", "trailing %"]);
+		
+		let mut config = Config::default();
+		config.set_option("time_format", "%H:%M");
+		assert_eq!(config.time_format.as_deref(), Some("%H:%M"));
+		config.set_option("time_format", "");
+		assert_eq!(config.time_format, None);
+	}
+	
+	#[test]
+	fn compact_times_keep_a_fixed_width() {
+		let cases = [
+			(0, "  0s"),
+			(59, " 59s"),
+			(60, "  1m"),
+			(SECS_PER_HOUR - 1, " 59m"),
+			(SECS_PER_HOUR, "  1h"),
+			(SECS_PER_DAY, "  1d"),
+			(SECS_PER_WEEK - 1, "  6d"),
+			(SECS_PER_WEEK, "  1w"),
+			(SECS_PER_YEAR - 1, " 52w"),
+			(SECS_PER_YEAR, "  1y"),
+			(250 * SECS_PER_YEAR, "250y"),
+			(u64::MAX, "999y"),
+		];
+		for (seconds, expected) in cases {
+			let compact = format_compact_seconds(seconds);
+			assert_eq!(compact, expected, "{} seconds", seconds);
+			assert_eq!(compact.len(), COMPACT_TIME_WIDTH);
+		}
+		
+		let config = Config { fuzzy_fixed_width: true, ..Config::default() };
+		assert_eq!(config.relative_time_style(true), RelativeTimeStyle::Compact);
+		assert_eq!(config.relative_time_style(false), RelativeTimeStyle::Timestamp);
+		let hour_ago = SystemTime::now() - Duration::from_secs(SECS_PER_HOUR + 30);
+		assert_eq!(format_duration_since(hour_ago, RelativeTimeStyle::Compact), Ok("  1h".to_string()));
+	}
+	
+	#[test]
+	fn fractional_times_show_tenths_below_ten_units() {
+		let cases = [
+			(3599, "59 minutes"),
+			(SECS_PER_HOUR, "1 hour"),
+			(5400, "1.5 hours"),
+			// Tenths are rounded down, so 1h59m is still 1.9
+			(7199, "1.9 hours"),
+			(SECS_PER_HOUR * 99 / 10, "9.9 hours"),
+			(SECS_PER_HOUR * 10, "10 hours"),
+			(SECS_PER_HOUR * 23, "23 hours"),
+			(SECS_PER_DAY * 3 / 2, "1.5 days"),
+			(SECS_PER_DAY * 6, "6 days"),
+			(SECS_PER_WEEK * 5 / 2, "2.5 weeks"),
+			(SECS_PER_MONTH, "1 month"),
+			(SECS_PER_YEAR * 3 / 2, "1 year"),
+		];
+		for (seconds, expected) in cases {
+			assert_eq!(format_fractional_seconds(seconds), expected, "{} seconds", seconds);
+		}
+		
+		let config = Config { fuzzy_fractional: true, ..Config::default() };
+		assert_eq!(config.relative_time_style(true), RelativeTimeStyle::Fractional);
+		let config = Config { fuzzy_fixed_width: true, ..config };
+		assert_eq!(config.relative_time_style(true), RelativeTimeStyle::Compact);
+	}
+	
+	#[test]
+	fn related_key_strips_sidecar_extensions() {
+		assert_eq!(related_key("main.rs"), ("main", "rs"));
+		assert_eq!(related_key("main.rs.bak"), ("main", "rs"));
+		assert_eq!(related_key("photo.jpg.thumb"), ("photo", "jpg"));
+		assert_eq!(related_key("README"), ("README", ""));
+		assert_eq!(related_key(".bashrc"), (".bashrc", ""));
+		assert_eq!(related_key(".eslintrc.json"), (".eslintrc", "json"));
+		assert_eq!(related_key("archive.tar.gz"), ("archive", "tar"));
+	}
+	
+	#[test]
+	fn group_related_keeps_sidecars_next_to_their_files() {
+		let files: Vec<(&str, bool, u64, u64, u32)> = [
+			"main.rs.bak", "photo.jpg.thumb", "lib.rs", "app.js.map", "main.rs", "photo.jpg", "app.js", "README",
+		].iter().map(|&name| (name, false, 1, 60, 1000)).collect();
+		let source = MockFiles::new(&files);
+		let names = |config: &Config| -> Vec<String> {
+			collect_mock(&source, config).0.into_iter().map(|entry| entry.name).collect()
+		};
+		
+		let config = Config { sort_key: SortKey::Extension, ..Config::default() };
+		assert_eq!(names(&config), ["README", "main.rs.bak", "photo.jpg", "app.js", "app.js.map", "lib.rs", "main.rs", "photo.jpg.thumb"]);
+		let config = Config { group_related: true, ..config };
+		assert_eq!(names(&config), ["README", "photo.jpg", "photo.jpg.thumb", "app.js", "app.js.map", "lib.rs", "main.rs", "main.rs.bak"]);
+	}
+	
+	#[test]
+	fn reverse_keeps_or_flips_the_directory_group() {
+		let source = MockFiles::new(&[
+			("delta", false, 1, 60, 1000),
+			("bravo", true, 1, 60, 1000),
+			("alpha", false, 1, 60, 1000),
+			("charlie", true, 1, 60, 1000),
+		]);
+		let names = |config: &Config| -> Vec<String> {
+			collect_mock(&source, config).0.into_iter().map(|entry| entry.name).collect()
+		};
+		
+		let config = Config::default();
+		assert!(config.reverse_keeps_dirs_first);
+		assert_eq!(names(&config), ["bravo", "charlie", "alpha", "delta"]);
+		let config = Config { reverse_sort: true, ..config };
+		assert_eq!(names(&config), ["charlie", "bravo", "delta", "alpha"]);
+		let config = Config { reverse_keeps_dirs_first: false, ..config };
+		assert_eq!(names(&config), ["delta", "alpha", "charlie", "bravo"]);
+		// Without reversing the setting changes nothing
+		let config = Config { reverse_sort: false, ..config };
+		assert_eq!(names(&config), ["bravo", "charlie", "alpha", "delta"]);
+		// Without dirs-first everything is reversed together
+		let config = Config { reverse_sort: true, sort_dirs_first: false, ..config };
+		assert_eq!(names(&config), ["delta", "charlie", "bravo", "alpha"]);
+	}
+	
+	#[test]
+	fn visible_chars_reveal_tabs_newlines_and_edge_spaces() {
+		let cases = [
+			("tab\there", "tab^Ihere"),
+			("line\nbreak", "line^Jbreak"),
+			("dos\r", "dos^M"),
+			("del\x7f", "del^?"),
+			("trailing  ", "trailing␣␣"),
+			(" leading", "␣leading"),
+			("   ", "␣␣␣"),
+			("evil\u{202E}txt", "evil<U+202E>txt"),
+			("two words", "two words"),
+		];
+		for (name, expected) in cases {
+			assert_eq!(visible_chars(name), expected, "{:?}", name);
+		}
+		assert!(has_invisible_chars("trailing "));
+		assert!(has_invisible_chars("zero\u{200B}width"));
+		assert!(!has_invisible_chars("two words"));
+	}
+	
+	#[test]
+	fn show_control_chars_rewrites_only_hazardous_names() {
+		let source = MockFiles::new(&[("bad\tname ", false, 1, 60, 1000), ("fine name", false, 1, 60, 1000)]);
+		let config = Config { show_control_chars: true, ..Config::default() };
+		let cells: Vec<String> = collect_mock(&source, &config).0.iter().map(|entry| strip_ansi(&entry.name_cell(&config))).collect();
+		assert_eq!(cells, ["bad^Iname␣", "fine name"]);
+		
+		let config = Config::default();
+		let cells: Vec<String> = collect_mock(&source, &config).0.iter().map(|entry| strip_ansi(&entry.name_cell(&config))).collect();
+		assert_eq!(cells, ["bad\tname ", "fine name"]);
+	}
+	
+	#[test]
+	fn future_times_say_how_far_ahead_they_are() {
+		let ahead = |seconds| SystemTime::now() + Duration::from_secs(seconds);
+		// A moment passes between taking the time and formatting it, so aim mid-unit
+		assert!(matches!(format_duration_since(ahead(2 * 3600 + 30), RelativeTimeStyle::Fuzzy), Err(TimeAnomaly::Future(7200..=7230))));
+		// Even a fraction of a second ahead counts as one second
+		let barely = SystemTime::now() + Duration::from_millis(500);
+		assert!(matches!(format_duration_since(barely, RelativeTimeStyle::Fuzzy), Err(TimeAnomaly::Future(1))));
+		
+		let config = Config::default();
+		assert_eq!(TimeAnomaly::Future(7215).text(&config), "in 2 hours");
+		assert_eq!(TimeAnomaly::Future(1).text(&config), "in 1 second");
+		assert_eq!(TimeAnomaly::Future(3 * SECS_PER_YEAR).text(&config), "in 3 years");
+		
+		let config = Config { fuzzy_fixed_width: true, ..Config::default() };
+		assert_eq!(TimeAnomaly::Future(7215).text(&config), "in 2h");
+		let config = Config { fuzzy_fractional: true, ..Config::default() };
+		assert_eq!(TimeAnomaly::Future(5400).text(&config), "in 1.5 hours");
+		
+		let mut config = Config::default();
+		config.set_option("future_time_text", "{} ahead");
+		assert_eq!(TimeAnomaly::Future(90).text(&config), "1 minute ahead");
+		config.set_option("future_time_text", "future");
+		assert_eq!(TimeAnomaly::Future(90).text(&config), "future");
+		assert_eq!(TimeAnomaly::Unknown.text(&config), "unknown");
+	}
+	
+	#[test]
+	fn uniform_columns_note_their_values() {
+		let source = MockFiles::new(&[
+			("one", false, 10, 7200, 1000),
+			("two", false, 20, 7200, 1000),
+			("three", false, 30, 7200, 1000),
+		]);
+		let config = Config { auto_hide_uniform_columns: true, show_size: true, ..Config::default() };
+		let entries = collect_mock(&source, &config).0;
+		let uniform = uniform_columns(&entries, &config);
+		let columns: Vec<&str> = uniform.iter().map(|(column, _)| column.as_str()).collect();
+		assert_eq!(columns, ["permissions", "owner", "group", "modified"]);
+		assert_eq!(uniform_note(&uniform).unwrap(), "Same for all entries: perms 644, owner alice, group alice, modified 2 hours");
+		
+		// A column blank for every entry is hidden without a note
+		let blank = [("owner".to_string(), String::new())];
+		assert_eq!(uniform_note(&blank), None);
+	}
+	
+	#[test]
+	fn name_colors_are_stable_and_readable() {
+		let readable = readable_cube_colors();
+		assert!(!readable.contains(&16) && !readable.contains(&231), "black and white are unreadable");
+		for name in ["root", "alice", "bob", "www-data", ""] {
+			let color = name_to_color(name);
+			assert_eq!(name_to_color(name), color);
+			assert!(readable.contains(&color), "{} got {}", name, color);
+		}
+		assert_ne!(name_to_color("alice"), name_to_color("bob"));
+	}
+	
+	#[test]
+	fn glob_matches_stars_classes_and_literals() {
+		let cases = [
+			("*.rs", "main.rs", true),
+			("*.rs", "main.rs.bak", false),
+			("*", "", true),
+			("", "", true),
+			("", "a", false),
+			("a*", "a", true),
+			("*a*b*", "xaxxbx", true),
+			("*a*b", "xaxxbx", false),
+			("?.txt", "a.txt", true),
+			("?.txt", ".txt", false),
+			("[abc]*", "bravo", true),
+			("[!abc]*", "bravo", false),
+			("[^abc]*", "delta", true),
+			("file[0-9]", "file7", true),
+			("file[0-9]", "filex", false),
+			("[", "[", true),
+			("[*", "[abc", true),
+			("a[", "ab", false),
+			("*.[ch]", "util.h", true),
+			("Makefile", "makefile", false),
+			("é*", "éclair", true),
+		];
+		for (pattern, name, expected) in cases {
+			assert_eq!(glob_match(pattern, name), expected, "{:?} against {:?}", pattern, name);
+		}
+	}
+	
+	#[test]
+	fn glob_backtracking_stays_fast() {
+		// Exponential in the number of stars for a recursive matcher
+		let name = "a".repeat(200);
+		let pattern = format!("{}b", "*a".repeat(20));
+		let started = Instant::now();
+		assert!(!glob_match(&pattern, &name));
+		assert!(glob_match(&format!("{}*", "*a".repeat(20)), &name));
+		assert!(started.elapsed() < Duration::from_secs(1));
+	}
+	
+	#[test]
+	fn paths_relative_to_the_base_itself_show_as_dot() {
+		let config = Config { paths_relative_to: Some(PathBuf::from("/mock")), ..Config::default() };
+		let mut entries = collect_sample(&config).0;
+		let names: Vec<String> = entries.iter().map(|entry| entry.display_name(&config)).collect();
+		assert_eq!(names, ["src", "archive.tar", "Build.log", "notes.txt"]);
+		
+		entries[0].path = PathBuf::from("/mock");
+		assert_eq!(entries[0].display_name(&config), ".");
+		assert_eq!(entries[0].shell_name(&config), PathBuf::from("."));
+		entries[0].path = PathBuf::from("/mock/src/lib");
+		assert_eq!(entries[0].display_name(&config), "src/lib");
+		entries[0].path = PathBuf::from("/elsewhere/file");
+		assert_eq!(entries[0].display_name(&config), "/elsewhere/file");
+	}
+}
@@ -0,0 +1,18 @@
+//! `--batch-file` edge cases that only show up in a whole run
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn empty_batch_with_select_chooses_nothing() {
+	let root = common::scratch_dir("batch-select");
+	let batch = root.join("batch");
+	fs::write(&batch, "# nothing to list\n\n").unwrap();
+	
+	let output = common::yal(&root).arg("--batch-file").arg(&batch).arg("--select").output().unwrap();
+	fs::remove_dir_all(&root).unwrap();
+	
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,35 @@
+//! Helpers shared by the integration tests
+
+// Each test crate compiles this module but uses only some of it
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A fresh, empty scratch directory for one test
+pub fn scratch_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("yal-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// A `listed` directory under `root` holding `count` empty files
+pub fn files_dir(root: &Path, count: usize) -> PathBuf {
+	let listed = root.join("listed");
+	fs::create_dir_all(&listed).unwrap();
+	for i in 0..count {
+		fs::write(listed.join(format!("file-{:05}", i)), "").unwrap();
+	}
+	listed
+}
+
+/// The yal binary, run with an empty home under `root` so the user's config stays out
+pub fn yal(root: &Path) -> Command {
+	let home = root.join("home");
+	fs::create_dir_all(&home).unwrap();
+	let mut command = Command::new(env!("CARGO_BIN_EXE_rusttest"));
+	command.env("HOME", &home).env("XDG_CONFIG_HOME", &home).env_remove("YAL_CONFIG");
+	command
+}
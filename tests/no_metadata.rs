@@ -0,0 +1,41 @@
+//! Timing of `--no-metadata` against a full listing; run with `cargo test -- --ignored --nocapture`
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+/// Entries in the timed directory
+const ENTRIES: usize = 20_000;
+
+/// Runs of each listing; the fastest one counts, to keep scheduling noise out
+const RUNS: usize = 5;
+
+/// Fastest of `RUNS` listings of `listed` with `args`
+fn fastest(root: &Path, listed: &Path, args: &[&str]) -> Duration {
+	(0..RUNS)
+		.map(|_| {
+			let started = Instant::now();
+			let status = common::yal(root).args(args).arg(listed).stdout(Stdio::null()).status().unwrap();
+			assert!(status.success());
+			started.elapsed()
+		})
+		.min()
+		.unwrap()
+}
+
+#[test]
+#[ignore = "timing comparison; run explicitly"]
+fn no_metadata_lists_faster_than_a_full_listing() {
+	let root = common::scratch_dir("no-metadata");
+	let listed = common::files_dir(&root, ENTRIES);
+	
+	let full = fastest(&root, &listed, &["--format=simple"]);
+	let names_only = fastest(&root, &listed, &["--format=simple", "--no-metadata"]);
+	println!("{} entries: full listing {:?}, --no-metadata {:?}", ENTRIES, full, names_only);
+	
+	fs::remove_dir_all(&root).unwrap();
+	assert!(names_only < full, "--no-metadata took {:?}, a full listing {:?}", names_only, full);
+}
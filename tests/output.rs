@@ -0,0 +1,23 @@
+//! `--output` writes the listing to a file and keeps headers on stderr
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn output_file_gets_the_listing_and_stderr_the_header() {
+	let root = common::scratch_dir("output");
+	let listed = common::files_dir(&root, 2);
+	let file = root.join("listing.txt");
+	
+	let output = common::yal(&root).args(["--format=simple", "--output"]).arg(&file).arg(&listed).output().unwrap();
+	let written = fs::read_to_string(&file).unwrap();
+	fs::remove_dir_all(&root).unwrap();
+	
+	assert!(output.status.success());
+	assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+	assert!(String::from_utf8_lossy(&output.stderr).contains(&listed.display().to_string()));
+	assert!(written.contains("file-00000") && written.contains("file-00001"), "{}", written);
+	assert!(!written.contains(&listed.display().to_string()), "{}", written);
+	assert!(!written.contains('\x1b'), "{}", written);
+}
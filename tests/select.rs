@@ -0,0 +1,21 @@
+//! `--select` argument checks, made before the terminal is touched
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn select_rejects_more_than_one_directory() {
+	let root = common::scratch_dir("select-two");
+	let first = root.join("first");
+	let second = root.join("second");
+	fs::create_dir_all(&first).unwrap();
+	fs::create_dir_all(&second).unwrap();
+	
+	let output = common::yal(&root).arg("--select").arg(&first).arg(&second).output().unwrap();
+	fs::remove_dir_all(&root).unwrap();
+	
+	assert_eq!(output.status.code(), Some(2));
+	assert!(output.stdout.is_empty());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("--select takes at most one directory"));
+}
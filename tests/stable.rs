@@ -0,0 +1,58 @@
+//! `--stable` output must not depend on the host's locale
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Names whose order differs between byte-wise and natural or accent-folding collation
+const NAMES: [&str; 6] = ["Zebra", "apple", "éclair", "eclair2", "file10", "file2"];
+
+/// Locales the listing is run under
+const LOCALES: [&str; 4] = ["C", "en_US.UTF-8", "de_DE.UTF-8", "ru_RU.UTF-8"];
+
+/// Output of yal listing `listed` with `LANG` set to `lang`, following the locale where allowed
+fn listing(root: &Path, listed: &Path, lang: &str, args: &[&str]) -> String {
+	let output = common::yal(root)
+		.args(args)
+		.arg(listed)
+		.env_remove("LC_ALL")
+		.env_remove("LC_MESSAGES")
+		.env("LANG", lang)
+		.env("YAL_TIME_LANGUAGE", "auto")
+		.env("YAL_COLLATION", "unicode")
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stable_output_is_identical_under_every_lang() {
+	let root = common::scratch_dir("stable");
+	let listed = root.join("listed");
+	fs::create_dir_all(&listed).unwrap();
+	for name in NAMES {
+		let file = fs::File::create(listed.join(name)).unwrap();
+		// A fixed, long-past time keeps the fuzzy age the same between runs
+		file.set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000)).unwrap();
+	}
+	
+	let stable: Vec<String> = LOCALES.iter().map(|lang| listing(&root, &listed, lang, &["--stable"])).collect();
+	let followed: Vec<String> = LOCALES.iter().map(|lang| listing(&root, &listed, lang, &[])).collect();
+	fs::remove_dir_all(&root).unwrap();
+	
+	for (lang, output) in LOCALES.iter().zip(&stable) {
+		assert_eq!(output, &stable[0], "LANG={}", lang);
+	}
+	assert!(stable[0].contains("years"), "{}", stable[0]);
+	let positions: Vec<usize> = ["Zebra", "apple", "eclair2", "file10", "file2", "éclair"].iter()
+		.map(|name| stable[0].find(name).unwrap())
+		.collect();
+	assert!(positions.is_sorted(), "byte-wise order expected:\n{}", stable[0]);
+	
+	// Without --stable the same settings follow LANG, so the check above means something
+	assert!(followed[2].contains("Jahre"), "{}", followed[2]);
+	assert_ne!(followed[0], followed[2]);
+}
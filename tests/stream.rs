@@ -0,0 +1,38 @@
+//! End-to-end checks of `--stream`, run against the built binary
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+
+/// Entries in the test directory; their JSON lines are far more than a pipe buffer holds
+const ENTRIES: usize = 5000;
+
+#[test]
+fn jsonl_stream_writes_entries_before_exiting() {
+	let root = common::scratch_dir("stream");
+	let listed = common::files_dir(&root, ENTRIES);
+	
+	let mut child = common::yal(&root)
+		.args(["--format=jsonl", "--stream"])
+		.arg(&listed)
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+	let mut stdout = BufReader::new(child.stdout.take().unwrap());
+	
+	let mut first = String::new();
+	stdout.read_line(&mut first).unwrap();
+	// The rest of the output doesn't fit in the pipe, so yal can't have finished yet
+	let running = child.try_wait().unwrap().is_none();
+	
+	assert!(first.starts_with("{\"name\": \"file-"), "first line: {}", first);
+	assert!(first.ends_with("}\n"), "first line: {}", first);
+	assert!(running, "yal exited before its first line was read");
+	
+	let rest = stdout.lines().count();
+	assert!(child.wait().unwrap().success());
+	assert_eq!(rest + 1, ENTRIES);
+	fs::remove_dir_all(&root).unwrap();
+}